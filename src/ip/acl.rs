@@ -0,0 +1,488 @@
+use std::ops::RangeInclusive;
+
+use super::address::Address;
+use super::header::{Header, HeaderV4, HeaderV6};
+use super::{Network, NetworkError, Protocol, Version};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Permit,
+    Deny,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AddressMatch {
+    Any,
+    Network(Network),
+    // Bits set in the mask must equal the same bits of the address, the
+    // rest is ignored. Masks don't have to be contiguous.
+    Masked { version: Version, bits: u128, mask: u128 },
+}
+
+// Where the packet sits in a fragmented datagram
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FragmentMatch {
+    Any,
+    // No more-fragments flag and offset 0
+    Unfragmented,
+    // Any fragment, the first one included
+    Fragment,
+    // More-fragments flag and offset 0
+    Initial,
+    // Offset above 0
+    NonInitial,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub action: Action,
+    pub remark: String,
+    pub source: AddressMatch,
+    pub destination: AddressMatch,
+    pub protocol: Option<Protocol>,
+    pub dscp: RangeInclusive<u8>,
+    pub ttl: RangeInclusive<u8>,
+    pub fragment: FragmentMatch,
+    pub dont_fragment: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Verdict {
+    pub action: Action,
+    // Index of the first matching rule, none when the default applied
+    pub rule: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Finding {
+    // Every packet of the rule is caught earlier by a rule with the other
+    // action, so it never applies
+    Shadowed { rule: usize, by: usize },
+    // Removing the rule changes nothing, `by` is the rule that takes over
+    // or none for the default action
+    Redundant { rule: usize, by: Option<usize> },
+}
+
+// Ordered rules, first match wins, the default action applies when no rule matches
+#[derive(Debug, Clone, PartialEq)]
+pub struct Acl {
+    rules: Vec<Rule>,
+    default: Action,
+}
+
+// The fields of a header the rules look at
+struct Packet {
+    version: Version,
+    source: u128,
+    destination: u128,
+    protocol: Protocol,
+    dscp: u8,
+    ttl: u8,
+    // One of the FragmentMatch::states bits
+    fragment: u8,
+    dont_fragment: bool,
+}
+
+impl AddressMatch {
+    pub fn masked(address: &dyn Address, mask: &dyn Address) -> Result<AddressMatch, NetworkError> {
+        let (address, mask) = (address.as_some(), mask.as_some());
+        if address.version() != mask.version() {
+            return Err(NetworkError::VersionMismatch);
+        }
+        Ok(AddressMatch::Masked { version: address.version(), bits: address.to_bits() & mask.to_bits(), mask: mask.to_bits() })
+    }
+
+    // Version, significant bits and mask, none for any address
+    fn parts(&self) -> Option<(Version, u128, u128)> {
+        match self {
+            AddressMatch::Any => None,
+            AddressMatch::Network(network) => Some((network.version(), network.first_bits(), network.netmask().to_bits())),
+            AddressMatch::Masked { version, bits, mask } => Some((*version, bits & mask, *mask)),
+        }
+    }
+
+    fn matches(&self, version: Version, address: u128) -> bool {
+        match self.parts() {
+            None => true,
+            Some((own_version, bits, mask)) => own_version == version && address & mask == bits,
+        }
+    }
+
+    fn is_subset_of(&self, other: &AddressMatch) -> bool {
+        match (self.parts(), other.parts()) {
+            (_, None) => true,
+            (None, Some(_)) => false,
+            // Other fixes no bit this one leaves free, and they agree on those
+            (Some((version, bits, mask)), Some((other_version, other_bits, other_mask))) => {
+                version == other_version && other_mask & !mask == 0 && bits & other_mask == other_bits
+            }
+        }
+    }
+
+    fn intersects(&self, other: &AddressMatch) -> bool {
+        match (self.parts(), other.parts()) {
+            (Some((version, bits, mask)), Some((other_version, other_bits, other_mask))) => {
+                version == other_version && (bits ^ other_bits) & mask & other_mask == 0
+            }
+            _ => true,
+        }
+    }
+}
+
+impl FragmentMatch {
+    // Unfragmented, initial and non-initial as bits
+    fn states(&self) -> u8 {
+        match self {
+            FragmentMatch::Any => 0b111,
+            FragmentMatch::Unfragmented => 0b001,
+            FragmentMatch::Fragment => 0b110,
+            FragmentMatch::Initial => 0b010,
+            FragmentMatch::NonInitial => 0b100,
+        }
+    }
+}
+
+fn range_within(range: &RangeInclusive<u8>, other: &RangeInclusive<u8>) -> bool {
+    range.start() >= other.start() && range.end() <= other.end()
+}
+
+fn ranges_meet(range: &RangeInclusive<u8>, other: &RangeInclusive<u8>) -> bool {
+    range.start() <= other.end() && other.start() <= range.end()
+}
+
+fn option_within<T: PartialEq>(value: &Option<T>, other: &Option<T>) -> bool {
+    other.is_none() || value == other
+}
+
+fn options_meet<T: PartialEq>(value: &Option<T>, other: &Option<T>) -> bool {
+    value.is_none() || other.is_none() || value == other
+}
+
+impl Rule {
+    // Matches everything until narrowed down
+    pub fn new(action: Action) -> Rule {
+        Rule {
+            action,
+            remark: String::new(),
+            source: AddressMatch::Any,
+            destination: AddressMatch::Any,
+            protocol: None,
+            dscp: 0..=63,
+            ttl: 0..=255,
+            fragment: FragmentMatch::Any,
+            dont_fragment: None,
+        }
+    }
+
+    pub fn permit() -> Rule {
+        Rule::new(Action::Permit)
+    }
+
+    pub fn deny() -> Rule {
+        Rule::new(Action::Deny)
+    }
+
+    pub fn remark(mut self, remark: &str) -> Rule {
+        self.remark = remark.to_string();
+        self
+    }
+
+    pub fn source(mut self, network: Network) -> Rule {
+        self.source = AddressMatch::Network(network);
+        self
+    }
+
+    pub fn source_match(mut self, source: AddressMatch) -> Rule {
+        self.source = source;
+        self
+    }
+
+    pub fn destination(mut self, network: Network) -> Rule {
+        self.destination = AddressMatch::Network(network);
+        self
+    }
+
+    pub fn destination_match(mut self, destination: AddressMatch) -> Rule {
+        self.destination = destination;
+        self
+    }
+
+    // A known transport or any other protocol number, e.g. 1 for ICMP or 47 for GRE
+    pub fn protocol<P: Into<Protocol>>(mut self, protocol: P) -> Rule {
+        self.protocol = Some(protocol.into());
+        self
+    }
+
+    pub fn dscp(mut self, dscp: RangeInclusive<u8>) -> Rule {
+        self.dscp = dscp;
+        self
+    }
+
+    pub fn ttl(mut self, ttl: RangeInclusive<u8>) -> Rule {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn fragment(mut self, fragment: FragmentMatch) -> Rule {
+        self.fragment = fragment;
+        self
+    }
+
+    pub fn dont_fragment(mut self, dont_fragment: bool) -> Rule {
+        self.dont_fragment = Some(dont_fragment);
+        self
+    }
+
+    fn matches(&self, packet: &Packet) -> bool {
+        self.source.matches(packet.version, packet.source)
+            && self.destination.matches(packet.version, packet.destination)
+            && self.protocol.is_none_or(|protocol| protocol == packet.protocol)
+            && self.dscp.contains(&packet.dscp)
+            && self.ttl.contains(&packet.ttl)
+            && self.fragment.states() & packet.fragment != 0
+            && self.dont_fragment.is_none_or(|dont_fragment| dont_fragment == packet.dont_fragment)
+    }
+
+    // Every packet this rule matches is matched by the other one
+    pub fn is_covered_by(&self, other: &Rule) -> bool {
+        self.source.is_subset_of(&other.source)
+            && self.destination.is_subset_of(&other.destination)
+            && option_within(&self.protocol, &other.protocol)
+            && range_within(&self.dscp, &other.dscp)
+            && range_within(&self.ttl, &other.ttl)
+            && self.fragment.states() & !other.fragment.states() == 0
+            && option_within(&self.dont_fragment, &other.dont_fragment)
+    }
+
+    // Some packet matches both rules
+    pub fn overlaps(&self, other: &Rule) -> bool {
+        self.source.intersects(&other.source)
+            && self.destination.intersects(&other.destination)
+            && options_meet(&self.protocol, &other.protocol)
+            && ranges_meet(&self.dscp, &other.dscp)
+            && ranges_meet(&self.ttl, &other.ttl)
+            && self.fragment.states() & other.fragment.states() != 0
+            && options_meet(&self.dont_fragment, &other.dont_fragment)
+    }
+}
+
+impl Packet {
+    fn from_v4(header: &HeaderV4) -> Packet {
+        let fragment = match (header.has_fragments, header.fragment_offset) {
+            (false, 0) => 0b001,
+            (true, 0) => 0b010,
+            _ => 0b100,
+        };
+        Packet {
+            version: Version::IpV4,
+            source: header.src_ip.as_some().to_bits(),
+            destination: header.dst_ip.as_some().to_bits(),
+            protocol: header.protocol,
+            dscp: header.dscp,
+            ttl: header.ttl,
+            fragment,
+            dont_fragment: header.dont_fragment,
+        }
+    }
+
+    // Fragmentation lives in an extension header the fixed header doesn't
+    // carry, so IPv6 packets count as unfragmented. Routers never fragment
+    // them, which is what the don't fragment flag says.
+    fn from_v6(header: &HeaderV6) -> Packet {
+        Packet {
+            version: Version::IpV6,
+            source: header.source_address.as_some().to_bits(),
+            destination: header.destination_address.as_some().to_bits(),
+            protocol: header.next_header,
+            dscp: header.dscp,
+            ttl: header.hop_limit,
+            fragment: 0b001,
+            dont_fragment: true,
+        }
+    }
+}
+
+impl Acl {
+    pub fn new(default: Action) -> Acl {
+        Acl { rules: Vec::new(), default }
+    }
+
+    pub fn rule(mut self, rule: Rule) -> Acl {
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn push(&mut self, rule: Rule) {
+        self.rules.push(rule);
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    pub fn default_action(&self) -> Action {
+        self.default
+    }
+
+    pub fn evaluate(&self, header: &Header) -> Verdict {
+        match header {
+            Header::V4(header) => self.evaluate_v4(header),
+            Header::V6(header) => self.evaluate_v6(header),
+        }
+    }
+
+    pub fn evaluate_v4(&self, header: &HeaderV4) -> Verdict {
+        self.verdict(&Packet::from_v4(header))
+    }
+
+    pub fn evaluate_v6(&self, header: &HeaderV6) -> Verdict {
+        self.verdict(&Packet::from_v6(header))
+    }
+
+    fn verdict(&self, packet: &Packet) -> Verdict {
+        match self.rules.iter().position(|rule| rule.matches(packet)) {
+            Some(index) => Verdict { action: self.rules[index].action, rule: Some(index) },
+            None => Verdict { action: self.default, rule: None },
+        }
+    }
+
+    // Pairwise checks: a rule hidden by one earlier rule, or one that a
+    // single later rule or the default would stand in for. Rules hidden only
+    // by several earlier rules together aren't found.
+    pub fn analyse(&self) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        for (index, rule) in self.rules.iter().enumerate() {
+            if let Some(earlier) = self.rules[..index].iter().position(|earlier| rule.is_covered_by(earlier)) {
+                findings.push(match self.rules[earlier].action == rule.action {
+                    true => Finding::Redundant { rule: index, by: Some(earlier) },
+                    false => Finding::Shadowed { rule: index, by: earlier },
+                });
+                continue;
+            }
+            // Packets falling through would reach the same action anyway, as
+            // long as no rule in between with the other action catches them
+            let mut by = None;
+            let mut conflict = false;
+            for (later, other) in self.rules.iter().enumerate().skip(index + 1) {
+                if other.action == rule.action && rule.is_covered_by(other) {
+                    by = Some(Some(later));
+                    break;
+                }
+                if other.action != rule.action && rule.overlaps(other) {
+                    conflict = true;
+                    break;
+                }
+            }
+            if by.is_none() && !conflict && self.default == rule.action {
+                by = Some(None);
+            }
+            if let Some(by) = by {
+                findings.push(Finding::Redundant { rule: index, by });
+            }
+        }
+        findings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::address::AddressV4;
+    use super::super::TransportProtocolsNumbers;
+    use super::super::test_helpers::network;
+
+    fn packet<P: Into<Protocol>>(src: &str, dst: &str, protocol: P) -> HeaderV4 {
+        let mut header = HeaderV4::empty();
+        header.src_ip = AddressV4::from_string(src).unwrap();
+        header.dst_ip = AddressV4::from_string(dst).unwrap();
+        header.protocol = protocol.into();
+        header.ttl = 64;
+        header
+    }
+
+    fn acl() -> Acl {
+        Acl::new(Action::Deny)
+            .rule(Rule::deny().remark("no fragments").fragment(FragmentMatch::NonInitial))
+            .rule(Rule::deny().remark("expiring").ttl(0..=1))
+            .rule(Rule::permit().remark("voice").dscp(46..=46).protocol(TransportProtocolsNumbers::Udp))
+            .rule(Rule::permit().remark("office to web").source(network("10.1.0.0/16")).destination(network("192.0.2.0/24")).protocol(TransportProtocolsNumbers::Tcp))
+            .rule(Rule::deny().remark("odd hosts").source_match(AddressMatch::masked(&AddressV4::from_string("0.0.0.1").unwrap(), &AddressV4::from_string("0.0.0.1").unwrap()).unwrap()))
+            .rule(Rule::permit().source(network("10.0.0.0/8")))
+    }
+
+    #[test]
+    fn acl_evaluate() {
+        let acl = acl();
+        let header = packet("10.1.2.3", "192.0.2.10", TransportProtocolsNumbers::Tcp);
+        assert_eq!(acl.evaluate_v4(&header), Verdict { action: Action::Permit, rule: Some(3) });
+        let mut fragment = packet("10.1.2.3", "192.0.2.10", TransportProtocolsNumbers::Tcp);
+        fragment.fragment_offset = 185;
+        assert_eq!(acl.evaluate_v4(&fragment).rule, Some(0));
+        fragment.fragment_offset = 0;
+        fragment.ttl = 1;
+        assert_eq!(acl.evaluate_v4(&fragment), Verdict { action: Action::Deny, rule: Some(1) });
+        let mut voice = packet("172.16.0.1", "198.51.100.1", TransportProtocolsNumbers::Udp);
+        voice.dscp = 46;
+        assert_eq!(acl.evaluate(&Header::V4(voice)).rule, Some(2));
+        assert_eq!(acl.evaluate_v4(&packet("10.2.0.5", "198.51.100.1", TransportProtocolsNumbers::Udp)).rule, Some(4));
+        assert_eq!(acl.evaluate_v4(&packet("10.2.0.4", "198.51.100.1", TransportProtocolsNumbers::Udp)).rule, Some(5));
+        assert_eq!(acl.evaluate_v4(&packet("172.16.0.4", "198.51.100.1", TransportProtocolsNumbers::Sctp)), Verdict { action: Action::Deny, rule: None });
+        let mut v6 = HeaderV6::empty();
+        v6.hop_limit = 1;
+        assert_eq!(acl.evaluate_v6(&v6).rule, Some(1));
+        v6.hop_limit = 64;
+        assert_eq!(acl.evaluate_v6(&v6).rule, None);
+    }
+
+    #[test]
+    fn acl_other_protocols() {
+        let acl = Acl::new(Action::Permit)
+            .rule(Rule::deny().remark("no ping").protocol(1))
+            .rule(Rule::deny().remark("no tunnels").source(network("10.0.0.0/8")).protocol(47))
+            .rule(Rule::permit().protocol(6));
+        assert_eq!(acl.evaluate_v4(&packet("10.1.2.3", "192.0.2.10", 1)).rule, Some(0));
+        assert_eq!(acl.evaluate_v4(&packet("10.1.2.3", "192.0.2.10", 47)).rule, Some(1));
+        assert_eq!(acl.evaluate_v4(&packet("172.16.0.1", "192.0.2.10", 47)).rule, None);
+        // A number with a variant is the same protocol as the variant
+        assert_eq!(acl.evaluate_v4(&packet("10.1.2.3", "192.0.2.10", 6)).rule, Some(2));
+        assert_eq!(acl.evaluate_v4(&packet("10.1.2.3", "192.0.2.10", TransportProtocolsNumbers::Tcp)).rule, Some(2));
+        assert_eq!(acl.evaluate_v4(&packet("10.1.2.3", "192.0.2.10", TransportProtocolsNumbers::Udp)).rule, None);
+        assert_eq!(acl.rules()[2].protocol, Some(Protocol::Known(TransportProtocolsNumbers::Tcp)));
+        let covered = Rule::deny().source(network("10.1.0.0/16")).protocol(47);
+        assert!(covered.is_covered_by(&acl.rules()[1]) && !covered.overlaps(&acl.rules()[0]));
+    }
+
+    #[test]
+    fn acl_shadowing_and_redundancy() {
+        let acl = Acl::new(Action::Deny)
+            .rule(Rule::permit().source(network("10.0.0.0/8")))
+            .rule(Rule::deny().source(network("10.1.0.0/16")).protocol(TransportProtocolsNumbers::Tcp))
+            .rule(Rule::permit().source(network("10.2.0.0/16")).ttl(10..=20))
+            .rule(Rule::permit().source(network("192.168.1.0/24")))
+            .rule(Rule::deny().source(network("192.168.0.0/16")).fragment(FragmentMatch::Fragment))
+            .rule(Rule::permit().source(network("192.168.0.0/16")))
+            .rule(Rule::deny().destination(network("198.51.100.0/24")));
+        assert_eq!(acl.analyse(), vec![
+            Finding::Shadowed { rule: 1, by: 0 },
+            Finding::Redundant { rule: 2, by: Some(0) },
+            Finding::Redundant { rule: 6, by: None },
+        ]);
+        // Without the deny in between, the /24 permit is covered by the /16 one
+        let acl = Acl::new(Action::Deny)
+            .rule(Rule::permit().source(network("192.168.1.0/24")))
+            .rule(Rule::permit().source(network("192.168.0.0/16")));
+        assert_eq!(acl.analyse(), vec![Finding::Redundant { rule: 0, by: Some(1) }]);
+    }
+
+    #[test]
+    fn acl_masks() {
+        let even = AddressMatch::masked(&AddressV4::from_string("0.0.0.0").unwrap(), &AddressV4::from_string("0.0.0.1").unwrap()).unwrap();
+        let even_in_ten = AddressMatch::masked(&AddressV4::from_string("10.0.0.0").unwrap(), &AddressV4::from_string("255.0.0.1").unwrap()).unwrap();
+        let ten = AddressMatch::Network(network("10.0.0.0/8"));
+        assert!(even_in_ten.is_subset_of(&even) && even_in_ten.is_subset_of(&ten));
+        assert!(!even.is_subset_of(&ten) && even.intersects(&ten));
+        assert!(!AddressMatch::Network(network("11.0.0.0/8")).intersects(&even_in_ten));
+        assert!(!ten.intersects(&AddressMatch::Network(network("2001:0db8:0000:0000:0000:0000:0000:0000/32"))));
+        assert!(AddressMatch::masked(&AddressV4::from_string("10.0.0.0").unwrap(), &super::super::address::AddressV6::from_string("::1").unwrap()).is_err());
+    }
+}
@@ -1,28 +1,121 @@
+use std::fmt;
 use std::num::ParseIntError;
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum SomeAddress {
     V4(AddressV4),
     V6(AddressV6),
 }
 
-pub trait Address {
+// Send + Sync so networks, which box their address, can be shared between threads
+pub trait Address: Send + Sync {
     fn to_string(&self) -> String;
     fn next(&self) -> Box<dyn Address>;
     fn to_bitstring(&self) -> String;
     fn apply_bitmask(&self, bitmask: &super::bitmask::Bitmask) -> Box<dyn Address>;
+    fn as_some(&self) -> SomeAddress;
 }
 
-#[derive(Debug, Clone, Copy)]
+// Common integer view of AddressV4 and AddressV6, used by the generic
+// iterators and containers. Bits are always stored in the low end of u128.
+pub trait FixedWidth: Address + Copy + Ord {
+    const BITS: u8;
+    const VERSION: super::Version;
+    fn from_bits(bits: u128) -> Self;
+    fn to_bits(&self) -> u128;
+    fn parse(ip_address: &str) -> Result<Self, AddressError>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressError {
+    // Wrong number of groups, misplaced separators or non-digits
+    WrongFormat(String),
+    // A group that doesn't fit into its octet or hextet
+    WrongNumber(ParseIntError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct AddressV4 {
     bits: u32
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct AddressV6 {
     bits: u128
 }
 
+impl SomeAddress {
+    pub fn from_bits(version: super::Version, bits: u128) -> SomeAddress {
+        match version {
+            super::Version::IpV4 => SomeAddress::V4(AddressV4::from_bits(bits)),
+            super::Version::IpV6 => SomeAddress::V6(AddressV6::from_bits(bits)),
+        }
+    }
+
+    pub fn version(&self) -> super::Version {
+        match self {
+            SomeAddress::V4(_) => super::Version::IpV4,
+            SomeAddress::V6(_) => super::Version::IpV6,
+        }
+    }
+
+    pub fn to_bits(&self) -> u128 {
+        match self {
+            SomeAddress::V4(address) => address.to_bits(),
+            SomeAddress::V6(address) => address.to_bits(),
+        }
+    }
+}
+
+impl Address for SomeAddress {
+    fn to_string(&self) -> String {
+        match self {
+            SomeAddress::V4(address) => address.to_string(),
+            SomeAddress::V6(address) => address.to_string(),
+        }
+    }
+
+    fn next(&self) -> Box<dyn Address> {
+        match self {
+            SomeAddress::V4(address) => address.next(),
+            SomeAddress::V6(address) => address.next(),
+        }
+    }
+
+    fn to_bitstring(&self) -> String {
+        match self {
+            SomeAddress::V4(address) => address.to_bitstring(),
+            SomeAddress::V6(address) => address.to_bitstring(),
+        }
+    }
+
+    fn apply_bitmask(&self, bitmask: &super::bitmask::Bitmask) -> Box<dyn Address> {
+        match self {
+            SomeAddress::V4(address) => address.apply_bitmask(bitmask),
+            SomeAddress::V6(address) => address.apply_bitmask(bitmask),
+        }
+    }
+
+    fn as_some(&self) -> SomeAddress {
+        *self
+    }
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressError::WrongFormat(reason) => write!(f, "{}", reason),
+            AddressError::WrongNumber(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<ParseIntError> for AddressError {
+    fn from(error: ParseIntError) -> AddressError {
+        AddressError::WrongNumber(error)
+    }
+}
+
 impl AddressV4 {
     pub fn from_bytes(bytes: [u8; 4]) -> AddressV4 {
         AddressV4{bits: u32::from_be_bytes(bytes)}
@@ -41,12 +134,11 @@ impl AddressV4 {
     }
 
     pub fn as_bytes(&self) -> [u8; 4] {
-        let bytes = self.bits.to_be_bytes();
-        return bytes;
+        self.bits.to_be_bytes()
     }
 
     pub fn as_u32(&self) -> u32 {
-        return self.bits;
+        self.bits
     }
 
     pub fn set_u32(&mut self, new_value: u32) {
@@ -75,6 +167,30 @@ impl Address for AddressV4 {
             super::bitmask::Bitmask::V6(_) => panic!("Can't apply V6 bitmask to V4 address"),
         }
     }
+
+    fn as_some(&self) -> SomeAddress {
+        SomeAddress::V4(*self)
+    }
+}
+
+impl FixedWidth for AddressV4 {
+    const BITS: u8 = 32;
+    const VERSION: super::Version = super::Version::IpV4;
+
+    fn from_bits(bits: u128) -> AddressV4 {
+        AddressV4::from_u32(bits as u32)
+    }
+
+    fn to_bits(&self) -> u128 {
+        self.bits.into()
+    }
+
+    fn parse(ip_address: &str) -> Result<AddressV4, AddressError> {
+        if ip_address.split('.').count() != 4 {
+            return Err(AddressError::WrongFormat(format!("'{}' doesn't have 4 octets", ip_address)));
+        }
+        Ok(AddressV4::from_string(ip_address)?)
+    }
 }
 
 impl AddressV6 {
@@ -82,27 +198,51 @@ impl AddressV6 {
         AddressV6{bits: u128::from_be_bytes(bytes)}
     }
     
-    // TODO: Add support of :: and 
-    pub fn from_string(ip_address: &str) -> Result<AddressV6, ParseIntError> { // Only full format
-        let splitted: Vec<&str> = ip_address.split(':').collect();
-        let mut bytes = [0_u8; 16];
-        for i in (0..16).step_by(2) {
-            bytes[i] = u8::from_str_radix(&splitted[i / 2][0..2], 16)?;
-            bytes[i + 1] = u8::from_str_radix(&splitted[i / 2][2..4], 16)?;
+    // Full "2001:0db8:0000:0000:0000:0000:0000:0001" and compressed
+    // "2001:db8::1" forms, "::" standing for as many zero groups as needed
+    pub fn from_string(ip_address: &str) -> Result<AddressV6, AddressError> {
+        let (head, tail) = match ip_address.split_once("::") {
+            Some((head, tail)) => (head, Some(tail)),
+            None => (ip_address, None),
+        };
+        let head = AddressV6::parse_groups(head)?;
+        let groups = match tail {
+            None if head.len() == 8 => head,
+            Some(tail) => {
+                let tail = AddressV6::parse_groups(tail)?;
+                if head.len() + tail.len() > 7 {
+                    return Err(AddressError::WrongFormat(format!("'{}' has too many groups around '::'", ip_address)));
+                }
+                let zeros = vec![0; 8 - head.len() - tail.len()];
+                [head, zeros, tail].concat()
+            }
+            None => return Err(AddressError::WrongFormat(format!("'{}' doesn't have 8 groups", ip_address))),
+        };
+        Ok(AddressV6::from_u128(groups.iter().fold(0, |bits, group| (bits << 16) | u128::from(*group))))
+    }
+
+    fn parse_groups(groups: &str) -> Result<Vec<u16>, AddressError> {
+        if groups.is_empty() {
+            return Ok(Vec::new());
         }
-        Ok(AddressV6::from_bytes(bytes))
+        groups.split(':').map(|group| {
+            if group.is_empty() || group.len() > 4 || !group.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(AddressError::WrongFormat(format!("'{}' isn't a group of 1 to 4 hex digits", group)));
+            }
+            Ok(u16::from_str_radix(group, 16)?)
+        }).collect()
     }
+
     pub fn from_u128(ip_address: u128) -> AddressV6 {
         AddressV6 { bits: ip_address }
     }
 
     pub fn as_bytes(&self) -> [u8; 16] {
-        let bytes = self.bits.to_be_bytes();
-        return bytes;
+        self.bits.to_be_bytes()
     }
 
     pub fn as_u128(&self) -> u128 {
-        return self.bits;
+        self.bits
     }
 
     pub fn set_u128(& mut self, new_value: u128) {
@@ -114,14 +254,14 @@ impl Address for AddressV6 {
     // TODO: Refactor
     fn to_string(&self) -> String {
         let bytes = self.as_bytes();
-        format!("{:0<2x}", bytes[0]) + &format!("{:0<2x}", bytes[1]) + ":" +
-        &format!("{:0<2x}", bytes[2]) + &format!("{:0<2x}", bytes[3]) + ":" +
-        &format!("{:0<2x}", bytes[4]) + &format!("{:0<2x}", bytes[5]) + ":" +
-        &format!("{:0<2x}", bytes[6]) + &format!("{:0<2x}", bytes[7]) + ":" +
-        &format!("{:0<2x}", bytes[8]) + &format!("{:0<2x}", bytes[9]) + ":" +
-        &format!("{:0<2x}", bytes[10]) + &format!("{:0<2x}", bytes[11]) + ":" +
-        &format!("{:0<2x}", bytes[12]) + &format!("{:0<2x}", bytes[13]) + ":" +
-        &format!("{:0<2x}", bytes[14]) + &format!("{:0<2x}", bytes[15])
+        format!("{:0>2x}", bytes[0]) + &format!("{:0>2x}", bytes[1]) + ":" +
+        &format!("{:0>2x}", bytes[2]) + &format!("{:0>2x}", bytes[3]) + ":" +
+        &format!("{:0>2x}", bytes[4]) + &format!("{:0>2x}", bytes[5]) + ":" +
+        &format!("{:0>2x}", bytes[6]) + &format!("{:0>2x}", bytes[7]) + ":" +
+        &format!("{:0>2x}", bytes[8]) + &format!("{:0>2x}", bytes[9]) + ":" +
+        &format!("{:0>2x}", bytes[10]) + &format!("{:0>2x}", bytes[11]) + ":" +
+        &format!("{:0>2x}", bytes[12]) + &format!("{:0>2x}", bytes[13]) + ":" +
+        &format!("{:0>2x}", bytes[14]) + &format!("{:0>2x}", bytes[15])
     }
 
     // TODO: Refactor
@@ -148,6 +288,27 @@ impl Address for AddressV6 {
             super::bitmask::Bitmask::V6(mask) => Box::new(AddressV6::from_u128(self.as_u128() & mask.get())),
         }
     }
+
+    fn as_some(&self) -> SomeAddress {
+        SomeAddress::V6(*self)
+    }
+}
+
+impl FixedWidth for AddressV6 {
+    const BITS: u8 = 128;
+    const VERSION: super::Version = super::Version::IpV6;
+
+    fn from_bits(bits: u128) -> AddressV6 {
+        AddressV6::from_u128(bits)
+    }
+
+    fn to_bits(&self) -> u128 {
+        self.bits
+    }
+
+    fn parse(ip_address: &str) -> Result<AddressV6, AddressError> {
+        AddressV6::from_string(ip_address)
+    }
 }
 
 
@@ -213,10 +374,38 @@ mod tests {
         assert_eq!(address.to_bitstring(), "1111101010111100:0001001000110100:1011111011101111:0100010101100100:0000000000000000:1110111011111101:0001000100010010:0100000100100011");
     }
 
+    #[test]
+    fn address_v6_to_string_pads_bytes() {
+        let address = AddressV6::from_u128(0x20010db8000000000000000000000001);
+        assert_eq!(address.to_string(), "2001:0db8:0000:0000:0000:0000:0000:0001");
+        assert_eq!(AddressV6::from_string(&address.to_string()).unwrap().bits, address.bits);
+    }
+
+    #[test]
+    fn address_v6_compressed_from_string() {
+        assert_eq!(AddressV6::from_string("2001:db8::1").unwrap().bits, 0x20010db8000000000000000000000001);
+        assert_eq!(AddressV6::from_string("::").unwrap().bits, 0);
+        assert_eq!(AddressV6::from_string("::ffff:0:0").unwrap().bits, 0xffff00000000);
+        assert_eq!(AddressV6::from_string("fe80::").unwrap().bits, 0xfe80 << 112);
+        assert!(AddressV6::from_string("1::2::3").is_err());
+        assert!(AddressV6::from_string("1:2:3:4:5:6:7::8").is_err());
+        assert!(AddressV6::from_string("1:2:3").is_err());
+        assert!(AddressV6::from_string("12345::").is_err());
+    }
+
     #[test]
     fn address_v6_incorrect_from_string() {
         let address = AddressV6::from_string("123|123.423.432.23");
         assert!(address.is_err());
     }
-    
+
+    #[test]
+    fn address_parse_errors() {
+        assert!(matches!(AddressV6::from_string("1:2:3:4:5:6:7"), Err(AddressError::WrongFormat(_))));
+        assert!(matches!(AddressV6::from_string("1::2:3:4:5:6:7:8"), Err(AddressError::WrongFormat(_))));
+        assert!(matches!(AddressV6::from_string("2001:db8::g"), Err(AddressError::WrongFormat(_))));
+        assert!(matches!(AddressV4::parse("10.0.0"), Err(AddressError::WrongFormat(_))));
+        assert!(matches!(AddressV4::parse("10.0.0.256"), Err(AddressError::WrongNumber(_))));
+        assert_eq!(AddressV6::parse("1:2:3").unwrap_err().to_string(), "'1:2:3' doesn't have 8 groups");
+    }
 }
\ No newline at end of file
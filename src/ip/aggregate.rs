@@ -0,0 +1,110 @@
+use std::borrow::Borrow;
+
+use super::{Network, Version};
+
+// Smallest sorted list of networks that covers exactly the same addresses.
+// Covered entries are dropped and adjacent siblings merged, each family
+// separately. Single addresses go in as host networks, see `Network::host`.
+pub fn collapse<I>(networks: I) -> Vec<Network>
+where
+    I: IntoIterator,
+    I::Item: Borrow<Network>,
+{
+    let mut ranges: Vec<(Version, u128, u128)> = networks.into_iter()
+        .map(|network| {
+            let network = network.borrow();
+            (network.version(), network.first_bits(), network.last_bits())
+        })
+        .collect();
+    ranges.sort_unstable();
+    merge_ranges(ranges).into_iter()
+        .flat_map(|(version, first, last)| range_to_networks(version, first, last))
+        .collect()
+}
+
+// Joins overlapping and touching ranges. Input must be sorted.
+pub(crate) fn merge_ranges(sorted: Vec<(Version, u128, u128)>) -> Vec<(Version, u128, u128)> {
+    let mut merged: Vec<(Version, u128, u128)> = Vec::with_capacity(sorted.len());
+    for (version, first, last) in sorted {
+        match merged.last_mut() {
+            Some((previous_version, _, previous_last))
+                if *previous_version == version && first <= previous_last.saturating_add(1) =>
+            {
+                *previous_last = (*previous_last).max(last);
+            }
+            _ => merged.push((version, first, last)),
+        }
+    }
+    merged
+}
+
+// Minimal list of networks covering the inclusive range first..=last
+pub(crate) fn range_to_networks(version: Version, first: u128, last: u128) -> Vec<Network> {
+    let width = version.address_bits();
+    let mut networks = Vec::new();
+    let mut current = first;
+    loop {
+        let span = last - current;
+        // Biggest block that fits in what is left of the range...
+        let fitting = if span == u128::MAX { 128 } else { 127 - (span + 1).leading_zeros() as u8 };
+        // ...and that starts on its own boundary
+        let aligned = (current.trailing_zeros() as u8).min(width);
+        let host_bits = fitting.min(aligned);
+        networks.push(Network::from_bits(version, current, width - host_bits));
+        if host_bits == 128 {
+            break;
+        }
+        let block_last = current + ((1_u128 << host_bits) - 1);
+        if block_last >= last {
+            break;
+        }
+        current = block_last + 1;
+    }
+    networks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::address::*;
+    use super::super::test_helpers::network_v4;
+
+    fn to_strings(networks: &[Network]) -> Vec<String> {
+        networks.iter().map(|network| network.to_string()).collect()
+    }
+
+    #[test]
+    fn collapse_siblings_and_covered() {
+        let networks = vec![
+            network_v4("10.0.1.0", 24),
+            network_v4("10.0.0.0", 24),
+            network_v4("10.0.0.128", 25),
+            network_v4("10.0.2.0", 23),
+            network_v4("192.168.0.0", 24),
+        ];
+        assert_eq!(to_strings(&collapse(&networks)), vec!["10.0.0.0/22", "192.168.0.0/24"]);
+    }
+
+    #[test]
+    fn collapse_addresses_and_families() {
+        let mut networks: Vec<Network> = (0..4_u32)
+            .map(|i| Network::from(SomeAddress::V4(AddressV4::from_u32(0x0a000000 + i))))
+            .collect();
+        networks.push(Network::from(SomeAddress::V4(AddressV4::from_u32(0x0a000005))));
+        networks.push(Network::from(SomeAddress::V6(AddressV6::from_u128(1))));
+        networks.push(Network::from(SomeAddress::V6(AddressV6::from_u128(0))));
+        let collapsed = collapse(networks);
+        assert_eq!(collapsed.len(), 3);
+        assert_eq!(to_strings(&collapsed[..2]), vec!["10.0.0.0/30", "10.0.0.5/32"]);
+        assert_eq!(collapsed[2], Network::from_bits(Version::IpV6, 0, 127));
+    }
+
+    #[test]
+    fn range_to_networks_unaligned() {
+        let networks = range_to_networks(Version::IpV4, 0x0a000005, 0x0a00004d);
+        assert_eq!(to_strings(&networks), vec!["10.0.0.5/32", "10.0.0.6/31", "10.0.0.8/29", "10.0.0.16/28", "10.0.0.32/27", "10.0.0.64/29", "10.0.0.72/30", "10.0.0.76/31"]);
+        assert_eq!(to_strings(&range_to_networks(Version::IpV4, 0, u32::MAX.into())), vec!["0.0.0.0/0"]);
+        assert_eq!(range_to_networks(Version::IpV6, 0, u128::MAX), vec![Network::from_bits(Version::IpV6, 0, 0)]);
+        assert_eq!(range_to_networks(Version::IpV6, u128::MAX, u128::MAX).len(), 1);
+    }
+}
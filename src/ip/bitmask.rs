@@ -25,6 +25,23 @@ impl Bitmask { // Probably traits are the better way
             Bitmask::V6(mask) => mask.addresses_number(),
         }
     }
+
+    // Unlike BitmaskV4::new and BitmaskV6::new this allows host masks (/32 and /128).
+    // They aren't typed in by users but come up when networks are computed.
+    pub(crate) fn from_bits_number(version: super::Version, bits_number: u8) -> Bitmask {
+        assert!(bits_number <= version.address_bits(), "Mask is longer than the address");
+        match version {
+            super::Version::IpV4 => Bitmask::V4(BitmaskV4 { mask: BitmaskV4::fill_n_bits(bits_number), bits_number }),
+            super::Version::IpV6 => Bitmask::V6(BitmaskV6 { mask: BitmaskV6::fill_n_bits(bits_number), bits_number }),
+        }
+    }
+
+    pub fn version(&self) -> super::Version {
+        match self {
+            Bitmask::V4(_) => super::Version::IpV4,
+            Bitmask::V6(_) => super::Version::IpV6,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -49,7 +66,7 @@ impl BitmaskV4 {
         if bits_number >= 32 {
             return Err(MaskError::WrongBitsNumber(String::from("Mask can't be greater than 31")));
         }
-        return Ok(BitmaskV4{
+        Ok(BitmaskV4{
             mask: BitmaskV4::fill_n_bits(bits_number),
             bits_number
         })
@@ -65,7 +82,7 @@ impl BitmaskV4 {
 
     pub fn addresses_number(&self) -> u128 {
         let available_bits = 32 - self.bits_number;
-        (2_u32.pow(available_bits.into()) - 2).into()
+        (1_u128 << available_bits).saturating_sub(2)
     }
 
     pub fn to_bitstring(&self) -> String {
@@ -77,7 +94,7 @@ impl BitmaskV4 {
         for i in 0..n {
             bitmask |= 0x1 << i;
         }
-        return bitmask.reverse_bits();
+        bitmask.reverse_bits()
     }
 }
 
@@ -86,7 +103,7 @@ impl BitmaskV6 {
         if bits_number >= 127 {
             return Err(MaskError::WrongBitsNumber(String::from("Mask can't be greater than 127")));
         }
-        return Ok(BitmaskV6{
+        Ok(BitmaskV6{
             mask: BitmaskV6::fill_n_bits(bits_number),
             bits_number
         })
@@ -101,8 +118,9 @@ impl BitmaskV6 {
     }
 
     pub fn addresses_number(&self) -> u128 {
+        // Saturates for /0, 2^128 doesn't fit
         let available_bits = 128 - self.bits_number;
-        (2_u128.pow(available_bits.into())).into()
+        2_u128.checked_pow(available_bits.into()).unwrap_or(u128::MAX)
     }
 
     pub fn to_bitstring(&self) -> String {
@@ -122,7 +140,7 @@ impl BitmaskV6 {
         for i in 0..n {
             bitmask |= 0x1 << i;
         }
-        return bitmask.reverse_bits();
+        bitmask.reverse_bits()
     }
 }
 
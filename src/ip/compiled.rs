@@ -0,0 +1,327 @@
+use std::fs;
+use std::marker::PhantomData;
+use std::path::Path;
+
+use super::address::{AddressV4, AddressV6, FixedWidth};
+use super::prefix_map::PrefixMap;
+use super::Network;
+
+// Read-only longest-prefix-match table that is queried straight from its
+// serialised bytes, so a file can be memory-mapped (or just read) and used
+// without building anything. Nested prefixes are flattened into a sorted
+// list of disjoint ranges covering the whole address space, and a directory
+// indexed by the top 16 address bits narrows the binary search over them.
+// The directory is the same for both families: for IPv6 the top 16 bits
+// barely separate real tables, almost everything lives in 2000::/3, so
+// there it saves a step or two and the search stays O(log n).
+//
+// Layout, all integers little endian:
+//   header     32 bytes: magic "IPNETLPM", format version u16, family u8 (4 or 6), zero padding, ranges number u64
+//   directory  65537 x u32: index of the range holding the first address of every /16 slot, then the last index
+//   starts     ranges number x (4 or 16 bytes): first address of every range
+//   values     ranges number x u32: value of every range, u32::MAX where no prefix matches
+//   lengths    ranges number x u8: prefix length of the network the value comes from
+pub struct CompiledTable<'a, A> {
+    bytes: &'a [u8],
+    count: usize,
+    family: PhantomData<A>,
+}
+
+pub type CompiledTableV4<'a> = CompiledTable<'a, AddressV4>;
+pub type CompiledTableV6<'a> = CompiledTable<'a, AddressV6>;
+
+#[derive(Debug)]
+pub enum TableError {
+    WrongFormat(String),
+    ValueTooLarge,
+    Io(std::io::Error),
+}
+
+const MAGIC: &[u8; 8] = b"IPNETLPM";
+const FORMAT_VERSION: u16 = 1;
+const HEADER_SIZE: usize = 32;
+const DIRECTORY_SLOTS: usize = 1 << 16;
+const DIRECTORY_SIZE: usize = (DIRECTORY_SLOTS + 1) * 4;
+const NO_VALUE: u32 = u32::MAX;
+
+impl<'a, A: FixedWidth> CompiledTable<'a, A> {
+    // Values must be below u32::MAX, which marks addresses without a match
+    pub fn compile(map: &PrefixMap<A, u32>) -> Result<Vec<u8>, TableError> {
+        let ranges = flatten::<A>(map)?;
+        let width = (A::BITS / 8) as usize;
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + DIRECTORY_SIZE + ranges.len() * (width + 5));
+
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.push(family::<A>());
+        bytes.resize(16, 0);
+        bytes.extend_from_slice(&(ranges.len() as u64).to_le_bytes());
+        bytes.resize(HEADER_SIZE, 0);
+
+        let shift = A::BITS - 16;
+        for slot in 0..DIRECTORY_SLOTS as u128 {
+            let index = ranges.partition_point(|(start, _, _)| *start <= slot << shift) - 1;
+            bytes.extend_from_slice(&(index as u32).to_le_bytes());
+        }
+        bytes.extend_from_slice(&(ranges.len() as u32 - 1).to_le_bytes());
+
+        for (start, _, _) in &ranges {
+            bytes.extend_from_slice(&start.to_le_bytes()[..width]);
+        }
+        for (_, value, _) in &ranges {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend(ranges.iter().map(|(_, _, len)| *len));
+        Ok(bytes)
+    }
+
+    pub fn write_file<P: AsRef<Path>>(map: &PrefixMap<A, u32>, path: P) -> Result<(), TableError> {
+        fs::write(path, CompiledTable::compile(map)?).map_err(TableError::Io)
+    }
+
+    // Nothing is copied, but the directory, the starts and the prefix
+    // lengths are checked once here so that lookup can't panic on a
+    // corrupted or hostile file
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<CompiledTable<'a, A>, TableError> {
+        if bytes.len() < HEADER_SIZE || &bytes[..8] != MAGIC {
+            return Err(TableError::WrongFormat(String::from("Not a compiled prefix table")));
+        }
+        let format_version = u16::from_le_bytes([bytes[8], bytes[9]]);
+        if format_version != FORMAT_VERSION {
+            return Err(TableError::WrongFormat(format!("Unsupported format version {}", format_version)));
+        }
+        if bytes[10] != family::<A>() {
+            return Err(TableError::WrongFormat(format!("Table is for IPv{}, not IPv{}", bytes[10], family::<A>())));
+        }
+        let mut count = [0_u8; 8];
+        count.copy_from_slice(&bytes[16..24]);
+        let count = usize::try_from(u64::from_le_bytes(count)).ok().filter(|count| *count > 0 && *count <= u32::MAX as usize)
+            .ok_or_else(|| TableError::WrongFormat(String::from("Wrong number of ranges")))?;
+        let expected = count.checked_mul((A::BITS / 8) as usize + 5)
+            .and_then(|size| size.checked_add(HEADER_SIZE + DIRECTORY_SIZE));
+        if expected != Some(bytes.len()) {
+            return Err(TableError::WrongFormat(format!("Expected {} bytes for {} ranges, got {}",
+                expected.map_or_else(|| String::from("more than usize::MAX"), |size| size.to_string()), count, bytes.len())));
+        }
+        let table = CompiledTable { bytes, count, family: PhantomData };
+        table.validate()?;
+        Ok(table)
+    }
+
+    fn validate(&self) -> Result<(), TableError> {
+        let mut previous = 0;
+        for slot in 0..=DIRECTORY_SLOTS {
+            let index = self.directory(slot);
+            if index < previous || index >= self.count {
+                return Err(TableError::WrongFormat(format!("Directory slot {} points to range {} out of order", slot, index)));
+            }
+            previous = index;
+        }
+        if self.directory(DIRECTORY_SLOTS) != self.count - 1 {
+            return Err(TableError::WrongFormat(String::from("Directory doesn't end at the last range")));
+        }
+        if self.start(0) != 0 || (1..self.count).any(|index| self.start(index - 1) >= self.start(index)) {
+            return Err(TableError::WrongFormat(String::from("Range starts aren't strictly increasing from 0")));
+        }
+        let lengths = &self.bytes[self.values_offset() + self.count * 4..];
+        if let Some(len) = lengths.iter().find(|len| **len > A::BITS) {
+            return Err(TableError::WrongFormat(format!("Prefix length {} is too long for IPv{}", len, family::<A>())));
+        }
+        Ok(())
+    }
+
+    // Number of flattened ranges, including the ones without a value
+    pub fn ranges_number(&self) -> usize {
+        self.count
+    }
+
+    pub fn lookup(&self, address: &A) -> Option<(Network, u32)> {
+        let bits = address.to_bits();
+        let slot = (bits >> (A::BITS - 16)) as usize;
+        let mut low = self.directory(slot);
+        let mut high = self.directory(slot + 1);
+        // Last range starting at or before the address
+        while low < high {
+            let middle = (low + high).div_ceil(2);
+            if self.start(middle) <= bits {
+                low = middle;
+            } else {
+                high = middle - 1;
+            }
+        }
+        let value = self.read_u32(self.values_offset() + low * 4);
+        if value == NO_VALUE {
+            return None;
+        }
+        let len = self.bytes[self.values_offset() + self.count * 4 + low];
+        let host_bits = A::BITS - len;
+        let first = if host_bits == 128 { 0 } else { bits >> host_bits << host_bits };
+        Some((Network::from_bits(A::VERSION, first, len), value))
+    }
+
+    fn directory(&self, slot: usize) -> usize {
+        self.read_u32(HEADER_SIZE + slot * 4) as usize
+    }
+
+    fn start(&self, index: usize) -> u128 {
+        let width = (A::BITS / 8) as usize;
+        let offset = HEADER_SIZE + DIRECTORY_SIZE + index * width;
+        let mut start = [0_u8; 16];
+        start[..width].copy_from_slice(&self.bytes[offset..offset + width]);
+        u128::from_le_bytes(start)
+    }
+
+    fn values_offset(&self) -> usize {
+        HEADER_SIZE + DIRECTORY_SIZE + self.count * (A::BITS / 8) as usize
+    }
+
+    fn read_u32(&self, offset: usize) -> u32 {
+        let mut value = [0_u8; 4];
+        value.copy_from_slice(&self.bytes[offset..offset + 4]);
+        u32::from_le_bytes(value)
+    }
+}
+
+fn family<A: FixedWidth>() -> u8 {
+    A::VERSION as u8
+}
+
+// Turns nested prefixes into disjoint (start, value, prefix length) ranges
+// covering the whole space. Entries come sorted by address with shorter
+// prefixes first, so a stack of the currently open prefixes is enough.
+fn flatten<A: FixedWidth>(map: &PrefixMap<A, u32>) -> Result<Vec<(u128, u32, u8)>, TableError> {
+    let mut ranges: Vec<(u128, u32, u8)> = Vec::new();
+    let mut open: Vec<(u128, u32, u8)> = Vec::new();
+
+    fn push(ranges: &mut Vec<(u128, u32, u8)>, start: u128, value: u32, len: u8) {
+        match ranges.last_mut() {
+            Some(last) if last.0 == start => *last = (start, value, len),
+            Some(last) if last.1 == value && last.2 == len && value == NO_VALUE => {}
+            _ => ranges.push((start, value, len)),
+        }
+    }
+    // Closes open prefixes that end before `until`, resuming their parents
+    fn close(ranges: &mut Vec<(u128, u32, u8)>, open: &mut Vec<(u128, u32, u8)>, until: Option<u128>, max: u128) {
+        while let Some(&(last, _, _)) = open.last() {
+            if until.is_some_and(|until| last >= until) {
+                break;
+            }
+            open.pop();
+            if last < max {
+                let (value, len) = open.last().map(|&(_, value, len)| (value, len)).unwrap_or((NO_VALUE, 0));
+                push(ranges, last + 1, value, len);
+            }
+        }
+    }
+
+    for (network, &value) in map.iter() {
+        if value == NO_VALUE {
+            return Err(TableError::ValueTooLarge);
+        }
+        let (first, last) = (network.first_bits(), network.last_bits());
+        close(&mut ranges, &mut open, Some(first), A::VERSION.max_bits());
+        if ranges.is_empty() && first > 0 {
+            push(&mut ranges, 0, NO_VALUE, 0);
+        }
+        push(&mut ranges, first, value, network.prefix_len());
+        open.push((last, value, network.prefix_len()));
+    }
+    close(&mut ranges, &mut open, None, A::VERSION.max_bits());
+    if ranges.is_empty() {
+        ranges.push((0, NO_VALUE, 0));
+    }
+    Ok(ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::prefix_map::{PrefixMapV4, PrefixMapV6};
+    use super::super::Version;
+    use super::super::test_helpers::network_v4;
+
+    fn v4(address: &str) -> AddressV4 {
+        AddressV4::from_string(address).unwrap()
+    }
+
+    fn routes() -> PrefixMapV4<u32> {
+        let mut map = PrefixMapV4::new();
+        map.insert(&network_v4("10.0.0.0", 8), 1).unwrap();
+        map.insert(&network_v4("10.1.0.0", 16), 2).unwrap();
+        map.insert(&network_v4("10.1.2.0", 24), 3).unwrap();
+        map.insert(&network_v4("10.1.2.3", 32), 4).unwrap();
+        map.insert(&network_v4("10.1.3.0", 24), 5).unwrap();
+        map.insert(&network_v4("192.168.0.0", 16), 6).unwrap();
+        map.insert(&network_v4("255.255.255.255", 32), 7).unwrap();
+        map
+    }
+
+    #[test]
+    fn compiled_matches_prefix_map() {
+        let map = routes();
+        let bytes = CompiledTableV4::compile(&map).unwrap();
+        let table = CompiledTableV4::from_bytes(&bytes).unwrap();
+        for address in ["0.0.0.0", "9.255.255.255", "10.0.0.0", "10.1.2.2", "10.1.2.3", "10.1.2.4", "10.1.3.255", "10.1.4.0",
+                        "10.255.255.255", "11.0.0.0", "192.168.77.1", "255.255.255.254", "255.255.255.255"] {
+            let expected = map.longest_match(&v4(address)).map(|(network, value)| (network, *value));
+            assert_eq!(table.lookup(&v4(address)), expected, "{}", address);
+        }
+        // Pseudo-random addresses from a fixed LCG, weighted towards 10/8
+        let mut seed = 12345_u32;
+        for _ in 0..10000 {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            let address = AddressV4::from_u32(if seed.is_multiple_of(2) { seed } else { 0x0a000000 | (seed >> 8) });
+            assert_eq!(table.lookup(&address), map.longest_match(&address).map(|(network, value)| (network, *value)));
+        }
+    }
+
+    #[test]
+    fn compiled_file_round_trip() {
+        let path = std::env::temp_dir().join(format!("ip-network-compiled-{}.lpm", std::process::id()));
+        CompiledTableV4::write_file(&routes(), &path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let table = CompiledTableV4::from_bytes(&bytes).unwrap();
+        assert_eq!(table.lookup(&v4("10.1.3.1")), Some((network_v4("10.1.3.0", 24), 5)));
+        assert!(matches!(CompiledTableV6::from_bytes(&bytes), Err(TableError::WrongFormat(_))));
+        assert!(matches!(CompiledTableV4::from_bytes(&bytes[..bytes.len() - 1]), Err(TableError::WrongFormat(_))));
+    }
+
+    #[test]
+    fn compiled_v6_and_edge_cases() {
+        let mut map = PrefixMapV6::new();
+        map.insert(&Network::from_bits(Version::IpV6, 0x2001_0db8 << 96, 32), 1).unwrap();
+        map.insert(&Network::from_bits(Version::IpV6, 0, 0), 0).unwrap();
+        let bytes = CompiledTableV6::compile(&map).unwrap();
+        let table = CompiledTableV6::from_bytes(&bytes).unwrap();
+        assert_eq!(table.lookup(&AddressV6::from_u128((0x2001_0db8 << 96) | 5)).map(|(_, value)| value), Some(1));
+        assert_eq!(table.lookup(&AddressV6::from_u128(u128::MAX)), Some((Network::from_bits(Version::IpV6, 0, 0), 0)));
+
+        let empty = CompiledTableV4::compile(&PrefixMapV4::new()).unwrap();
+        assert_eq!(CompiledTableV4::from_bytes(&empty).unwrap().lookup(&v4("1.2.3.4")), None);
+        let mut wrong = PrefixMapV4::new();
+        wrong.insert(&network_v4("10.0.0.0", 8), u32::MAX).unwrap();
+        assert!(matches!(CompiledTableV4::compile(&wrong), Err(TableError::ValueTooLarge)));
+    }
+
+    #[test]
+    fn compiled_rejects_corrupted_bytes() {
+        let bytes = CompiledTableV4::compile(&routes()).unwrap();
+        let count = CompiledTableV4::from_bytes(&bytes).unwrap().ranges_number();
+        let corrupt = |offset: usize, value: &[u8]| {
+            let mut corrupted = bytes.clone();
+            corrupted[offset..offset + value.len()].copy_from_slice(value);
+            CompiledTableV4::from_bytes(&corrupted).err()
+        };
+        // Ranges number so large that the expected size overflows
+        assert!(matches!(corrupt(16, &u64::MAX.to_le_bytes()), Some(TableError::WrongFormat(_))));
+        assert!(matches!(corrupt(16, &(1_u64 << 62).to_le_bytes()), Some(TableError::WrongFormat(_))));
+        // Directory slot past the last range, and one going backwards
+        assert!(matches!(corrupt(HEADER_SIZE + 4, &(count as u32).to_le_bytes()), Some(TableError::WrongFormat(_))));
+        assert!(matches!(corrupt(HEADER_SIZE + 0xc000 * 4, &0_u32.to_le_bytes()), Some(TableError::WrongFormat(_))));
+        // Prefix length longer than the address
+        assert!(matches!(corrupt(bytes.len() - 1, &[33]), Some(TableError::WrongFormat(_))));
+        // Starts out of order
+        assert!(matches!(corrupt(HEADER_SIZE + DIRECTORY_SIZE + 4, &u32::MAX.to_le_bytes()), Some(TableError::WrongFormat(_))));
+    }
+}
@@ -0,0 +1,251 @@
+use std::cmp::Reverse;
+
+use super::{Network, NetworkError, Version};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub label: String,
+    pub network: Network,
+}
+
+// The same network listed more than once
+#[derive(Debug, Clone, PartialEq)]
+pub struct Duplicate {
+    pub network: Network,
+    pub labels: Vec<String>,
+}
+
+// CIDR blocks can only overlap by nesting, so every overlap has an outer
+// and an inner network
+#[derive(Debug, Clone, PartialEq)]
+pub struct Overlap {
+    pub outer: Entry,
+    pub inner: Entry,
+}
+
+// Entries connected by overlaps, all inside the outermost one
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverlapGroup {
+    pub span: Network,
+    pub labels: Vec<String>,
+}
+
+// Source text like "10.0.0.1/24" whose address isn't the network address
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostBits {
+    pub label: String,
+    pub text: String,
+    pub network: Network,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Invalid {
+    pub label: String,
+    pub text: String,
+    pub error: NetworkError,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ConflictReport {
+    pub duplicates: Vec<Duplicate>,
+    pub overlaps: Vec<Overlap>,
+    pub groups: Vec<OverlapGroup>,
+    // Maximal outer-to-inner nesting chains of at least two networks.
+    // Duplicates show up once, under their first label.
+    pub chains: Vec<Vec<Entry>>,
+    pub host_bits: Vec<HostBits>,
+    pub invalid: Vec<Invalid>,
+}
+
+impl ConflictReport {
+    pub fn is_clean(&self) -> bool {
+        self.duplicates.is_empty() && self.overlaps.is_empty() && self.host_bits.is_empty() && self.invalid.is_empty()
+    }
+}
+
+// Checks labelled networks as written in the inventory, e.g. ("team a", "10.0.0.0/24").
+// A bare address is taken as a host network.
+pub fn detect<I, L, T>(entries: I) -> ConflictReport
+where
+    I: IntoIterator<Item = (L, T)>,
+    L: Into<String>,
+    T: AsRef<str>,
+{
+    let mut host_bits = Vec::new();
+    let mut invalid = Vec::new();
+    let mut networks = Vec::new();
+    for (label, text) in entries {
+        let (label, text) = (label.into(), text.as_ref().trim().to_string());
+        let (address, network) = match parse(&text) {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                invalid.push(Invalid { label, text, error });
+                continue;
+            }
+        };
+        if address.first_bits() != network.first_bits() {
+            host_bits.push(HostBits { label: label.clone(), text, network: network.clone() });
+        }
+        networks.push(Entry { label, network });
+    }
+    let mut report = detect_networks(networks);
+    report.host_bits = host_bits;
+    report.invalid = invalid;
+    report
+}
+
+// The address as a host network and the network it names
+fn parse(text: &str) -> Result<(Network, Network), NetworkError> {
+    let (address, prefix_len) = match text.split_once('/') {
+        Some((address, prefix_len)) => (address, Some(prefix_len)),
+        None => (text, None),
+    };
+    let address_bits = if address.contains(':') { Version::IpV6 } else { Version::IpV4 }.address_bits();
+    let host = Network::from_string(&format!("{}/{}", address, address_bits))?;
+    let network = match prefix_len {
+        Some(_) => Network::from_string(text)?,
+        None => host.clone(),
+    };
+    Ok((host, network))
+}
+
+// Same as `detect` for networks that are already parsed. One sort and a
+// sweep with a stack of the networks holding the current one, so the cost
+// is O(n log n) plus the size of the report.
+pub fn detect_networks<I: IntoIterator<Item = Entry>>(entries: I) -> ConflictReport {
+    let mut entries: Vec<Entry> = entries.into_iter().collect();
+    // Outer networks before the ones they hold, input order among duplicates
+    entries.sort_by_key(|entry| (entry.network.version(), entry.network.first_bits(), Reverse(entry.network.last_bits())));
+
+    // Equal networks become one node
+    let mut nodes: Vec<Vec<Entry>> = Vec::new();
+    for entry in entries {
+        match nodes.last_mut() {
+            Some(node) if node[0].network == entry.network => node.push(entry),
+            _ => nodes.push(vec![entry]),
+        }
+    }
+
+    let mut report = ConflictReport::default();
+    let mut stack: Vec<(usize, bool)> = Vec::new();
+    let mut group: Option<OverlapGroup> = None;
+    for (index, node) in nodes.iter().enumerate() {
+        if node.len() > 1 {
+            report.duplicates.push(Duplicate {
+                network: node[0].network.clone(),
+                labels: node.iter().map(|entry| entry.label.clone()).collect(),
+            });
+        }
+        while let Some(&(top, _)) = stack.last() {
+            let outer = &nodes[top][0].network;
+            if outer.version() == node[0].network.version() && outer.last_bits() >= node[0].network.first_bits() {
+                break;
+            }
+            pop(&nodes, &mut stack, &mut report);
+        }
+        match stack.last_mut() {
+            Some((_, has_inner)) => *has_inner = true,
+            None => {
+                finish(group.take(), &mut report);
+                group = Some(OverlapGroup { span: node[0].network.clone(), labels: Vec::new() });
+            }
+        }
+        for (outer, _) in &stack {
+            for outer in &nodes[*outer] {
+                for inner in node {
+                    report.overlaps.push(Overlap { outer: outer.clone(), inner: inner.clone() });
+                }
+            }
+        }
+        if let Some(group) = group.as_mut() {
+            group.labels.extend(node.iter().map(|entry| entry.label.clone()));
+        }
+        stack.push((index, false));
+    }
+    while !stack.is_empty() {
+        pop(&nodes, &mut stack, &mut report);
+    }
+    finish(group, &mut report);
+    report
+}
+
+// A node without inner networks ends a chain made of the whole stack
+fn pop(nodes: &[Vec<Entry>], stack: &mut Vec<(usize, bool)>, report: &mut ConflictReport) {
+    if let Some(&(_, false)) = stack.last() {
+        if stack.len() > 1 {
+            report.chains.push(stack.iter().map(|(index, _)| nodes[*index][0].clone()).collect());
+        }
+    }
+    stack.pop();
+}
+
+fn finish(group: Option<OverlapGroup>, report: &mut ConflictReport) {
+    if let Some(group) = group.filter(|group| group.labels.len() > 1) {
+        report.groups.push(group);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(entries: &[Entry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.label.as_str()).collect()
+    }
+
+    #[test]
+    fn conflicts_overlaps_and_chains() {
+        let report = detect([
+            ("dc", "10.0.0.0/16"),
+            ("office", "192.168.0.0/24"),
+            ("rack 1", "10.0.1.0/24"),
+            ("web", "10.0.1.16/28"),
+            ("rack 2", "10.0.2.0/24"),
+            ("v6 lab", "2001:0db8:0000:0000:0000:0000:0000:0000/48"),
+        ]);
+        let pairs: Vec<(&str, &str)> = report.overlaps.iter().map(|overlap| (overlap.outer.label.as_str(), overlap.inner.label.as_str())).collect();
+        assert_eq!(pairs, vec![("dc", "rack 1"), ("dc", "web"), ("rack 1", "web"), ("dc", "rack 2")]);
+        assert_eq!(report.chains.iter().map(|chain| labels(chain)).collect::<Vec<Vec<&str>>>(), vec![
+            vec!["dc", "rack 1", "web"],
+            vec!["dc", "rack 2"],
+        ]);
+        assert_eq!(report.groups, vec![OverlapGroup {
+            span: Network::from_string("10.0.0.0/16").unwrap(),
+            labels: vec![String::from("dc"), String::from("rack 1"), String::from("web"), String::from("rack 2")],
+        }]);
+        assert!(report.duplicates.is_empty());
+        assert!(!report.is_clean());
+    }
+
+    #[test]
+    fn conflicts_duplicates_host_bits_and_invalid() {
+        let report = detect([
+            ("team a", "10.1.0.0/24"),
+            ("team b", "10.1.0.77/24"),
+            ("team c", "10.1.0.0/24"),
+            ("router", "10.2.0.1"),
+            ("typo", "10.3.0.0/33"),
+            ("junk", "ten dot one"),
+        ]);
+        assert_eq!(report.duplicates, vec![Duplicate {
+            network: Network::from_string("10.1.0.0/24").unwrap(),
+            labels: vec![String::from("team a"), String::from("team b"), String::from("team c")],
+        }]);
+        assert!(report.overlaps.is_empty());
+        assert_eq!(report.groups.len(), 1);
+        assert_eq!(report.host_bits.iter().map(|host_bits| host_bits.label.as_str()).collect::<Vec<&str>>(), vec!["team b"]);
+        assert_eq!(report.invalid.iter().map(|invalid| invalid.label.as_str()).collect::<Vec<&str>>(), vec!["typo", "junk"]);
+        assert!(matches!(report.invalid[0].error, NetworkError::WrongPrefixLength(_)));
+    }
+
+    #[test]
+    fn conflicts_clean_inventory() {
+        let report = detect([("a", "10.0.0.0/25"), ("b", "10.0.0.128/25"), ("c", "0.0.0.0/0"), ("d", "0000:0000:0000:0000:0000:0000:0000:0001")]);
+        assert_eq!(report.overlaps.len(), 2);
+        assert!(report.invalid.is_empty());
+        let report = detect([("a", "10.0.0.0/25"), ("b", "10.0.0.128/25"), ("d", "0000:0000:0000:0000:0000:0000:0000:0001")]);
+        assert!(report.is_clean());
+        assert!(report.invalid.is_empty());
+        assert!(report.groups.is_empty() && report.chains.is_empty());
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use super::{Network, NetworkError};
+
+// Where new delegations are placed inside the pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    // Lowest free aligned block, packs the pool densely
+    Sequential,
+    // Candidates in bit-reversed order (0, 1/2, 1/4, 3/4, ...), so every
+    // delegation keeps as much free space around it as possible to grow into
+    Bisection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Delegated,
+    Reserved,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delegation {
+    pub network: Network,
+    pub kind: Kind,
+    pub owner: String,
+}
+
+#[derive(Debug)]
+pub enum PoolError {
+    OutsidePool(Network),
+    Overlap(Network),
+    Exhausted(String),
+    WrongLength(String),
+    NotFound(Network),
+    Snapshot(String),
+    Network(NetworkError),
+    Io(std::io::Error),
+}
+
+// Prefix delegation pool, e.g. a /40 handing out /48, /56 and /64 prefixes.
+// Every delegation is aligned to its own size and never overlaps another one.
+pub struct DelegationPool {
+    pool: Network,
+    strategy: Strategy,
+    // First address -> delegation, delegations are disjoint
+    delegations: BTreeMap<u128, Delegation>,
+}
+
+// Snapshots are plain text on purpose, one "Delegated <prefix> <owner>" line
+// per delegation after the pool and strategy lines. Pools hold a flat list
+// that operators diff between backups, grep for a customer and fix by hand,
+// which a line format keeps easy. Owners are escaped so a line stays a line.
+const SNAPSHOT_HEADER: &str = "# ip-network delegation pool v1";
+
+impl DelegationPool {
+    pub fn new(pool: Network, strategy: Strategy) -> DelegationPool {
+        DelegationPool { pool, strategy, delegations: BTreeMap::new() }
+    }
+
+    pub fn pool(&self) -> &Network {
+        &self.pool
+    }
+
+    pub fn allocate(&mut self, prefix_len: u8, owner: &str) -> Result<Network, PoolError> {
+        if prefix_len < self.pool.prefix_len() || prefix_len > self.pool.version().address_bits() {
+            return Err(PoolError::WrongLength(format!(
+                "/{} can't be delegated from {}", prefix_len, self.pool
+            )));
+        }
+        let network = match self.strategy {
+            Strategy::Sequential => self.first_free(prefix_len),
+            Strategy::Bisection => self.bisection_free(prefix_len),
+        }.ok_or_else(|| PoolError::Exhausted(format!("No free /{} left in {}", prefix_len, self.pool)))?;
+        self.insert(network.clone(), Kind::Delegated, owner);
+        Ok(network)
+    }
+
+    // Takes a specific prefix out of the pool, for infrastructure or a customer moving in
+    pub fn reserve(&mut self, network: &Network, owner: &str) -> Result<(), PoolError> {
+        self.check_free(network)?;
+        self.insert(network.clone(), Kind::Reserved, owner);
+        Ok(())
+    }
+
+    // Delegates a specific prefix, like `reserve` but counted as a delegation
+    pub fn assign(&mut self, network: &Network, owner: &str) -> Result<(), PoolError> {
+        self.check_free(network)?;
+        self.insert(network.clone(), Kind::Delegated, owner);
+        Ok(())
+    }
+
+    pub fn release(&mut self, network: &Network) -> Result<Delegation, PoolError> {
+        match self.delegations.get(&network.first_bits()) {
+            Some(delegation) if delegation.network == *network => {}
+            _ => return Err(PoolError::NotFound(network.clone())),
+        }
+        Ok(self.delegations.remove(&network.first_bits()).unwrap())
+    }
+
+    // Releases everything held by the owner, e.g. when a customer leaves
+    pub fn reclaim(&mut self, owner: &str) -> Vec<Delegation> {
+        let starts: Vec<u128> = self.delegations.iter()
+            .filter(|(_, delegation)| delegation.owner == owner)
+            .map(|(start, _)| *start)
+            .collect();
+        starts.iter().filter_map(|start| self.delegations.remove(start)).collect()
+    }
+
+    // Changes the prefix length of a delegation in place. Growing only works
+    // while the covering block is free, which Bisection tries to keep so.
+    pub fn resize(&mut self, network: &Network, prefix_len: u8) -> Result<Network, PoolError> {
+        let delegation = self.release(network)?;
+        let resized = if prefix_len <= network.prefix_len() {
+            network.supernet_of_len(prefix_len).map_err(PoolError::Network)?
+        } else {
+            network.subnet(prefix_len, 0).map_err(PoolError::Network)?
+        };
+        if let Err(error) = self.check_free(&resized) {
+            self.delegations.insert(network.first_bits(), delegation);
+            return Err(error);
+        }
+        self.insert(resized.clone(), delegation.kind, &delegation.owner);
+        Ok(resized)
+    }
+
+    pub fn get(&self, network: &Network) -> Option<&Delegation> {
+        self.delegations.get(&network.first_bits()).filter(|delegation| delegation.network == *network)
+    }
+
+    // The delegation holding the network, if any
+    pub fn find(&self, network: &Network) -> Option<&Delegation> {
+        let (_, delegation) = self.delegations.range(..=network.first_bits()).next_back()?;
+        if delegation.network.contains_network(network) == Ok(true) {
+            return Some(delegation);
+        }
+        None
+    }
+
+    pub fn delegations(&self) -> impl Iterator<Item = &Delegation> {
+        self.delegations.values()
+    }
+
+    pub fn owned_by<'a>(&'a self, owner: &'a str) -> impl Iterator<Item = &'a Delegation> {
+        self.delegations.values().filter(move |delegation| delegation.owner == owner)
+    }
+
+    // Maximal free blocks of the pool, sorted
+    pub fn free_blocks(&self) -> Vec<Network> {
+        let used: Vec<Network> = self.delegations.values().map(|delegation| delegation.network.clone()).collect();
+        self.pool.exclude_all(&used).unwrap_or_default()
+    }
+
+    // Number of prefixes of the given length that could still be delegated
+    pub fn available(&self, prefix_len: u8) -> u128 {
+        self.free_blocks().iter()
+            .filter(|block| block.prefix_len() <= prefix_len)
+            .map(|block| 1_u128.checked_shl((prefix_len - block.prefix_len()).into()).unwrap_or(u128::MAX))
+            .fold(0, u128::saturating_add)
+    }
+
+    fn check_free(&self, network: &Network) -> Result<(), PoolError> {
+        if !self.pool.contains_network(network).map_err(PoolError::Network)? {
+            return Err(PoolError::OutsidePool(network.clone()));
+        }
+        if let Some((_, delegation)) = self.delegations.range(..=network.last_bits()).next_back() {
+            if delegation.network.last_bits() >= network.first_bits() {
+                return Err(PoolError::Overlap(delegation.network.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, network: Network, kind: Kind, owner: &str) {
+        self.delegations.insert(network.first_bits(), Delegation { network, kind, owner: owner.to_string() });
+    }
+
+    // Walks the gaps between delegations, O(delegations)
+    fn first_free(&self, prefix_len: u8) -> Option<Network> {
+        let host_bits = self.pool.version().address_bits() - prefix_len;
+        let size_mask = if host_bits == 128 { u128::MAX } else { (1_u128 << host_bits) - 1 };
+        let mut gap_start = Some(self.pool.first_bits());
+        let gap_ends = self.delegations.values()
+            .map(|delegation| (delegation.network.first_bits().checked_sub(1), delegation.network.last_bits().checked_add(1)))
+            .chain([(Some(self.pool.last_bits()), None)]);
+        for (gap_end, next_start) in gap_ends {
+            if let (Some(start), Some(end)) = (gap_start, gap_end) {
+                // Round the gap start up to the block size
+                if let Some(aligned) = start.checked_add(size_mask).map(|start| start & !size_mask) {
+                    if aligned <= end && end - aligned >= size_mask {
+                        return Some(Network::from_bits(self.pool.version(), aligned, prefix_len));
+                    }
+                }
+            }
+            gap_start = next_start;
+        }
+        None
+    }
+
+    // First free candidate in bit-reversed index order. Inside a free block
+    // that is always the block's start, the only index of the block aligned
+    // to its size, so comparing the starts of the free blocks big enough is
+    // enough and a full pool gives None in O(delegations).
+    fn bisection_free(&self, prefix_len: u8) -> Option<Network> {
+        let index_bits = prefix_len - self.pool.prefix_len();
+        let host_bits = self.pool.version().address_bits() - prefix_len;
+        let order = |block: &Network| {
+            let index = (block.first_bits() - self.pool.first_bits()).checked_shr(host_bits.into()).unwrap_or(0);
+            if index_bits == 0 { 0 } else { index.reverse_bits() >> (128 - index_bits) }
+        };
+        self.free_blocks().into_iter()
+            .filter(|block| block.prefix_len() <= prefix_len)
+            .min_by_key(order)
+            .map(|block| Network::from_bits(self.pool.version(), block.first_bits(), prefix_len))
+    }
+
+    pub fn to_snapshot(&self) -> String {
+        let mut snapshot = format!("{}\npool {}\nstrategy {:?}\n", SNAPSHOT_HEADER, self.pool, self.strategy);
+        for delegation in self.delegations.values() {
+            snapshot += &format!("{:?} {} {}\n", delegation.kind, delegation.network, escape(&delegation.owner));
+        }
+        snapshot
+    }
+
+    pub fn from_snapshot(snapshot: &str) -> Result<DelegationPool, PoolError> {
+        let wrong = |line: &str| PoolError::Snapshot(format!("Unexpected line '{}'", line));
+        let mut lines = snapshot.lines();
+        if lines.next() != Some(SNAPSHOT_HEADER) {
+            return Err(PoolError::Snapshot(String::from("Not a delegation pool snapshot")));
+        }
+        let pool_line = lines.next().unwrap_or_default();
+        let pool = pool_line.strip_prefix("pool ").ok_or_else(|| wrong(pool_line))?;
+        let pool = Network::from_string(pool).map_err(PoolError::Network)?;
+        let strategy = match lines.next() {
+            Some("strategy Sequential") => Strategy::Sequential,
+            Some("strategy Bisection") => Strategy::Bisection,
+            other => return Err(wrong(other.unwrap_or_default())),
+        };
+        let mut pool = DelegationPool::new(pool, strategy);
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            let mut parts = line.splitn(3, ' ');
+            let kind = parts.next().unwrap_or_default();
+            let network = Network::from_string(parts.next().ok_or_else(|| wrong(line))?).map_err(PoolError::Network)?;
+            let owner = unescape(parts.next().unwrap_or_default()).ok_or_else(|| wrong(line))?;
+            let owner = owner.as_str();
+            match kind {
+                "Delegated" => pool.assign(&network, owner)?,
+                "Reserved" => pool.reserve(&network, owner)?,
+                _ => return Err(wrong(line)),
+            }
+        }
+        Ok(pool)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), PoolError> {
+        fs::write(path, self.to_snapshot()).map_err(PoolError::Io)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<DelegationPool, PoolError> {
+        DelegationPool::from_snapshot(&fs::read_to_string(path).map_err(PoolError::Io)?)
+    }
+}
+
+// Owners are free text, one per line in the snapshot, so line breaks and
+// the escape character itself are written as \n, \r and \\
+fn escape(owner: &str) -> String {
+    owner.replace('\\', "\\\\").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+fn unescape(owner: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(owner.len());
+    let mut chars = owner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '\\' => unescaped.push('\\'),
+            'n' => unescaped.push('\n'),
+            'r' => unescaped.push('\r'),
+            _ => return None,
+        }
+    }
+    Some(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_helpers::network;
+
+    fn pool(strategy: Strategy) -> DelegationPool {
+        DelegationPool::new(network("2001:0db8:0000:0000:0000:0000:0000:0000/40"), strategy)
+    }
+
+    #[test]
+    fn sequential_allocation() {
+        let mut pool = pool(Strategy::Sequential);
+        assert_eq!(pool.allocate(56, "alice").unwrap(), network("2001:0db8:0000:0000:0000:0000:0000:0000/56"));
+        assert_eq!(pool.allocate(48, "bob").unwrap(), network("2001:0db8:0001:0000:0000:0000:0000:0000/48"));
+        assert_eq!(pool.allocate(56, "carol").unwrap(), network("2001:0db8:0000:0100:0000:0000:0000:0000/56"));
+        assert_eq!(pool.allocate(64, "dave").unwrap(), network("2001:0db8:0000:0200:0000:0000:0000:0000/64"));
+        assert!(matches!(pool.allocate(32, "eve"), Err(PoolError::WrongLength(_))));
+        assert_eq!(pool.available(48), 256 - 2);
+    }
+
+    #[test]
+    fn bisection_allocation() {
+        let mut pool = pool(Strategy::Bisection);
+        let allocated: Vec<String> = (0..4).map(|i| pool.allocate(48, &format!("customer {}", i)).unwrap().to_string()).collect();
+        assert_eq!(allocated, vec![
+            "2001:0db8:0000:0000:0000:0000:0000:0000/48",
+            "2001:0db8:0080:0000:0000:0000:0000:0000/48",
+            "2001:0db8:0040:0000:0000:0000:0000:0000/48",
+            "2001:0db8:00c0:0000:0000:0000:0000:0000/48",
+        ]);
+        let grown = pool.resize(&network(&allocated[1]), 42).unwrap();
+        assert_eq!(grown, network("2001:0db8:0080:0000:0000:0000:0000:0000/42"));
+        assert_eq!(pool.get(&grown).unwrap().owner, "customer 1");
+        assert!(matches!(pool.resize(&network(&allocated[0]), 40), Err(PoolError::Overlap(_))));
+        assert_eq!(pool.get(&network(&allocated[0])).unwrap().owner, "customer 0");
+    }
+
+    #[test]
+    fn bisection_full_pool() {
+        let mut pool = pool(Strategy::Bisection);
+        let last = network("2001:0db8:00ff:ffff:ffff:ffff:ffff:ffff/128");
+        for block in pool.pool().exclude(&last).unwrap() {
+            pool.reserve(&block, "core").unwrap();
+        }
+        assert!(matches!(pool.allocate(64, "alice"), Err(PoolError::Exhausted(_))));
+        assert_eq!(pool.allocate(128, "alice").unwrap(), last);
+        assert!(matches!(pool.allocate(128, "bob"), Err(PoolError::Exhausted(_))));
+        pool.reclaim("core");
+        pool.reserve(&network("2001:0db8:0020:0000:0000:0000:0000:0000/43"), "core").unwrap();
+        let allocated: Vec<String> = (0..3).map(|i| pool.allocate(43, &format!("customer {}", i)).unwrap().to_string()).collect();
+        assert_eq!(allocated, vec![
+            "2001:0db8:0000:0000:0000:0000:0000:0000/43",
+            "2001:0db8:0080:0000:0000:0000:0000:0000/43",
+            "2001:0db8:0040:0000:0000:0000:0000:0000/43",
+        ]);
+        assert_eq!(pool.allocate(43, "customer 3").unwrap(), network("2001:0db8:00c0:0000:0000:0000:0000:0000/43"));
+        assert_eq!(pool.allocate(43, "customer 4").unwrap(), network("2001:0db8:00a0:0000:0000:0000:0000:0000/43"));
+    }
+
+    #[test]
+    fn reserve_release_reclaim() {
+        let mut pool = pool(Strategy::Sequential);
+        pool.reserve(&network("2001:0db8:0000:0000:0000:0000:0000:0000/44"), "core").unwrap();
+        assert!(matches!(pool.reserve(&network("2001:0db8:0001:0000:0000:0000:0000:0000/48"), "x"), Err(PoolError::Overlap(_))));
+        assert!(matches!(pool.reserve(&network("2001:0db9:0000:0000:0000:0000:0000:0000/48"), "x"), Err(PoolError::OutsidePool(_))));
+        let first = pool.allocate(48, "alice").unwrap();
+        assert_eq!(first, network("2001:0db8:0010:0000:0000:0000:0000:0000/48"));
+        pool.allocate(56, "alice").unwrap();
+        pool.allocate(56, "bob").unwrap();
+        assert_eq!(pool.find(&network("2001:0db8:0010:0001:0000:0000:0000:0000/64")).unwrap().owner, "alice");
+        assert_eq!(pool.owned_by("alice").count(), 2);
+        assert_eq!(pool.reclaim("alice").len(), 2);
+        assert!(matches!(pool.release(&first), Err(PoolError::NotFound(_))));
+        assert_eq!(pool.delegations().count(), 2);
+        assert_eq!(pool.allocate(48, "carol").unwrap(), first);
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let mut pool = pool(Strategy::Bisection);
+        pool.reserve(&network("2001:0db8:0000:0000:0000:0000:0000:0000/48"), "core routers").unwrap();
+        pool.allocate(56, "alice").unwrap();
+        pool.allocate(64, "bob").unwrap();
+        let path = std::env::temp_dir().join(format!("ip-network-delegation-{}.txt", std::process::id()));
+        pool.save(&path).unwrap();
+        let loaded = DelegationPool::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.to_snapshot(), pool.to_snapshot());
+        assert_eq!(loaded.delegations().cloned().collect::<Vec<Delegation>>(), pool.delegations().cloned().collect::<Vec<Delegation>>());
+        assert!(matches!(DelegationPool::from_snapshot("pool 10.0.0.0/8"), Err(PoolError::Snapshot(_))));
+    }
+
+    #[test]
+    fn snapshot_escapes_owners() {
+        let mut pool = pool(Strategy::Sequential);
+        pool.allocate(48, "alice\nReserved 2001:0db8:0001:0000:0000:0000:0000:0000/48 mallory").unwrap();
+        pool.allocate(48, "C:\\customers\\bob\r").unwrap();
+        let snapshot = pool.to_snapshot();
+        assert_eq!(snapshot.lines().count(), 5);
+        let loaded = DelegationPool::from_snapshot(&snapshot).unwrap();
+        assert_eq!(loaded.delegations().cloned().collect::<Vec<Delegation>>(), pool.delegations().cloned().collect::<Vec<Delegation>>());
+        let broken = snapshot.replace("\\\\customers", "\\customers");
+        assert!(matches!(DelegationPool::from_snapshot(&broken), Err(PoolError::Snapshot(_))));
+    }
+}
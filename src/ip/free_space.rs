@@ -0,0 +1,271 @@
+use std::borrow::Borrow;
+
+use super::address::Address;
+use super::aggregate::merge_ranges;
+use super::{Network, NetworkError};
+
+// Free space of a parent network around a list of used subnets and addresses.
+// Used entries may overlap each other or stick out of the parent, only the
+// part inside the parent counts.
+#[derive(Debug, Clone)]
+pub struct FreeSpace {
+    parent: Network,
+    used: Vec<Network>,
+    // Maximal free CIDR blocks, sorted by address
+    free: Vec<Network>,
+}
+
+// Per prefix length: what is used and what could still be carved out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrefixUsage {
+    pub prefix_len: u8,
+    // Used entries with this prefix length and their addresses
+    pub used: usize,
+    pub used_addresses: u128,
+    // Maximal free blocks of exactly this length
+    pub free_blocks: usize,
+    // Aligned blocks of this length that fit into the free space
+    pub available: u128,
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub parent: Network,
+    pub total: u128,
+    pub used: u128,
+    pub free: u128,
+    pub free_blocks: Vec<Network>,
+    pub by_prefix: Vec<PrefixUsage>,
+    // Free blocks longer than this prefix count as fragments
+    pub fragment_prefix_len: u8,
+    // Share of the free space sitting in fragments, 0 to 1
+    pub fragmentation: f64,
+}
+
+impl FreeSpace {
+    pub fn new<I>(parent: &Network, used: I) -> Result<FreeSpace, NetworkError>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Network>,
+    {
+        let mut space = FreeSpace { parent: parent.clone(), used: Vec::new(), free: Vec::new() };
+        for network in used {
+            let network = network.borrow();
+            if network.version() != parent.version() {
+                return Err(NetworkError::VersionMismatch);
+            }
+            space.used.push(network.clone());
+        }
+        space.free = space.parent.exclude_all(&space.used)?;
+        Ok(space)
+    }
+
+    pub fn add(&mut self, network: &Network) -> Result<(), NetworkError> {
+        if network.version() != self.parent.version() {
+            return Err(NetworkError::VersionMismatch);
+        }
+        self.used.push(network.clone());
+        self.free = self.parent.exclude_all(&self.used)?;
+        Ok(())
+    }
+
+    pub fn add_address(&mut self, address: &dyn Address) -> Result<(), NetworkError> {
+        self.add(&Network::host(address))
+    }
+
+    pub fn parent(&self) -> &Network {
+        &self.parent
+    }
+
+    pub fn free_blocks(&self) -> &[Network] {
+        &self.free
+    }
+
+    // Lowest free aligned block of the given length. Every aligned block in
+    // the free space lies inside one of the maximal free blocks.
+    pub fn first_free(&self, prefix_len: u8) -> Option<Network> {
+        self.free.iter()
+            .find(|block| block.prefix_len() <= prefix_len)
+            .and_then(|block| block.subnet(prefix_len, 0).ok())
+    }
+
+    // Block of the given length taken from the smallest free block it fits in,
+    // so large free blocks stay whole
+    pub fn best_free(&self, prefix_len: u8) -> Option<Network> {
+        self.free.iter()
+            .filter(|block| block.prefix_len() <= prefix_len)
+            .max_by_key(|block| (block.prefix_len(), std::cmp::Reverse(block.first_bits())))
+            .and_then(|block| block.subnet(prefix_len, 0).ok())
+    }
+
+    // Addresses of the parent covered by used entries
+    pub fn used_addresses(&self) -> u128 {
+        let mut ranges: Vec<_> = self.used.iter()
+            .filter(|network| network.overlaps(&self.parent) == Ok(true))
+            .map(|network| {
+                let first = network.first_bits().max(self.parent.first_bits());
+                let last = network.last_bits().min(self.parent.last_bits());
+                (network.version(), first, last)
+            })
+            .collect();
+        ranges.sort_unstable();
+        merge_ranges(ranges).iter().fold(0, |total, (_, first, last)| total.saturating_add((last - first).saturating_add(1)))
+    }
+
+    // Number of aligned /prefix_len blocks that fit into the free space
+    pub fn available(&self, prefix_len: u8) -> u128 {
+        self.free.iter()
+            .filter(|block| block.prefix_len() <= prefix_len)
+            .map(|block| 1_u128.checked_shl((prefix_len - block.prefix_len()).into()).unwrap_or(u128::MAX))
+            .fold(0, u128::saturating_add)
+    }
+
+    // Rows for every prefix length that is used or free, plus the lengths
+    // between the parent and the longest of them
+    pub fn by_prefix(&self) -> Vec<PrefixUsage> {
+        let longest = self.used.iter().chain(&self.free).map(Network::prefix_len).max().unwrap_or(self.parent.prefix_len());
+        let mut rows: Vec<PrefixUsage> = (self.parent.prefix_len()..=longest)
+            .map(|prefix_len| PrefixUsage { prefix_len, used: 0, used_addresses: 0, free_blocks: 0, available: self.available(prefix_len) })
+            .collect();
+        // Rows are indexed by the prefix length below the parent's
+        let base = self.parent.prefix_len();
+        for network in self.used.iter().filter(|network| network.overlaps(&self.parent) == Ok(true)) {
+            if let Some(usage) = rows.get_mut(network.prefix_len().saturating_sub(base) as usize) {
+                if usage.prefix_len == network.prefix_len() {
+                    usage.used += 1;
+                    usage.used_addresses = usage.used_addresses.saturating_add(network.total_addresses());
+                }
+            }
+        }
+        for block in &self.free {
+            rows[(block.prefix_len() - base) as usize].free_blocks += 1;
+        }
+        rows
+    }
+
+    // Share of the free space in blocks smaller than /fragment_prefix_len,
+    // i.e. space that can't hold a block of that size any more
+    pub fn fragmentation(&self, fragment_prefix_len: u8) -> f64 {
+        let free: f64 = self.free.iter().map(|block| block.total_addresses() as f64).sum();
+        if free == 0.0 {
+            return 0.0;
+        }
+        let fragments: f64 = self.free.iter()
+            .filter(|block| block.prefix_len() > fragment_prefix_len)
+            .map(|block| block.total_addresses() as f64)
+            .sum();
+        fragments / free
+    }
+
+    pub fn report(&self, fragment_prefix_len: u8) -> Report {
+        let total = self.parent.total_addresses();
+        let used = self.used_addresses();
+        Report {
+            parent: self.parent.clone(),
+            total,
+            used,
+            free: total - used,
+            free_blocks: self.free.clone(),
+            by_prefix: self.by_prefix(),
+            fragment_prefix_len,
+            fragmentation: self.fragmentation(fragment_prefix_len),
+        }
+    }
+}
+
+impl Report {
+    // Share of the parent that is used, in percent
+    pub fn utilisation(&self) -> f64 {
+        self.used as f64 / self.total as f64 * 100.0
+    }
+
+    pub fn to_table(&self) -> String {
+        let mut table = format!(
+            "Network:       {}\nUsed:          {} of {} ({:.2}%)\nFree:          {} in {} blocks\nFragmentation: {:.2}% of free space in blocks smaller than /{}\n\n",
+            self.parent, self.used, self.total, self.utilisation(), self.free, self.free_blocks.len(),
+            self.fragmentation * 100.0, self.fragment_prefix_len
+        );
+        let header = ["Prefix", "Used", "Used addresses", "Free blocks", "Available"];
+        let rows: Vec<[String; 5]> = self.by_prefix.iter().map(|usage| [
+            format!("/{}", usage.prefix_len),
+            usage.used.to_string(),
+            usage.used_addresses.to_string(),
+            usage.free_blocks.to_string(),
+            usage.available.to_string(),
+        ]).collect();
+        let widths: Vec<usize> = (0..header.len())
+            .map(|column| rows.iter().map(|row| row[column].len()).chain([header[column].len()]).max().unwrap_or(0))
+            .collect();
+        let line = |cells: Vec<&str>| {
+            let cells: Vec<String> = cells.iter().zip(&widths).map(|(cell, width)| format!("{:>width$}", cell, width = width)).collect();
+            cells.join("  ") + "\n"
+        };
+        table += &line(header.to_vec());
+        for row in &rows {
+            table += &line(row.iter().map(String::as_str).collect());
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::address::AddressV4;
+    use super::super::test_helpers::network;
+
+    fn space() -> FreeSpace {
+        let used = [network("10.0.0.0/26"), network("10.0.0.128/25"), network("10.0.0.100/30")];
+        FreeSpace::new(&network("10.0.0.0/24"), used).unwrap()
+    }
+
+    #[test]
+    fn free_space_blocks() {
+        let mut space = space();
+        let free: Vec<String> = space.free_blocks().iter().map(Network::to_string).collect();
+        assert_eq!(free, vec!["10.0.0.64/27", "10.0.0.96/30", "10.0.0.104/29", "10.0.0.112/28"]);
+        assert_eq!(space.first_free(29), Some(network("10.0.0.64/29")));
+        assert_eq!(space.best_free(29), Some(network("10.0.0.104/29")));
+        assert_eq!(space.best_free(30), Some(network("10.0.0.96/30")));
+        assert_eq!(space.first_free(26), None);
+        assert_eq!(space.available(30), 8 + 1 + 2 + 4);
+        space.add_address(&AddressV4::from_string("10.0.0.96").unwrap()).unwrap();
+        assert_eq!(space.best_free(30), Some(network("10.0.0.104/30")));
+        assert!(FreeSpace::new(&network("10.0.0.0/24"), [Network::from_bits(super::super::Version::IpV6, 0, 64)]).is_err());
+    }
+
+    #[test]
+    fn free_space_report() {
+        let report = space().report(28);
+        assert_eq!((report.total, report.used, report.free), (256, 196, 60));
+        assert!((report.fragmentation - 12.0 / 60.0).abs() < 1e-9);
+        let rows: Vec<(u8, usize, usize, u128)> = report.by_prefix.iter()
+            .map(|usage| (usage.prefix_len, usage.used, usage.free_blocks, usage.available))
+            .collect();
+        assert_eq!(rows, vec![
+            (24, 0, 0, 0),
+            (25, 1, 0, 0),
+            (26, 1, 0, 0),
+            (27, 0, 1, 1),
+            (28, 0, 1, 3),
+            (29, 0, 1, 7),
+            (30, 1, 1, 15),
+        ]);
+        assert_eq!(report.to_table(), [
+            "Network:       10.0.0.0/24",
+            "Used:          196 of 256 (76.56%)",
+            "Free:          60 in 4 blocks",
+            "Fragmentation: 20.00% of free space in blocks smaller than /28",
+            "",
+            "Prefix  Used  Used addresses  Free blocks  Available",
+            "   /24     0               0            0          0",
+            "   /25     1             128            0          0",
+            "   /26     1              64            0          0",
+            "   /27     0               0            1          1",
+            "   /28     0               0            1          3",
+            "   /29     0               0            1          7",
+            "   /30     1               4            1         15",
+            "",
+        ].join("\n"));
+    }
+}
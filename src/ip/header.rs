@@ -1,5 +1,3 @@
-use num_traits::FromPrimitive;
-
 #[derive(Debug)]
 pub enum Header {
     V4(HeaderV4),
@@ -9,45 +7,232 @@ pub enum Header {
 #[derive(Debug)]
 pub enum HeaderError {
     EmptyPacker,
-    InvalidPacket
+    InvalidPacket,
+    ChecksumMismatch,
+    // An option with over 253 data bytes, or more options and padding than the 40
+    // bytes an IHL of 15 leaves room for
+    OptionsTooLong
+}
+
+// What `HeaderV4::encode_with` writes into the checksum field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumMode {
+    Compute,
+    // The field as it is, e.g. for deliberately broken packets
+    Keep
+}
+
+// What `HeaderV4::decode_with` does with a wrong checksum
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumPolicy {
+    Ignore,
+    Report,
+    Reject
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumStatus {
+    NotChecked,
+    Valid,
+    Mismatch { found: u16, expected: u16 }
 }
 
-pub fn get_version(bytes: &Vec<u8>) -> Result<super::Version, HeaderError> {
-    if bytes.len() == 0 {
+// RFC 791 header checksum: one's complement of the one's complement sum of
+// the header's 16-bit words, the checksum field itself taken as zero
+pub fn checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header.chunks(2)
+        .enumerate()
+        .filter(|(index, _)| *index != 5)
+        .map(|(_, word)| u32::from(u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)])))
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+pub fn get_version(bytes: &[u8]) -> Result<super::Version, HeaderError> {
+    if bytes.is_empty() {
         return Err(HeaderError::EmptyPacker);
     }
     if bytes[0] & 0xf0 == 0x40 {
-        return Ok(super::Version::IpV4);
+        Ok(super::Version::IpV4)
     }
     else {
-        return Ok(super::Version::IpV6);
+        Ok(super::Version::IpV6)
     }
 }
 
-pub fn decode(bytes: &Vec<u8>) -> Result<Header, HeaderError> {
-    if get_version(&bytes)? == super::Version::IpV4 {
-        Ok(Header::V4(HeaderV4::decode(&bytes)?))
+pub fn decode(bytes: &[u8]) -> Result<Header, HeaderError> {
+    if get_version(bytes)? == super::Version::IpV4 {
+        Ok(Header::V4(HeaderV4::decode(bytes)?))
     }
     else {
-        Ok(Header::V6(HeaderV6::decode(&bytes)?))
+        Ok(Header::V6(HeaderV6::decode(bytes)?))
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct HeaderV4 {
     pub header_length: u8,
     pub dscp: u8, // Differentiated Services Code Point
     pub ecn: u8, // Explicit Congestion Notification
     pub packet_length: u16,
     pub id: u16,
+    pub reserved: bool, // Reserved flag bit, the "evil bit" of RFC 3514
     pub dont_fragment: bool,
     pub has_fragments: bool,
     pub fragment_offset: u16,
     pub ttl: u8, // Time to live
-    pub protocol: super::TransportProtocolsNumbers,
+    pub protocol: super::Protocol,
     pub checksum: u16,
     pub src_ip: super::address::AddressV4,
-    pub dst_ip: super::address::AddressV4
+    pub dst_ip: super::address::AddressV4,
+    pub options: Vec<OptionV4>,
+    // Bytes after End of List, normally zeros, kept so decoding and
+    // encoding give back the exact header
+    pub padding: Vec<u8>
+}
+
+// IPv4 options, RFC 791 unless noted. Routes and timestamps with a length
+// that doesn't fit their layout decode as `Unknown`, so any option bytes
+// come back out unchanged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionV4 {
+    EndOfList,
+    NoOperation,
+    RecordRoute { pointer: u8, route: Vec<super::address::AddressV4> },
+    LooseSourceRoute { pointer: u8, route: Vec<super::address::AddressV4> },
+    StrictSourceRoute { pointer: u8, route: Vec<super::address::AddressV4> },
+    // Entries carry an address for flags 1 and 3
+    Timestamp { pointer: u8, overflow: u8, flag: u8, entries: Vec<(Option<super::address::AddressV4>, u32)> },
+    // RFC 2113, 0 asks routers to examine the packet
+    RouterAlert(u16),
+    // RFC 1108 classification level and protection authority flags
+    Security { classification: u8, authority: Vec<u8> },
+    Unknown { kind: u8, data: Vec<u8> }
+}
+
+impl OptionV4 {
+    pub const END_OF_LIST: u8 = 0;
+    pub const NO_OPERATION: u8 = 1;
+    pub const RECORD_ROUTE: u8 = 7;
+    pub const TIMESTAMP: u8 = 68;
+    pub const SECURITY: u8 = 130;
+    pub const LOOSE_SOURCE_ROUTE: u8 = 131;
+    pub const STRICT_SOURCE_ROUTE: u8 = 137;
+    pub const ROUTER_ALERT: u8 = 148;
+
+    pub fn kind(&self) -> u8 {
+        match self {
+            OptionV4::EndOfList => OptionV4::END_OF_LIST,
+            OptionV4::NoOperation => OptionV4::NO_OPERATION,
+            OptionV4::RecordRoute { .. } => OptionV4::RECORD_ROUTE,
+            OptionV4::LooseSourceRoute { .. } => OptionV4::LOOSE_SOURCE_ROUTE,
+            OptionV4::StrictSourceRoute { .. } => OptionV4::STRICT_SOURCE_ROUTE,
+            OptionV4::Timestamp { .. } => OptionV4::TIMESTAMP,
+            OptionV4::RouterAlert(_) => OptionV4::ROUTER_ALERT,
+            OptionV4::Security { .. } => OptionV4::SECURITY,
+            OptionV4::Unknown { kind, .. } => *kind
+        }
+    }
+
+    // The length byte counts kind and length too, so data stops at 253 bytes
+    pub fn encode(&self, bytes: &mut Vec<u8>) -> Result<(), HeaderError> {
+        let (kind, data) = match self {
+            OptionV4::EndOfList | OptionV4::NoOperation => {
+                bytes.push(self.kind());
+                return Ok(());
+            }
+            OptionV4::RecordRoute { pointer, route }
+            | OptionV4::LooseSourceRoute { pointer, route }
+            | OptionV4::StrictSourceRoute { pointer, route } => {
+                let mut data = vec![*pointer];
+                data.extend(route.iter().flat_map(|address| address.as_bytes()));
+                (self.kind(), data)
+            }
+            OptionV4::Timestamp { pointer, overflow, flag, entries } => {
+                let mut data = vec![*pointer, overflow << 4 | flag & 0x0f];
+                for (address, timestamp) in entries {
+                    if let Some(address) = address {
+                        data.extend(address.as_bytes());
+                    }
+                    data.extend(timestamp.to_be_bytes());
+                }
+                (self.kind(), data)
+            }
+            OptionV4::RouterAlert(value) => (self.kind(), value.to_be_bytes().to_vec()),
+            OptionV4::Security { classification, authority } => {
+                let mut data = vec![*classification];
+                data.extend(authority);
+                (self.kind(), data)
+            }
+            OptionV4::Unknown { kind, data } => (*kind, data.clone())
+        };
+        let length = u8::try_from(data.len() + 2).map_err(|_| HeaderError::OptionsTooLong)?;
+        bytes.push(kind);
+        bytes.push(length);
+        bytes.extend(data);
+        Ok(())
+    }
+
+    // Options up to and including End of List, and the padding after it
+    pub fn decode_all(bytes: &[u8]) -> Result<(Vec<OptionV4>, Vec<u8>), HeaderError> {
+        let mut options = Vec::new();
+        let mut rest = bytes;
+        while let Some(&kind) = rest.first() {
+            if kind == OptionV4::END_OF_LIST {
+                options.push(OptionV4::EndOfList);
+                return Ok((options, rest[1..].to_vec()));
+            }
+            if kind == OptionV4::NO_OPERATION {
+                options.push(OptionV4::NoOperation);
+                rest = &rest[1..];
+                continue;
+            }
+            let length = usize::from(*rest.get(1).ok_or(HeaderError::InvalidPacket)?);
+            if length < 2 || length > rest.len() {
+                return Err(HeaderError::InvalidPacket);
+            }
+            options.push(OptionV4::decode(kind, &rest[2..length]));
+            rest = &rest[length..];
+        }
+        Ok((options, Vec::new()))
+    }
+
+    fn decode(kind: u8, data: &[u8]) -> OptionV4 {
+        let addresses = |bytes: &[u8]| -> Vec<super::address::AddressV4> {
+            bytes.chunks(4).map(|chunk| super::address::AddressV4::from_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])).collect()
+        };
+        match kind {
+            OptionV4::RECORD_ROUTE | OptionV4::LOOSE_SOURCE_ROUTE | OptionV4::STRICT_SOURCE_ROUTE
+                if !data.is_empty() && (data.len() - 1).is_multiple_of(4) =>
+            {
+                let (pointer, route) = (data[0], addresses(&data[1..]));
+                match kind {
+                    OptionV4::RECORD_ROUTE => OptionV4::RecordRoute { pointer, route },
+                    OptionV4::LOOSE_SOURCE_ROUTE => OptionV4::LooseSourceRoute { pointer, route },
+                    _ => OptionV4::StrictSourceRoute { pointer, route }
+                }
+            }
+            OptionV4::TIMESTAMP if data.len() >= 2 => {
+                let flag = data[1] & 0x0f;
+                let entry_size = if flag == 0 { 4 } else { 8 };
+                if !(data.len() - 2).is_multiple_of(entry_size) {
+                    return OptionV4::Unknown { kind, data: data.to_vec() };
+                }
+                let entries = data[2..].chunks(entry_size).map(|entry| {
+                    let timestamp = u32::from_be_bytes([entry[entry_size - 4], entry[entry_size - 3], entry[entry_size - 2], entry[entry_size - 1]]);
+                    let address = (entry_size == 8).then(|| addresses(&entry[..4])[0]);
+                    (address, timestamp)
+                }).collect();
+                OptionV4::Timestamp { pointer: data[0], overflow: data[1] >> 4, flag, entries }
+            }
+            OptionV4::ROUTER_ALERT if data.len() == 2 => OptionV4::RouterAlert(u16::from_be_bytes([data[0], data[1]])),
+            OptionV4::SECURITY if !data.is_empty() => OptionV4::Security { classification: data[0], authority: data[1..].to_vec() },
+            _ => OptionV4::Unknown { kind, data: data.to_vec() }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -56,7 +241,8 @@ pub struct HeaderV6 {
     pub ecn: u8, // Explicit Congestion Notification
     pub flow_label: u32,
     pub payload_length: u16,
-    pub next_header: super::TransportProtocolsNumbers,
+    // Upper-layer protocol or the first extension header, e.g. 58 for ICMPv6
+    pub next_header: super::Protocol,
     pub hop_limit: u8,
     pub source_address: super::address::AddressV6,
     pub destination_address: super::address::AddressV6,
@@ -71,35 +257,74 @@ impl HeaderV4 {
             ecn: 0x0,
             packet_length: 0,
             id: 0x0000,
+            reserved: false,
             dont_fragment: false,
             has_fragments: false,
             fragment_offset: 0,
             ttl: 0,
-            protocol: super::TransportProtocolsNumbers::Tcp,
+            protocol: super::Protocol::Known(super::TransportProtocolsNumbers::Tcp),
             checksum: 0x0000,
             src_ip: super::address::AddressV4::from_u32(0),
-            dst_ip: super::address::AddressV4::from_u32(0)
+            dst_ip: super::address::AddressV4::from_u32(0),
+            options: Vec::new(),
+            padding: Vec::new()
         }
     }
-    pub fn encode(&self) -> Vec<u8> {
+
+    // Bytes of the encoded header: IHL words, or more when the options
+    // need them. Anything over 60 doesn't fit the IHL field.
+    pub fn header_size(&self) -> Result<usize, HeaderError> {
+        self.padded_size(self.encode_options()?.len())
+    }
+
+    fn encode_options(&self) -> Result<Vec<u8>, HeaderError> {
+        let mut bytes = Vec::new();
+        for option in &self.options {
+            option.encode(&mut bytes)?;
+        }
+        bytes.extend(&self.padding);
+        if bytes.len() > 40 {
+            return Err(HeaderError::OptionsTooLong);
+        }
+        Ok(bytes)
+    }
+
+    fn padded_size(&self, options_size: usize) -> Result<usize, HeaderError> {
+        let size = (20 + options_size).div_ceil(4).max(usize::from(self.header_length)) * 4;
+        // A header length over 15 words asks for option space that doesn't exist
+        if size > 60 {
+            return Err(HeaderError::OptionsTooLong);
+        }
+        Ok(size)
+    }
+
+    // Header bytes with a freshly computed checksum
+    pub fn encode(&self) -> Result<Vec<u8>, HeaderError> {
+        self.encode_with(ChecksumMode::Compute)
+    }
+
+    pub fn encode_with(&self, mode: ChecksumMode) -> Result<Vec<u8>, HeaderError> {
+        let options = self.encode_options()?;
+        let size = self.padded_size(options.len())?;
         let mut bytes = vec![0; 20];
-        bytes.resize(20, 0);
+        bytes.extend(options);
+        // Filling up to whole words with zeros, i.e. End of List
+        bytes.resize(size, 0);
         bytes[0] = 0x40;
-        bytes[0] |= 5;
-        bytes[1] = self.dscp << 2 & 0xf6;
-        bytes[1] = self.ecn & 0x03;
+        bytes[0] |= (size / 4) as u8 & 0x0f;
+        bytes[1] = self.dscp << 2 | self.ecn & 0x03;
         let length_bytes = self.packet_length.to_be_bytes();
         bytes[2] = length_bytes[0];
         bytes[3] = length_bytes[1];
         let id_bytes = self.id.to_be_bytes();
         bytes[4] = id_bytes[0];
         bytes[5] = id_bytes[1];
-        bytes[6] |= if self.dont_fragment {0x40} else {0x0} | if self.has_fragments {0x20} else {0x0};
+        bytes[6] |= if self.reserved {0x80} else {0x0} | if self.dont_fragment {0x40} else {0x0} | if self.has_fragments {0x20} else {0x0};
         let offset_bytes = self.fragment_offset.to_be_bytes();
         bytes[6] |= offset_bytes[0]& 0x1f;
         bytes[7] = offset_bytes[1];
         bytes[8] = self.ttl;
-        bytes[9] = self.protocol as u8;
+        bytes[9] = self.protocol.number();
         let checksum_bytes = self.checksum.to_be_bytes();
         bytes[10] = checksum_bytes[0];
         bytes[11] = checksum_bytes[1];
@@ -113,39 +338,81 @@ impl HeaderV4 {
         bytes[17] = dst_ip_bytes[1];
         bytes[18] = dst_ip_bytes[2];
         bytes[19] = dst_ip_bytes[3];
-        return bytes;
+        if mode == ChecksumMode::Compute {
+            let checksum_bytes = checksum(&bytes).to_be_bytes();
+            bytes[10] = checksum_bytes[0];
+            bytes[11] = checksum_bytes[1];
+        }
+        Ok(bytes)
+    }
+
+    // Checksum the header should carry
+    pub fn compute_checksum(&self) -> Result<u16, HeaderError> {
+        Ok(checksum(&self.encode_with(ChecksumMode::Keep)?))
     }
-    
-    pub fn decode(bytes: &Vec<u8>) -> Result<HeaderV4, HeaderError> {
-        if bytes.len() != 20 {
+
+    // A header that can't be encoded has no valid checksum either
+    pub fn verify_checksum(&self) -> bool {
+        self.compute_checksum().is_ok_and(|checksum| checksum == self.checksum)
+    }
+
+    // Decodes without looking at the checksum
+    pub fn decode(bytes: &[u8]) -> Result<HeaderV4, HeaderError> {
+        Ok(HeaderV4::decode_with(bytes, ChecksumPolicy::Ignore)?.0)
+    }
+
+    // Checks the checksum against the received header bytes, so it holds
+    // even for fields the header doesn't keep
+    pub fn decode_with(bytes: &[u8], policy: ChecksumPolicy) -> Result<(HeaderV4, ChecksumStatus), HeaderError> {
+        let header = HeaderV4::decode_fields(bytes)?;
+        if policy == ChecksumPolicy::Ignore {
+            return Ok((header, ChecksumStatus::NotChecked));
+        }
+        let expected = checksum(&bytes[..usize::from(header.header_length) * 4]);
+        if header.checksum == expected {
+            return Ok((header, ChecksumStatus::Valid));
+        }
+        if policy == ChecksumPolicy::Reject {
+            return Err(HeaderError::ChecksumMismatch);
+        }
+        let found = header.checksum;
+        Ok((header, ChecksumStatus::Mismatch { found, expected }))
+    }
+
+    // Bytes past the header length are payload and left alone
+    fn decode_fields(bytes: &[u8]) -> Result<HeaderV4, HeaderError> {
+        let header_size = usize::from(bytes.first().ok_or(HeaderError::InvalidPacket)? & 0x0f) * 4;
+        if header_size < 20 || bytes.len() < header_size {
             return Err(HeaderError::InvalidPacket);
         }
         let mut header = HeaderV4::empty();
         header.header_length = bytes[0] & 0x0f;
+        (header.options, header.padding) = OptionV4::decode_all(&bytes[20..header_size])?;
         header.dscp = bytes[1] >> 2;
         header.ecn = bytes[1] & 0x03;
         header.packet_length = u16::from_be_bytes([bytes[2], bytes[3]]);
         header.id = u16::from_be_bytes([bytes[4], bytes[5]]);
+        header.reserved = bytes[6] & 0x80 == 0x80;
         header.dont_fragment = bytes[6] & 0x40 == 0x40;
         header.has_fragments = bytes[6] & 0x20 == 0x20;
         header.fragment_offset = u16::from_be_bytes([bytes[6] & 0x1f, bytes[7]]);
         header.ttl = bytes[8];
-        header.protocol = FromPrimitive::from_u8(bytes[9]).unwrap();
+        header.protocol = super::Protocol::from_number(bytes[9]);
         header.checksum = u16::from_be_bytes([bytes[10], bytes[11]]);
         header.src_ip = super::address::AddressV4::from_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]);
         header.dst_ip = super::address::AddressV4::from_bytes([bytes[16], bytes[17], bytes[18], bytes[19]]);
-        return Ok(header);
+        Ok(header)
     }
 }
 
 impl HeaderV6 {
     pub fn empty() -> HeaderV6 {
-        return HeaderV6 {
+        HeaderV6 {
             dscp: 0,
             ecn: 0,
             flow_label: 0,
             payload_length: 0,
-            next_header: super::TransportProtocolsNumbers::Tcp,
+            next_header: super::Protocol::Known(super::TransportProtocolsNumbers::Tcp),
             hop_limit: 0,
             source_address: super::address::AddressV6::from_u128(0),
             destination_address: super::address::AddressV6::from_u128(0)
@@ -162,16 +429,16 @@ impl HeaderV6 {
         let payload_length = self.payload_length.to_be_bytes();
         bytes.push(payload_length[0]);
         bytes.push(payload_length[1]);
-        bytes.push(self.next_header as u8);
+        bytes.push(self.next_header.number());
         bytes.push(self.hop_limit);
         let src_bytes = self.source_address.as_bytes();
         bytes.append(&mut src_bytes.to_vec());
         let dst_bytes = self.destination_address.as_bytes();
         bytes.append(&mut dst_bytes.to_vec());
-        return bytes;
+        bytes
     }
 
-    pub fn decode(bytes: &Vec<u8>) -> Result<HeaderV6, HeaderError> {
+    pub fn decode(bytes: &[u8]) -> Result<HeaderV6, HeaderError> {
         if bytes.len() != 40 {
             return Err(HeaderError::InvalidPacket);
         }
@@ -180,13 +447,354 @@ impl HeaderV6 {
         header.ecn = bytes[1] >> 4 & 0x03;
         header.flow_label = u32::from_be_bytes([0, bytes[1] & 0x0f, bytes[2], bytes[3]]);
         header.payload_length = u16::from_be_bytes([bytes[4], bytes[5]]);
-        header.next_header = FromPrimitive::from_u8(bytes[6]).unwrap() ;
+        header.next_header = super::Protocol::from_number(bytes[6]);
         header.hop_limit = bytes[7];
         let mut arr = [0u8; 16];
         arr.copy_from_slice(&bytes[8..24]);
         header.source_address.set_u128(u128::from_be_bytes(arr));
         arr.copy_from_slice(&bytes[24..]);
         header.destination_address.set_u128(u128::from_be_bytes(arr));
-        return Ok(header)
+        Ok(header)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::address::AddressV4;
+    use super::super::Protocol;
+
+    // UDP header from 192.168.0.1 to 192.168.0.199, checksum 0xb861
+    const SAMPLE: [u8; 20] = [
+        0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11,
+        0xb8, 0x61, 0xc0, 0xa8, 0x00, 0x01, 0xc0, 0xa8, 0x00, 0xc7,
+    ];
+
+    fn sample() -> HeaderV4 {
+        let mut header = HeaderV4::empty();
+        header.packet_length = 0x73;
+        header.dont_fragment = true;
+        header.ttl = 64;
+        header.protocol = super::super::TransportProtocolsNumbers::Udp.into();
+        header.src_ip = AddressV4::from_string("192.168.0.1").unwrap();
+        header.dst_ip = AddressV4::from_string("192.168.0.199").unwrap();
+        header
+    }
+
+    #[test]
+    fn header_v4_checksum_on_encode() {
+        assert_eq!(checksum(&SAMPLE), 0xb861);
+        let header = sample();
+        assert!(!header.verify_checksum());
+        assert_eq!(header.encode().unwrap(), SAMPLE);
+        assert_eq!(header.compute_checksum().unwrap(), 0xb861);
+        // Keeping the field writes the zero checksum as is
+        assert_eq!(&header.encode_with(ChecksumMode::Keep).unwrap()[10..12], &[0, 0]);
+        let decoded = HeaderV4::decode(&header.encode().unwrap()).unwrap();
+        assert_eq!(decoded.checksum, 0xb861);
+        assert!(decoded.verify_checksum());
+    }
+
+    #[test]
+    fn header_v4_checksum_policy() {
+        let (_, status) = HeaderV4::decode_with(&SAMPLE, ChecksumPolicy::Reject).unwrap();
+        assert_eq!(status, ChecksumStatus::Valid);
+        let mut broken = SAMPLE;
+        broken[8] = 63;
+        let (header, status) = HeaderV4::decode_with(&broken, ChecksumPolicy::Ignore).unwrap();
+        assert_eq!((header.ttl, status), (63, ChecksumStatus::NotChecked));
+        let (_, status) = HeaderV4::decode_with(&broken, ChecksumPolicy::Report).unwrap();
+        assert_eq!(status, ChecksumStatus::Mismatch { found: 0xb861, expected: 0xb961 });
+        assert!(matches!(HeaderV4::decode_with(&broken, ChecksumPolicy::Reject), Err(HeaderError::ChecksumMismatch)));
+    }
+
+    // Header with options in front of a payload, checksum filled in
+    fn with_options(options: &[u8]) -> Vec<u8> {
+        let mut bytes = SAMPLE.to_vec();
+        bytes.extend(options);
+        bytes[0] = 0x40 | (bytes.len() / 4) as u8;
+        let checksum_bytes = checksum(&bytes).to_be_bytes();
+        bytes[10] = checksum_bytes[0];
+        bytes[11] = checksum_bytes[1];
+        bytes
+    }
+
+    #[test]
+    fn header_v4_options_round_trip() {
+        let raw = with_options(&[
+            0x01,
+            0x07, 0x07, 0x08, 192, 0, 2, 1,
+            0x83, 0x07, 0x04, 198, 51, 100, 1,
+            0x44, 0x0c, 0x0d, 0x11, 192, 0, 2, 1, 0, 0, 0x01, 0x00,
+            0x82, 0x04, 0xab, 0x01,
+            0x94, 0x04, 0x00, 0x00,
+            0x19, 0x04, 0xde, 0xad,
+            0x00,
+        ]);
+        let (header, status) = HeaderV4::decode_with(&raw, ChecksumPolicy::Reject).unwrap();
+        assert_eq!(status, ChecksumStatus::Valid);
+        assert_eq!(header.header_length, 15);
+        let address = |address: &str| AddressV4::from_string(address).unwrap();
+        assert_eq!(header.options, vec![
+            OptionV4::NoOperation,
+            OptionV4::RecordRoute { pointer: 8, route: vec![address("192.0.2.1")] },
+            OptionV4::LooseSourceRoute { pointer: 4, route: vec![address("198.51.100.1")] },
+            OptionV4::Timestamp { pointer: 13, overflow: 1, flag: 1, entries: vec![(Some(address("192.0.2.1")), 256)] },
+            OptionV4::Security { classification: 0xab, authority: vec![0x01] },
+            OptionV4::RouterAlert(0),
+            OptionV4::Unknown { kind: 0x19, data: vec![0xde, 0xad] },
+            OptionV4::EndOfList,
+        ]);
+        assert_eq!(header.encode().unwrap(), raw);
+        assert!(header.verify_checksum());
+        // A route that isn't whole addresses keeps its bytes
+        let raw = with_options(&[0x07, 0x04, 0x04, 0x00]);
+        let header = HeaderV4::decode(&raw).unwrap();
+        assert_eq!(header.options, vec![OptionV4::Unknown { kind: OptionV4::RECORD_ROUTE, data: vec![0x04, 0x00] }]);
+        assert_eq!(header.encode().unwrap(), raw);
+    }
+
+    #[test]
+    fn header_v4_options_padding() {
+        let mut header = sample();
+        header.options = vec![OptionV4::RouterAlert(0)];
+        let bytes = header.encode().unwrap();
+        assert_eq!((bytes.len(), bytes[0]), (24, 0x46));
+        assert_eq!(&bytes[20..], &[0x94, 0x04, 0x00, 0x00]);
+        header.options = vec![OptionV4::NoOperation, OptionV4::StrictSourceRoute { pointer: 4, route: vec![AddressV4::from_u32(1)] }];
+        let bytes = header.encode().unwrap();
+        assert_eq!(&bytes[20..], &[0x01, 0x89, 0x07, 0x04, 0, 0, 0, 1]);
+        // Trailing bytes are the payload
+        let mut packet = bytes.clone();
+        packet.extend([0xff; 8]);
+        let decoded = HeaderV4::decode_with(&packet, ChecksumPolicy::Reject).unwrap().0;
+        assert_eq!(decoded.options, header.options);
+        assert_eq!(decoded.encode().unwrap(), bytes);
+    }
+
+    #[test]
+    fn header_v4_options_invalid() {
+        // Option length runs past the header
+        assert!(matches!(HeaderV4::decode(&with_options(&[0x07, 0x08, 0x04, 0x00])), Err(HeaderError::InvalidPacket)));
+        assert!(matches!(HeaderV4::decode(&with_options(&[0x01, 0x01, 0x01, 0x44])), Err(HeaderError::InvalidPacket)));
+        let mut short = SAMPLE;
+        short[0] = 0x44;
+        assert!(matches!(HeaderV4::decode(&short), Err(HeaderError::InvalidPacket)));
+        short[0] = 0x46;
+        assert!(matches!(HeaderV4::decode(&short), Err(HeaderError::InvalidPacket)));
+        assert!(matches!(HeaderV4::decode(&SAMPLE[..19]), Err(HeaderError::InvalidPacket)));
+    }
+
+    #[test]
+    fn header_v4_options_too_long() {
+        let mut header = sample();
+        header.options = vec![OptionV4::Unknown { kind: 25, data: vec![0; 254] }];
+        assert!(matches!(header.encode(), Err(HeaderError::OptionsTooLong)));
+        let mut bytes = Vec::new();
+        OptionV4::Unknown { kind: 25, data: vec![0; 253] }.encode(&mut bytes).unwrap();
+        assert_eq!((bytes.len(), bytes[1]), (255, 255));
+        // 40 bytes of options fit, 41 don't
+        header.options = vec![OptionV4::Security { classification: 0, authority: vec![0; 37] }];
+        assert_eq!(header.header_size().unwrap(), 60);
+        header.options.push(OptionV4::NoOperation);
+        assert!(matches!(header.header_size(), Err(HeaderError::OptionsTooLong)));
+        assert!(matches!(header.compute_checksum(), Err(HeaderError::OptionsTooLong)));
+        assert!(!header.verify_checksum());
+        header.options.clear();
+        header.header_length = 16;
+        assert!(matches!(header.encode_with(ChecksumMode::Keep), Err(HeaderError::OptionsTooLong)));
+    }
+
+    // xorshift64*, enough to spread test inputs without a dependency
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+        }
+
+        fn below(&mut self, bound: u64) -> u64 {
+            self.next() % bound
+        }
+
+        fn byte(&mut self) -> u8 {
+            self.next() as u8
+        }
+
+        fn address(&mut self) -> AddressV4 {
+            AddressV4::from_u32(self.next() as u32)
+        }
+    }
+
+    fn random_option(random: &mut Random) -> OptionV4 {
+        let route = |random: &mut Random| (0..random.below(3)).map(|_| random.address()).collect();
+        match random.below(8) {
+            0 => OptionV4::NoOperation,
+            1 => OptionV4::RecordRoute { pointer: random.byte(), route: route(random) },
+            2 => OptionV4::LooseSourceRoute { pointer: random.byte(), route: route(random) },
+            3 => OptionV4::StrictSourceRoute { pointer: random.byte(), route: route(random) },
+            4 => {
+                let flag = [0, 1, 3][random.below(3) as usize];
+                let entries = (0..random.below(3))
+                    .map(|_| ((flag != 0).then(|| random.address()), random.next() as u32))
+                    .collect();
+                OptionV4::Timestamp { pointer: random.byte(), overflow: random.below(16) as u8, flag, entries }
+            }
+            5 => OptionV4::RouterAlert(random.next() as u16),
+            6 => OptionV4::Security { classification: random.byte(), authority: (0..random.below(3)).map(|_| random.byte()).collect() },
+            _ => OptionV4::Unknown {
+                kind: [25, 82, 94, 149][random.below(4) as usize],
+                data: (0..random.below(4)).map(|_| random.byte()).collect(),
+            },
+        }
+    }
+
+    fn random_header(random: &mut Random) -> HeaderV4 {
+        let mut header = HeaderV4::empty();
+        header.dscp = random.below(64) as u8;
+        header.ecn = random.below(4) as u8;
+        header.packet_length = random.next() as u16;
+        header.id = random.next() as u16;
+        header.reserved = random.below(2) == 1;
+        header.dont_fragment = random.below(2) == 1;
+        header.has_fragments = random.below(2) == 1;
+        header.fragment_offset = random.below(0x2000) as u16;
+        header.ttl = random.byte();
+        header.protocol = Protocol::from_number(random.byte());
+        header.src_ip = random.address();
+        header.dst_ip = random.address();
+        // Options take at most 40 bytes
+        for _ in 0..random.below(6) {
+            let option = random_option(random);
+            let mut bytes = Vec::new();
+            for option in header.options.iter().chain([&option]) {
+                option.encode(&mut bytes).unwrap();
+            }
+            if bytes.len() <= 40 {
+                header.options.push(option);
+            }
+        }
+        header
+    }
+
+    #[test]
+    fn header_v4_fields_round_trip() {
+        let mut random = Random(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..5000 {
+            let header = random_header(&mut random);
+            let bytes = header.encode().unwrap();
+            let (decoded, status) = HeaderV4::decode_with(&bytes, ChecksumPolicy::Reject).unwrap();
+            assert_eq!(status, ChecksumStatus::Valid);
+            // Filling comes back as End of List and zero padding, everything
+            // else as it was
+            let mut expected = header.clone();
+            expected.header_length = (bytes.len() / 4) as u8;
+            expected.checksum = header.compute_checksum().unwrap();
+            if decoded.options.len() > header.options.len() {
+                expected.options.push(OptionV4::EndOfList);
+                expected.padding = vec![0; bytes.len() - 20 - header.encode_options().unwrap().len() - 1];
+            }
+            assert_eq!(decoded, expected);
+            assert_eq!(decoded.encode().unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn header_v4_raw_bytes_round_trip() {
+        let mut random = Random(0x0123_4567_89ab_cdef);
+        let mut decoded = 0;
+        for round in 0..20000 {
+            let words = 5 + random.below(11) as usize;
+            let mut raw: Vec<u8> = (0..words * 4).map(|_| random.byte()).collect();
+            raw[0] = 0x40 | words as u8;
+            // Half the rounds get well-formed options, then End of List and
+            // random padding, so they always decode
+            if round % 2 == 0 {
+                let space = raw.len() - 20;
+                let mut options = Vec::new();
+                for _ in 0..8 {
+                    let mut option = Vec::new();
+                    random_option(&mut random).encode(&mut option).unwrap();
+                    if options.len() + option.len() <= space {
+                        options.extend(option);
+                    }
+                }
+                if options.len() < space {
+                    options.push(OptionV4::END_OF_LIST);
+                }
+                while options.len() < space {
+                    options.push(random.byte());
+                }
+                raw[20..].copy_from_slice(&options);
+            }
+            let Ok(header) = HeaderV4::decode(&raw) else {
+                continue;
+            };
+            decoded += 1;
+            assert_eq!(usize::from(header.header_length), words);
+            assert_eq!(header.encode_with(ChecksumMode::Keep).unwrap(), raw);
+            let checksum_bytes = checksum(&raw).to_be_bytes();
+            raw[10..12].copy_from_slice(&checksum_bytes);
+            assert_eq!(HeaderV4::decode(&raw).unwrap().encode().unwrap(), raw);
+        }
+        assert!(decoded >= 10000, "only {} random headers decoded", decoded);
+    }
+
+    #[test]
+    fn header_v4_padding_kept() {
+        // End of List, then garbage the sender left in the padding
+        let raw = with_options(&[0x94, 0x04, 0x00, 0x00, 0x00, 0xde, 0xad, 0xbe]);
+        let header = HeaderV4::decode(&raw).unwrap();
+        assert_eq!(header.options, vec![OptionV4::RouterAlert(0), OptionV4::EndOfList]);
+        assert_eq!(header.padding, vec![0xde, 0xad, 0xbe]);
+        assert_eq!(header.encode().unwrap(), raw);
+    }
+
+    #[test]
+    fn header_v4_all_qos_and_flags() {
+        let mut header = sample();
+        for dscp in 0..64 {
+            for ecn in 0..4 {
+                for flags in 0..8 {
+                    header.dscp = dscp;
+                    header.ecn = ecn;
+                    header.reserved = flags & 4 != 0;
+                    header.dont_fragment = flags & 2 != 0;
+                    header.has_fragments = flags & 1 != 0;
+                    let bytes = header.encode().unwrap();
+                    assert_eq!(bytes[1], dscp << 2 | ecn);
+                    assert_eq!(bytes[6] >> 5, flags);
+                    let decoded = HeaderV4::decode(&bytes).unwrap();
+                    assert_eq!((decoded.dscp, decoded.ecn), (dscp, ecn));
+                    assert_eq!((decoded.reserved, decoded.dont_fragment, decoded.has_fragments), (header.reserved, header.dont_fragment, header.has_fragments));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn header_v6_any_next_header() {
+        let mut header = HeaderV6::empty();
+        header.hop_limit = 64;
+        for next_header in [0, 6, 43, 44, 58, 59, 255] {
+            header.next_header = Protocol::from_number(next_header);
+            let decoded = HeaderV6::decode(&header.encode()).unwrap();
+            assert_eq!(decoded.next_header.number(), next_header);
+        }
+        header.next_header = Protocol::from_number(58);
+        assert_eq!(HeaderV6::decode(&header.encode()).unwrap().next_header, Protocol::Other(58));
+    }
+
+    #[test]
+    fn header_v4_unknown_protocol() {
+        let mut raw = SAMPLE;
+        raw[9] = 89;
+        let header = HeaderV4::decode(&raw).unwrap();
+        assert_eq!(header.protocol, Protocol::Other(89));
+        assert_eq!(header.encode_with(ChecksumMode::Keep).unwrap()[9], 89);
+        assert_eq!(HeaderV4::decode(&SAMPLE).unwrap().protocol, Protocol::Known(super::super::TransportProtocolsNumbers::Udp));
+    }
+}
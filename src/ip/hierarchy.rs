@@ -0,0 +1,186 @@
+use std::borrow::Borrow;
+use std::cmp::Reverse;
+
+use super::Network;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub network: Network,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub depth: usize,
+}
+
+// Nesting tree of a flat list of networks, each one under the tightest
+// network holding it. Nodes are stored in pre-order, so walking the tree
+// is walking the list, and are addressed by their index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hierarchy {
+    nodes: Vec<Node>,
+    roots: Vec<usize>,
+}
+
+impl Hierarchy {
+    // Equal networks are kept once. O(n log n), one sort and a stack sweep.
+    pub fn new<I>(networks: I) -> Hierarchy
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Network>,
+    {
+        let mut networks: Vec<Network> = networks.into_iter().map(|network| network.borrow().clone()).collect();
+        networks.sort_by_key(|network| (network.version(), network.first_bits(), Reverse(network.last_bits())));
+        networks.dedup();
+
+        let mut hierarchy = Hierarchy { nodes: Vec::with_capacity(networks.len()), roots: Vec::new() };
+        let mut stack: Vec<usize> = Vec::new();
+        for network in networks {
+            while let Some(&top) = stack.last() {
+                if hierarchy.nodes[top].network.contains_network(&network) == Ok(true) {
+                    break;
+                }
+                stack.pop();
+            }
+            let index = hierarchy.nodes.len();
+            match stack.last() {
+                Some(&parent) => hierarchy.nodes[parent].children.push(index),
+                None => hierarchy.roots.push(index),
+            }
+            hierarchy.nodes.push(Node { network, parent: stack.last().copied(), children: Vec::new(), depth: stack.len() });
+            stack.push(index);
+        }
+        hierarchy
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn node(&self, index: usize) -> Option<&Node> {
+        self.nodes.get(index)
+    }
+
+    pub fn roots(&self) -> impl Iterator<Item = &Node> {
+        self.roots.iter().map(|index| &self.nodes[*index])
+    }
+
+    pub fn children(&self, index: usize) -> impl Iterator<Item = &Node> {
+        self.nodes[index].children.iter().map(|child| &self.nodes[*child])
+    }
+
+    pub fn parent(&self, index: usize) -> Option<&Node> {
+        self.nodes[index].parent.map(|parent| &self.nodes[parent])
+    }
+
+    // Depth-first, parents before their children, siblings by address
+    pub fn walk(&self) -> impl Iterator<Item = &Node> {
+        self.nodes.iter()
+    }
+
+    // Index of the node holding exactly this network
+    pub fn find(&self, network: &Network) -> Option<usize> {
+        let key = (network.version(), network.first_bits(), Reverse(network.last_bits()));
+        self.nodes
+            .binary_search_by_key(&key, |node| (node.network.version(), node.network.first_bits(), Reverse(node.network.last_bits())))
+            .ok()
+    }
+
+    // Tightest node holding the network, the network itself excluded
+    pub fn parent_of(&self, network: &Network) -> Option<usize> {
+        let key = (network.version(), network.first_bits(), Reverse(network.last_bits()));
+        let after = self.nodes.partition_point(|node| {
+            (node.network.version(), node.network.first_bits(), Reverse(node.network.last_bits())) <= key
+        });
+        // Candidates are the node just before and its ancestors
+        let mut candidate = after.checked_sub(1);
+        while let Some(index) = candidate {
+            let node = &self.nodes[index];
+            if node.network != *network && node.network.contains_network(network) == Ok(true) {
+                return Some(index);
+            }
+            candidate = node.parent;
+        }
+        None
+    }
+
+    // Root networks without children, entries that fit nowhere in the plan
+    pub fn orphans(&self) -> impl Iterator<Item = &Node> {
+        self.roots().filter(|node| node.children.is_empty())
+    }
+
+    // Share of the node covered by its children, in percent. Children never
+    // overlap, so their sizes just add up.
+    pub fn utilisation(&self, index: usize) -> f64 {
+        let node = &self.nodes[index];
+        let used: f64 = self.children(index).map(|child| child.network.total_addresses() as f64).sum();
+        used / node.network.total_addresses() as f64 * 100.0
+    }
+
+    // One line per node, two spaces of indent per level. Nodes with
+    // children show how much of them the children take.
+    pub fn to_text(&self) -> String {
+        self.nodes.iter().enumerate().map(|(index, node)| {
+            let indent = "  ".repeat(node.depth);
+            if node.children.is_empty() {
+                format!("{}{}\n", indent, node.network)
+            } else {
+                format!("{}{} ({:.2}% used)\n", indent, node.network, self.utilisation(index))
+            }
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_helpers::network;
+
+    fn hierarchy() -> Hierarchy {
+        Hierarchy::new([
+            network("10.0.1.16/28"),
+            network("192.168.7.0/24"),
+            network("10.0.2.0/24"),
+            network("10.0.0.0/16"),
+            network("10.0.1.0/24"),
+            network("10.0.1.0/24"),
+            network("10.0.1.32/28"),
+            network("2001:0db8:0000:0000:0000:0000:0000:0000/32"),
+            network("2001:0db8:0001:0000:0000:0000:0000:0000/48"),
+        ])
+    }
+
+    #[test]
+    fn hierarchy_text() {
+        assert_eq!(hierarchy().to_text(), [
+            "10.0.0.0/16 (0.78% used)",
+            "  10.0.1.0/24 (12.50% used)",
+            "    10.0.1.16/28",
+            "    10.0.1.32/28",
+            "  10.0.2.0/24",
+            "192.168.7.0/24",
+            "2001:0db8:0000:0000:0000:0000:0000:0000/32 (0.00% used)",
+            "  2001:0db8:0001:0000:0000:0000:0000:0000/48",
+            "",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn hierarchy_navigation() {
+        let hierarchy = hierarchy();
+        assert_eq!(hierarchy.len(), 8);
+        let rack = hierarchy.find(&network("10.0.1.0/24")).unwrap();
+        assert_eq!(hierarchy.children(rack).map(|node| node.network.to_string()).collect::<Vec<String>>(), vec!["10.0.1.16/28", "10.0.1.32/28"]);
+        assert_eq!(hierarchy.parent(rack).unwrap().network, network("10.0.0.0/16"));
+        assert_eq!(hierarchy.parent_of(&network("10.0.1.64/26")), Some(rack));
+        assert_eq!(hierarchy.parent_of(&network("10.0.1.0/24")), hierarchy.find(&network("10.0.0.0/16")));
+        assert_eq!(hierarchy.parent_of(&network("10.0.3.0/24")), hierarchy.find(&network("10.0.0.0/16")));
+        assert_eq!(hierarchy.parent_of(&network("172.16.0.0/12")), None);
+        assert_eq!(hierarchy.find(&network("10.0.3.0/24")), None);
+        assert_eq!(hierarchy.orphans().map(|node| node.network.to_string()).collect::<Vec<String>>(), vec!["192.168.7.0/24"]);
+        assert_eq!(hierarchy.roots().count(), 3);
+        assert_eq!(hierarchy.walk().map(|node| node.depth).collect::<Vec<usize>>(), vec![0, 1, 2, 2, 1, 0, 0, 1]);
+    }
+}
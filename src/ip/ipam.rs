@@ -0,0 +1,580 @@
+use std::fs;
+use std::path::PathBuf;
+
+use super::address::{Address, SomeAddress};
+use super::json::Json;
+use super::{Network, NetworkError};
+
+// A named block of address space. Pools nest: a child pool takes its space
+// out of the parent, so the parent can't hand that space out itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pool {
+    pub name: String,
+    pub network: Network,
+    pub parent: Option<String>,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssignmentKind {
+    Allocated,
+    Reserved,
+}
+
+// A single address (as a /32 or /128) or a subnet handed out from a pool
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub network: Network,
+    pub pool: String,
+    pub kind: AssignmentKind,
+    pub owner: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    CreatePool,
+    RemovePool,
+    Allocate,
+    Reserve,
+    Release,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub sequence: u64,
+    pub action: Action,
+    pub pool: String,
+    pub network: Network,
+    pub owner: String,
+    pub description: String,
+}
+
+// Everything a storage backend has to keep
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct State {
+    pub pools: Vec<Pool>,
+    pub assignments: Vec<Assignment>,
+    pub history: Vec<Event>,
+}
+
+#[derive(Debug)]
+pub enum IpamError {
+    UnknownPool(String),
+    DuplicatePool(String),
+    PoolNotEmpty(String),
+    OutsidePool(Network),
+    Overlap(Network),
+    Exhausted(String),
+    NotFound(Network),
+    Storage(String),
+    Network(NetworkError),
+    Io(std::io::Error),
+}
+
+// Where the IPAM keeps its state. `save` gets the whole state after every
+// change, so a backend only has to store and return it.
+pub trait Storage {
+    fn load(&mut self) -> Result<State, IpamError>;
+    fn save(&mut self, state: &State) -> Result<(), IpamError>;
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    state: State,
+}
+
+// Keeps the state as a JSON document. A missing file is an empty IPAM.
+#[derive(Debug)]
+pub struct JsonFileStore {
+    path: PathBuf,
+}
+
+pub struct Ipam<S: Storage> {
+    store: S,
+    state: State,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+impl Storage for MemoryStore {
+    fn load(&mut self) -> Result<State, IpamError> {
+        Ok(self.state.clone())
+    }
+
+    fn save(&mut self, state: &State) -> Result<(), IpamError> {
+        self.state = state.clone();
+        Ok(())
+    }
+}
+
+impl JsonFileStore {
+    pub fn new<P: Into<PathBuf>>(path: P) -> JsonFileStore {
+        JsonFileStore { path: path.into() }
+    }
+}
+
+impl Storage for JsonFileStore {
+    fn load(&mut self) -> Result<State, IpamError> {
+        match fs::read_to_string(&self.path) {
+            Ok(text) => State::from_json(&text),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(State::default()),
+            Err(error) => Err(IpamError::Io(error)),
+        }
+    }
+
+    fn save(&mut self, state: &State) -> Result<(), IpamError> {
+        // Write next to the target and rename, so a crash never leaves half a file
+        let temporary = self.path.with_extension("tmp");
+        fs::write(&temporary, state.to_json()).map_err(IpamError::Io)?;
+        fs::rename(&temporary, &self.path).map_err(IpamError::Io)
+    }
+}
+
+impl Action {
+    fn name(&self) -> &'static str {
+        match self {
+            Action::CreatePool => "create_pool",
+            Action::RemovePool => "remove_pool",
+            Action::Allocate => "allocate",
+            Action::Reserve => "reserve",
+            Action::Release => "release",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Action> {
+        [Action::CreatePool, Action::RemovePool, Action::Allocate, Action::Reserve, Action::Release]
+            .into_iter()
+            .find(|action| action.name() == name)
+    }
+}
+
+impl State {
+    pub fn to_json(&self) -> String {
+        let text = |value: &str| Json::String(value.to_string());
+        let network = |network: &Network| Json::String(network.to_string());
+        let pools = self.pools.iter().map(|pool| Json::Object(vec![
+            (String::from("name"), text(&pool.name)),
+            (String::from("network"), network(&pool.network)),
+            (String::from("parent"), pool.parent.as_deref().map_or(Json::Null, text)),
+            (String::from("description"), text(&pool.description)),
+        ])).collect();
+        let assignments = self.assignments.iter().map(|assignment| Json::Object(vec![
+            (String::from("network"), network(&assignment.network)),
+            (String::from("pool"), text(&assignment.pool)),
+            (String::from("kind"), text(match assignment.kind {
+                AssignmentKind::Allocated => "allocated",
+                AssignmentKind::Reserved => "reserved",
+            })),
+            (String::from("owner"), text(&assignment.owner)),
+            (String::from("description"), text(&assignment.description)),
+        ])).collect();
+        let history = self.history.iter().map(|event| Json::Object(vec![
+            (String::from("sequence"), Json::Number(event.sequence.into())),
+            (String::from("action"), text(event.action.name())),
+            (String::from("pool"), text(&event.pool)),
+            (String::from("network"), network(&event.network)),
+            (String::from("owner"), text(&event.owner)),
+            (String::from("description"), text(&event.description)),
+        ])).collect();
+        Json::Object(vec![
+            (String::from("version"), Json::Number(1)),
+            (String::from("pools"), Json::Array(pools)),
+            (String::from("assignments"), Json::Array(assignments)),
+            (String::from("history"), Json::Array(history)),
+        ]).to_text()
+    }
+
+    pub fn from_json(text: &str) -> Result<State, IpamError> {
+        let document = Json::parse(text).map_err(IpamError::Storage)?;
+        if document.get("version").and_then(Json::as_u64) != Some(1) {
+            return Err(IpamError::Storage(String::from("Unsupported IPAM state version")));
+        }
+        let missing = |field: &str| IpamError::Storage(format!("Missing or wrong field '{}'", field));
+        let list = |field: &str| document.get(field).and_then(Json::as_array).ok_or_else(|| missing(field));
+        let text = |object: &Json, field: &str| {
+            object.get(field).and_then(Json::as_str).map(String::from).ok_or_else(|| missing(field))
+        };
+        let network = |object: &Json| Network::from_string(&text(object, "network")?).map_err(IpamError::Network);
+
+        let mut state = State::default();
+        for pool in list("pools")? {
+            state.pools.push(Pool {
+                name: text(pool, "name")?,
+                network: network(pool)?,
+                parent: pool.get("parent").and_then(Json::as_str).map(String::from),
+                description: text(pool, "description")?,
+            });
+        }
+        for assignment in list("assignments")? {
+            let kind = match text(assignment, "kind")?.as_str() {
+                "allocated" => AssignmentKind::Allocated,
+                "reserved" => AssignmentKind::Reserved,
+                _ => return Err(missing("kind")),
+            };
+            state.assignments.push(Assignment {
+                network: network(assignment)?,
+                pool: text(assignment, "pool")?,
+                kind,
+                owner: text(assignment, "owner")?,
+                description: text(assignment, "description")?,
+            });
+        }
+        for event in list("history")? {
+            state.history.push(Event {
+                sequence: event.get("sequence").and_then(Json::as_u64).ok_or_else(|| missing("sequence"))?,
+                action: Action::from_name(&text(event, "action")?).ok_or_else(|| missing("action"))?,
+                pool: text(event, "pool")?,
+                network: network(event)?,
+                owner: text(event, "owner")?,
+                description: text(event, "description")?,
+            });
+        }
+        Ok(state)
+    }
+}
+
+impl<S: Storage> Ipam<S> {
+    pub fn open(mut store: S) -> Result<Ipam<S>, IpamError> {
+        let state = store.load()?;
+        Ok(Ipam { store, state })
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn pool(&self, name: &str) -> Option<&Pool> {
+        self.state.pools.iter().find(|pool| pool.name == name)
+    }
+
+    pub fn pools(&self) -> impl Iterator<Item = &Pool> {
+        self.state.pools.iter()
+    }
+
+    pub fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Pool> {
+        self.state.pools.iter().filter(move |pool| pool.parent.as_deref() == Some(name))
+    }
+
+    pub fn assignments<'a>(&'a self, pool: &'a str) -> impl Iterator<Item = &'a Assignment> {
+        self.state.assignments.iter().filter(move |assignment| assignment.pool == pool)
+    }
+
+    pub fn history(&self) -> &[Event] {
+        &self.state.history
+    }
+
+    // Every event touching the network or anything inside it
+    pub fn history_of<'a>(&'a self, network: &'a Network) -> impl Iterator<Item = &'a Event> {
+        self.state.history.iter().filter(move |event| network.contains_network(&event.network) == Ok(true))
+    }
+
+    // The assignment holding the address
+    pub fn find(&self, address: &dyn Address) -> Option<&Assignment> {
+        self.state.assignments.iter().find(|assignment| assignment.network.contains(address) == Ok(true))
+    }
+
+    // Percentage of the pool taken by assignments and child pools
+    pub fn utilisation(&self, pool: &str) -> Result<f64, IpamError> {
+        let network = &self.existing_pool(pool)?.network;
+        let used: f64 = self.used_in(pool).iter().map(|(first, last)| (last - first) as f64 + 1.0).sum();
+        Ok(used / network.total_addresses() as f64 * 100.0)
+    }
+
+    pub fn create_pool(&mut self, name: &str, network: &Network, parent: Option<&str>, description: &str) -> Result<(), IpamError> {
+        if self.pool(name).is_some() {
+            return Err(IpamError::DuplicatePool(name.to_string()));
+        }
+        match parent {
+            Some(parent) => self.check_free(parent, network)?,
+            None => {
+                // Top-level pools of the other IP version can't overlap
+                let top_level = self.state.pools.iter()
+                    .filter(|pool| pool.parent.is_none() && pool.network.version() == network.version());
+                for pool in top_level {
+                    if pool.network.overlaps(network).map_err(IpamError::Network)? {
+                        return Err(IpamError::Overlap(pool.network.clone()));
+                    }
+                }
+            }
+        }
+        self.state.pools.push(Pool {
+            name: name.to_string(),
+            network: network.clone(),
+            parent: parent.map(String::from),
+            description: description.to_string(),
+        });
+        self.record(Action::CreatePool, name, network, "", description);
+        self.commit(|state| {
+            state.pools.pop();
+        })
+    }
+
+    // Only empty pools can go, release their assignments and child pools first
+    pub fn remove_pool(&mut self, name: &str) -> Result<Pool, IpamError> {
+        self.existing_pool(name)?;
+        if self.children(name).next().is_some() || self.assignments(name).next().is_some() {
+            return Err(IpamError::PoolNotEmpty(name.to_string()));
+        }
+        let index = self.state.pools.iter().position(|pool| pool.name == name).unwrap();
+        let pool = self.state.pools.remove(index);
+        self.record(Action::RemovePool, name, &pool.network, "", &pool.description);
+        let restored = pool.clone();
+        self.commit(move |state| state.pools.insert(index, restored))?;
+        Ok(pool)
+    }
+
+    // Lowest free host address of the pool
+    pub fn allocate_address(&mut self, pool: &str, owner: &str, description: &str) -> Result<SomeAddress, IpamError> {
+        let network = self.existing_pool(pool)?.network.clone();
+        let exhausted = || IpamError::Exhausted(format!("No free address left in pool '{}'", pool));
+        let (first, last) = match (network.first_host(), network.last_host()) {
+            (Some(first), Some(last)) => (first.to_bits(), last.to_bits()),
+            _ => return Err(exhausted()),
+        };
+        let bits = first_fit(first, last, &self.used_in(pool), 0).ok_or_else(exhausted)?;
+        let address = SomeAddress::from_bits(network.version(), bits);
+        self.assign(pool, Network::from(address), AssignmentKind::Allocated, owner, description)?;
+        Ok(address)
+    }
+
+    // Lowest free aligned subnet of the given length
+    pub fn allocate_subnet(&mut self, pool: &str, prefix_len: u8, owner: &str, description: &str) -> Result<Network, IpamError> {
+        let network = self.existing_pool(pool)?.network.clone();
+        if prefix_len < network.prefix_len() || prefix_len > network.version().address_bits() {
+            return Err(IpamError::Network(NetworkError::WrongPrefixLength(format!(
+                "/{} doesn't fit into {}", prefix_len, network
+            ))));
+        }
+        let host_bits = network.version().address_bits() - prefix_len;
+        let size_mask = 1_u128.checked_shl(host_bits.into()).map_or(u128::MAX, |size| size - 1);
+        let bits = first_fit(network.first_bits(), network.last_bits(), &self.used_in(pool), size_mask)
+            .ok_or_else(|| IpamError::Exhausted(format!("No free /{} left in pool '{}'", prefix_len, pool)))?;
+        let subnet = Network::from_bits(network.version(), bits, prefix_len);
+        self.assign(pool, subnet.clone(), AssignmentKind::Allocated, owner, description)?;
+        Ok(subnet)
+    }
+
+    // Takes the given address or subnet out of the pool
+    pub fn reserve(&mut self, pool: &str, network: &Network, owner: &str, description: &str) -> Result<(), IpamError> {
+        self.check_free(pool, network)?;
+        self.assign(pool, network.clone(), AssignmentKind::Reserved, owner, description)
+    }
+
+    pub fn release(&mut self, network: &Network) -> Result<Assignment, IpamError> {
+        let index = self.state.assignments.iter()
+            .position(|assignment| assignment.network == *network)
+            .ok_or_else(|| IpamError::NotFound(network.clone()))?;
+        let assignment = self.state.assignments.remove(index);
+        self.record(Action::Release, &assignment.pool, network, &assignment.owner, &assignment.description);
+        let restored = assignment.clone();
+        self.commit(move |state| state.assignments.insert(index, restored))?;
+        Ok(assignment)
+    }
+
+    fn existing_pool(&self, name: &str) -> Result<&Pool, IpamError> {
+        self.pool(name).ok_or_else(|| IpamError::UnknownPool(name.to_string()))
+    }
+
+    // Sorted (first, last) of the assignments and child pools of the pool
+    fn used_in(&self, pool: &str) -> Vec<(u128, u128)> {
+        let mut used: Vec<(u128, u128)> = self.assignments(pool).map(|assignment| &assignment.network)
+            .chain(self.children(pool).map(|child| &child.network))
+            .map(|network| (network.first_bits(), network.last_bits()))
+            .collect();
+        used.sort_unstable();
+        used
+    }
+
+    fn check_free(&self, pool: &str, network: &Network) -> Result<(), IpamError> {
+        let parent = &self.existing_pool(pool)?.network;
+        if !parent.contains_network(network).map_err(IpamError::Network)? {
+            return Err(IpamError::OutsidePool(network.clone()));
+        }
+        let taken = self.assignments(pool).map(|assignment| &assignment.network)
+            .chain(self.children(pool).map(|child| &child.network))
+            .find(|taken| taken.overlaps(network) == Ok(true));
+        match taken {
+            Some(taken) => Err(IpamError::Overlap(taken.clone())),
+            None => Ok(()),
+        }
+    }
+
+    fn assign(&mut self, pool: &str, network: Network, kind: AssignmentKind, owner: &str, description: &str) -> Result<(), IpamError> {
+        let action = match kind {
+            AssignmentKind::Allocated => Action::Allocate,
+            AssignmentKind::Reserved => Action::Reserve,
+        };
+        self.record(action, pool, &network, owner, description);
+        self.state.assignments.push(Assignment {
+            network,
+            pool: pool.to_string(),
+            kind,
+            owner: owner.to_string(),
+            description: description.to_string(),
+        });
+        self.commit(|state| {
+            state.assignments.pop();
+        })
+    }
+
+    fn record(&mut self, action: Action, pool: &str, network: &Network, owner: &str, description: &str) {
+        let sequence = self.state.history.last().map_or(1, |event| event.sequence + 1);
+        self.state.history.push(Event {
+            sequence,
+            action,
+            pool: pool.to_string(),
+            network: network.clone(),
+            owner: owner.to_string(),
+            description: description.to_string(),
+        });
+    }
+
+    // Persists the state. If the backend fails, the change and its event are
+    // undone so memory never gets ahead of storage.
+    fn commit<F: FnOnce(&mut State)>(&mut self, undo: F) -> Result<(), IpamError> {
+        if let Err(error) = self.store.save(&self.state) {
+            self.state.history.pop();
+            undo(&mut self.state);
+            return Err(error);
+        }
+        Ok(())
+    }
+}
+
+// Lowest block start aligned to `size_mask` + 1 inside first..=last that
+// doesn't touch any of the sorted `used` ranges
+fn first_fit(first: u128, last: u128, used: &[(u128, u128)], size_mask: u128) -> Option<u128> {
+    let mut candidate = first.checked_add(size_mask)? & !size_mask;
+    for (used_first, used_last) in used {
+        if *used_last < candidate {
+            continue;
+        }
+        if candidate.checked_add(size_mask)? < *used_first {
+            break;
+        }
+        candidate = used_last.checked_add(1)?.checked_add(size_mask)? & !size_mask;
+    }
+    if candidate <= last && last - candidate >= size_mask {
+        return Some(candidate);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::address::AddressV4;
+    use super::super::test_helpers::network;
+
+    fn ipam() -> Ipam<MemoryStore> {
+        let mut ipam = Ipam::open(MemoryStore::new()).unwrap();
+        ipam.create_pool("corp", &network("10.0.0.0/16"), None, "Corporate").unwrap();
+        ipam.create_pool("servers", &network("10.0.1.0/24"), Some("corp"), "Server VLAN").unwrap();
+        ipam
+    }
+
+    #[test]
+    fn ipam_allocation() {
+        let mut ipam = ipam();
+        ipam.reserve("servers", &network("10.0.1.1/32"), "netops", "Gateway").unwrap();
+        let first = ipam.allocate_address("servers", "alice", "Web server").unwrap();
+        assert_eq!(first.to_string(), "10.0.1.2");
+        assert_eq!(ipam.allocate_address("servers", "bob", "Database").unwrap().to_string(), "10.0.1.3");
+        // The servers pool is taken out of the corp space
+        assert_eq!(ipam.allocate_subnet("corp", 24, "carol", "Office").unwrap(), network("10.0.0.0/24"));
+        assert_eq!(ipam.allocate_subnet("corp", 23, "dave", "Lab").unwrap(), network("10.0.2.0/23"));
+        assert_eq!(ipam.allocate_subnet("corp", 24, "erin", "Guests").unwrap(), network("10.0.4.0/24"));
+        assert_eq!(ipam.find(&AddressV4::from_string("10.0.3.9").unwrap()).unwrap().owner, "dave");
+        assert!((ipam.utilisation("servers").unwrap() - 300.0 / 256.0).abs() < 1e-9);
+        ipam.release(&Network::from(first)).unwrap();
+        assert_eq!(ipam.allocate_address("servers", "frank", "Web server").unwrap(), first);
+    }
+
+    #[test]
+    fn ipam_conflicts() {
+        let mut ipam = ipam();
+        ipam.reserve("corp", &network("10.0.2.0/24"), "netops", "").unwrap();
+        assert!(matches!(ipam.reserve("corp", &network("10.0.0.0/22"), "x", ""), Err(IpamError::Overlap(_))));
+        assert!(matches!(ipam.reserve("corp", &network("10.0.1.0/25"), "x", ""), Err(IpamError::Overlap(_))));
+        assert!(matches!(ipam.reserve("servers", &network("10.0.2.0/25"), "x", ""), Err(IpamError::OutsidePool(_))));
+        assert!(matches!(ipam.create_pool("lab", &network("10.0.2.128/25"), Some("corp"), ""), Err(IpamError::Overlap(_))));
+        assert!(matches!(ipam.create_pool("other", &network("10.0.0.0/8"), None, ""), Err(IpamError::Overlap(_))));
+        assert!(matches!(ipam.create_pool("servers", &network("192.168.0.0/24"), None, ""), Err(IpamError::DuplicatePool(_))));
+        assert!(matches!(ipam.remove_pool("corp"), Err(IpamError::PoolNotEmpty(_))));
+        assert!(matches!(ipam.allocate_subnet("servers", 23, "x", ""), Err(IpamError::Network(_))));
+        assert!(matches!(ipam.release(&network("10.0.3.0/24")), Err(IpamError::NotFound(_))));
+        for _ in 0..254 {
+            ipam.allocate_address("servers", "x", "").unwrap();
+        }
+        assert!(matches!(ipam.allocate_address("servers", "x", ""), Err(IpamError::Exhausted(_))));
+        ipam.remove_pool("servers").unwrap_err();
+    }
+
+    #[test]
+    fn ipam_dual_stack() {
+        let mut ipam = ipam();
+        ipam.create_pool("corp6", &network("2001:0db8:0000:0000:0000:0000:0000:0000/32"), None, "Corporate IPv6").unwrap();
+        ipam.create_pool("servers6", &network("2001:0db8:0001:0000:0000:0000:0000:0000/48"), Some("corp6"), "Server VLAN").unwrap();
+        assert!(matches!(ipam.create_pool("other6", &network("2001:0db8:00ff:0000:0000:0000:0000:0000/48"), None, ""), Err(IpamError::Overlap(_))));
+        assert_eq!(ipam.allocate_subnet("servers6", 64, "alice", "").unwrap(), network("2001:0db8:0001:0000:0000:0000:0000:0000/64"));
+        assert_eq!(ipam.allocate_subnet("corp", 24, "bob", "").unwrap(), network("10.0.0.0/24"));
+        assert_eq!(ipam.pools().filter(|pool| pool.parent.is_none()).count(), 2);
+    }
+
+    #[test]
+    fn ipam_history() {
+        let mut ipam = ipam();
+        let subnet = ipam.allocate_subnet("servers", 28, "alice", "Rack 1").unwrap();
+        ipam.release(&subnet).unwrap();
+        let actions: Vec<(u64, Action)> = ipam.history().iter().map(|event| (event.sequence, event.action)).collect();
+        assert_eq!(actions, vec![(1, Action::CreatePool), (2, Action::CreatePool), (3, Action::Allocate), (4, Action::Release)]);
+        assert_eq!(ipam.history_of(&subnet).map(|event| event.owner.as_str()).collect::<Vec<&str>>(), vec!["alice", "alice"]);
+        assert_eq!(ipam.history_of(&network("10.0.1.0/24")).count(), 3);
+    }
+
+    #[test]
+    fn ipam_json_file_store() {
+        let path = std::env::temp_dir().join(format!("ip-network-ipam-{}.json", std::process::id()));
+        {
+            let mut ipam = Ipam::open(JsonFileStore::new(&path)).unwrap();
+            ipam.create_pool("lab", &network("2001:0db8:0000:0000:0000:0000:0000:0000/48"), None, "Lab \"v6\"").unwrap();
+            ipam.allocate_subnet("lab", 64, "alice", "Test net").unwrap();
+            ipam.allocate_address("lab", "bob", "Router").unwrap();
+        }
+        let ipam = Ipam::open(JsonFileStore::new(&path)).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(ipam.pool("lab").unwrap().description, "Lab \"v6\"");
+        let assignments: Vec<String> = ipam.assignments("lab").map(|assignment| assignment.network.to_string()).collect();
+        assert_eq!(assignments, vec![
+            "2001:0db8:0000:0000:0000:0000:0000:0000/64",
+            "2001:0db8:0000:0001:0000:0000:0000:0000/128",
+        ]);
+        assert_eq!(ipam.history().len(), 3);
+        assert!(matches!(State::from_json("{\"version\":2}"), Err(IpamError::Storage(_))));
+    }
+
+    struct FailingStore;
+
+    impl Storage for FailingStore {
+        fn load(&mut self) -> Result<State, IpamError> {
+            Ok(State::default())
+        }
+
+        fn save(&mut self, _: &State) -> Result<(), IpamError> {
+            Err(IpamError::Storage(String::from("Disk full")))
+        }
+    }
+
+    #[test]
+    fn ipam_storage_failure_rolls_back() {
+        let mut ipam = Ipam::open(FailingStore).unwrap();
+        assert!(matches!(ipam.create_pool("lab", &network("10.0.0.0/8"), None, ""), Err(IpamError::Storage(_))));
+        assert_eq!(ipam.pools().count(), 0);
+        assert!(ipam.history().is_empty());
+    }
+}
@@ -0,0 +1,382 @@
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+
+use super::address::{AddressV4, AddressV6, FixedWidth, SomeAddress};
+use super::{Network, Version};
+
+// Inclusive range of u128 indexes walked from both ends, the arithmetic
+// shared by the lazy iterators below
+#[derive(Debug, Clone)]
+struct Steps {
+    front: u128,
+    back: u128,
+    done: bool,
+}
+
+impl Steps {
+    fn new(first: u128, last: u128) -> Steps {
+        Steps { front: first, back: last, done: first > last }
+    }
+
+    fn empty() -> Steps {
+        Steps { front: 0, back: 0, done: true }
+    }
+
+    // Saturates at u128::MAX when the range is the whole of u128
+    fn remaining(&self) -> u128 {
+        if self.done {
+            return 0;
+        }
+        (self.back - self.front).saturating_add(1)
+    }
+
+    fn get(&self, index: u128) -> Option<u128> {
+        if self.done || index > self.back - self.front {
+            return None;
+        }
+        Some(self.front + index)
+    }
+
+    fn next(&mut self) -> Option<u128> {
+        if self.done {
+            return None;
+        }
+        let current = self.front;
+        if self.front == self.back {
+            self.done = true;
+        } else {
+            self.front += 1;
+        }
+        Some(current)
+    }
+
+    fn nth(&mut self, n: usize) -> Option<u128> {
+        if self.done {
+            return None;
+        }
+        if n as u128 > self.back - self.front {
+            self.done = true;
+            return None;
+        }
+        self.front += n as u128;
+        self.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done {
+            return (0, Some(0));
+        }
+        match usize::try_from(self.back - self.front).ok().and_then(|n| n.checked_add(1)) {
+            Some(n) => (n, Some(n)),
+            None => (usize::MAX, None),
+        }
+    }
+
+    fn next_back(&mut self) -> Option<u128> {
+        if self.done {
+            return None;
+        }
+        let current = self.back;
+        if self.front == self.back {
+            self.done = true;
+        } else {
+            self.back -= 1;
+        }
+        Some(current)
+    }
+
+    fn nth_back(&mut self, n: usize) -> Option<u128> {
+        if self.done {
+            return None;
+        }
+        if n as u128 > self.back - self.front {
+            self.done = true;
+            return None;
+        }
+        self.back -= n as u128;
+        self.next_back()
+    }
+}
+
+// Lazy inclusive range of addresses. Only the two ends are stored, so even
+// the widest IPv6 network costs nothing until it is consumed.
+#[derive(Debug, Clone)]
+pub struct Addresses<A> {
+    steps: Steps,
+    family: PhantomData<A>,
+}
+
+impl<A: FixedWidth> Addresses<A> {
+    pub fn new(first: A, last: A) -> Addresses<A> {
+        Addresses::from_bits(first.to_bits(), last.to_bits())
+    }
+
+    pub fn empty() -> Addresses<A> {
+        Addresses { steps: Steps::empty(), family: PhantomData }
+    }
+
+    pub(crate) fn from_bits(first: u128, last: u128) -> Addresses<A> {
+        Addresses { steps: Steps::new(first, last), family: PhantomData }
+    }
+
+    // Number of addresses left. Saturates at u128::MAX for the whole IPv6 space.
+    pub fn remaining(&self) -> u128 {
+        self.steps.remaining()
+    }
+
+    // Random access into what is left of the range, without consuming it
+    pub fn get(&self, index: u128) -> Option<A> {
+        self.steps.get(index).map(A::from_bits)
+    }
+}
+
+impl<A: FixedWidth> Iterator for Addresses<A> {
+    type Item = A;
+
+    fn next(&mut self) -> Option<A> {
+        self.steps.next().map(A::from_bits)
+    }
+
+    fn nth(&mut self, n: usize) -> Option<A> {
+        self.steps.nth(n).map(A::from_bits)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.steps.size_hint()
+    }
+
+    fn count(self) -> usize {
+        usize::try_from(self.remaining()).expect("Addresses number doesn't fit into usize")
+    }
+
+    fn last(mut self) -> Option<A> {
+        self.next_back()
+    }
+}
+
+impl<A: FixedWidth> DoubleEndedIterator for Addresses<A> {
+    fn next_back(&mut self) -> Option<A> {
+        self.steps.next_back().map(A::from_bits)
+    }
+
+    fn nth_back(&mut self, n: usize) -> Option<A> {
+        self.steps.nth_back(n).map(A::from_bits)
+    }
+}
+
+impl<A: FixedWidth> FusedIterator for Addresses<A> {}
+
+// At most 2^32 items, so the length is always known
+impl ExactSizeIterator for Addresses<AddressV4> {}
+
+// Family-erased iterator returned by Network. Match on it to get the typed one.
+#[derive(Debug, Clone)]
+pub enum AddressIter {
+    V4(Addresses<AddressV4>),
+    V6(Addresses<AddressV6>),
+}
+
+impl AddressIter {
+    pub fn remaining(&self) -> u128 {
+        match self {
+            AddressIter::V4(iter) => iter.remaining(),
+            AddressIter::V6(iter) => iter.remaining(),
+        }
+    }
+
+    pub fn get(&self, index: u128) -> Option<SomeAddress> {
+        match self {
+            AddressIter::V4(iter) => iter.get(index).map(SomeAddress::V4),
+            AddressIter::V6(iter) => iter.get(index).map(SomeAddress::V6),
+        }
+    }
+}
+
+impl Iterator for AddressIter {
+    type Item = SomeAddress;
+
+    fn next(&mut self) -> Option<SomeAddress> {
+        match self {
+            AddressIter::V4(iter) => iter.next().map(SomeAddress::V4),
+            AddressIter::V6(iter) => iter.next().map(SomeAddress::V6),
+        }
+    }
+
+    fn nth(&mut self, n: usize) -> Option<SomeAddress> {
+        match self {
+            AddressIter::V4(iter) => iter.nth(n).map(SomeAddress::V4),
+            AddressIter::V6(iter) => iter.nth(n).map(SomeAddress::V6),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            AddressIter::V4(iter) => iter.size_hint(),
+            AddressIter::V6(iter) => iter.size_hint(),
+        }
+    }
+
+    fn count(self) -> usize {
+        match self {
+            AddressIter::V4(iter) => iter.count(),
+            AddressIter::V6(iter) => iter.count(),
+        }
+    }
+
+    fn last(mut self) -> Option<SomeAddress> {
+        self.next_back()
+    }
+}
+
+impl DoubleEndedIterator for AddressIter {
+    fn next_back(&mut self) -> Option<SomeAddress> {
+        match self {
+            AddressIter::V4(iter) => iter.next_back().map(SomeAddress::V4),
+            AddressIter::V6(iter) => iter.next_back().map(SomeAddress::V6),
+        }
+    }
+
+    fn nth_back(&mut self, n: usize) -> Option<SomeAddress> {
+        match self {
+            AddressIter::V4(iter) => iter.nth_back(n).map(SomeAddress::V4),
+            AddressIter::V6(iter) => iter.nth_back(n).map(SomeAddress::V6),
+        }
+    }
+}
+
+impl FusedIterator for AddressIter {}
+
+// Lazy sequence of equally sized subnets. Indexes are kept instead of
+// networks, splitting a /32 into /64s needs no memory at all.
+#[derive(Debug, Clone)]
+pub struct Subnets {
+    version: Version,
+    base: u128,
+    prefix_len: u8,
+    steps: Steps,
+}
+
+impl Subnets {
+    pub(crate) fn new(version: Version, base: u128, prefix_len: u8, last_index: u128) -> Subnets {
+        Subnets { version, base, prefix_len, steps: Steps::new(0, last_index) }
+    }
+
+    // Number of subnets left. Saturates at u128::MAX when splitting ::/0 into /128s.
+    pub fn remaining(&self) -> u128 {
+        self.steps.remaining()
+    }
+
+    pub fn get(&self, index: u128) -> Option<Network> {
+        self.steps.get(index).map(|index| self.network_at(index))
+    }
+
+    fn network_at(&self, index: u128) -> Network {
+        let host_bits = self.version.address_bits() - self.prefix_len;
+        let offset = if host_bits == 128 { 0 } else { index << host_bits };
+        Network::from_bits(self.version, self.base + offset, self.prefix_len)
+    }
+}
+
+impl Iterator for Subnets {
+    type Item = Network;
+
+    fn next(&mut self) -> Option<Network> {
+        self.steps.next().map(|index| self.network_at(index))
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Network> {
+        self.steps.nth(n).map(|index| self.network_at(index))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.steps.size_hint()
+    }
+
+    fn count(self) -> usize {
+        usize::try_from(self.remaining()).expect("Subnets number doesn't fit into usize")
+    }
+
+    fn last(mut self) -> Option<Network> {
+        self.next_back()
+    }
+}
+
+impl DoubleEndedIterator for Subnets {
+    fn next_back(&mut self) -> Option<Network> {
+        self.steps.next_back().map(|index| self.network_at(index))
+    }
+
+    fn nth_back(&mut self, n: usize) -> Option<Network> {
+        self.steps.nth_back(n).map(|index| self.network_at(index))
+    }
+}
+
+impl FusedIterator for Subnets {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v4(address: &str) -> AddressV4 {
+        AddressV4::from_string(address).unwrap()
+    }
+
+    #[test]
+    fn addresses_forward_and_backward() {
+        let mut iter = Addresses::new(v4("10.0.0.1"), v4("10.0.0.4"));
+        assert_eq!(iter.len(), 4);
+        assert_eq!(iter.next(), Some(v4("10.0.0.1")));
+        assert_eq!(iter.next_back(), Some(v4("10.0.0.4")));
+        assert_eq!(iter.len(), 2);
+        assert_eq!(iter.next_back(), Some(v4("10.0.0.3")));
+        assert_eq!(iter.next(), Some(v4("10.0.0.2")));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+    }
+
+    #[test]
+    fn addresses_nth_and_step() {
+        let mut iter = Addresses::new(v4("10.0.0.0"), v4("10.255.255.255"));
+        assert_eq!(iter.nth(256), Some(v4("10.0.1.0")));
+        assert_eq!(iter.nth_back(255), Some(v4("10.255.255.0")));
+        assert_eq!(iter.get(0), Some(v4("10.0.1.1")));
+        let stepped: Vec<AddressV4> = Addresses::new(v4("10.0.0.0"), v4("10.0.0.255")).step_by(64).collect();
+        assert_eq!(stepped, vec![v4("10.0.0.0"), v4("10.0.0.64"), v4("10.0.0.128"), v4("10.0.0.192")]);
+        let reversed: Vec<AddressV4> = Addresses::new(v4("10.0.0.0"), v4("10.0.0.255")).rev().step_by(128).collect();
+        assert_eq!(reversed, vec![v4("10.0.0.255"), v4("10.0.0.127")]);
+        assert_eq!(iter.nth(usize::MAX), None);
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn addresses_whole_v6_space() {
+        let mut iter: Addresses<AddressV6> = Addresses::from_bits(0, u128::MAX);
+        assert_eq!(iter.remaining(), u128::MAX);
+        assert_eq!(iter.size_hint(), (usize::MAX, None));
+        assert_eq!(iter.next_back(), Some(AddressV6::from_u128(u128::MAX)));
+        assert_eq!(iter.nth(usize::MAX), Some(AddressV6::from_u128(usize::MAX as u128)));
+        assert_eq!(iter.last(), Some(AddressV6::from_u128(u128::MAX - 1)));
+    }
+
+    #[test]
+    fn addresses_empty() {
+        let mut iter = Addresses::new(v4("10.0.0.2"), v4("10.0.0.1"));
+        assert_eq!(iter.len(), 0);
+        assert_eq!(iter.next(), None);
+        assert_eq!(Addresses::<AddressV4>::empty().remaining(), 0);
+    }
+
+    #[test]
+    fn subnets_both_ends() {
+        let network = Network::from_bits(Version::IpV4, 0x0a000000, 24);
+        let mut subnets = network.subnets(28).unwrap();
+        assert_eq!(subnets.size_hint(), (16, Some(16)));
+        assert_eq!(subnets.nth_back(1).unwrap().to_string(), "10.0.0.224/28");
+        assert_eq!(subnets.nth(2).unwrap().to_string(), "10.0.0.32/28");
+        assert_eq!(subnets.get(0).unwrap().to_string(), "10.0.0.48/28");
+        assert_eq!(subnets.remaining(), 11);
+        assert_eq!(subnets.clone().last().unwrap().to_string(), "10.0.0.208/28");
+        assert!(subnets.nth(usize::MAX).is_none());
+        assert!(subnets.next_back().is_none());
+    }
+}
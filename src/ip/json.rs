@@ -0,0 +1,276 @@
+// Minimal JSON reader and writer for the file formats of this crate.
+// Numbers are kept as u128 since nothing here stores fractions or negatives
+// and address counts need the full width.
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(u128),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub(crate) fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(value) => u64::try_from(*value).ok(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn to_text(&self) -> String {
+        match self {
+            Json::Null => String::from("null"),
+            Json::Bool(value) => value.to_string(),
+            Json::Number(value) => value.to_string(),
+            Json::String(value) => quote(value),
+            Json::Array(values) => {
+                let values: Vec<String> = values.iter().map(|value| value.to_text()).collect();
+                format!("[{}]", values.join(","))
+            }
+            Json::Object(members) => {
+                let members: Vec<String> = members.iter().map(|(key, value)| format!("{}:{}", quote(key), value.to_text())).collect();
+                format!("{{{}}}", members.join(","))
+            }
+        }
+    }
+
+    pub(crate) fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser { chars: text.chars().collect(), position: 0, depth: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.position != parser.chars.len() {
+            return Err(parser.error("Trailing characters"));
+        }
+        Ok(value)
+    }
+}
+
+fn quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted += "\\\"",
+            '\\' => quoted += "\\\\",
+            '\n' => quoted += "\\n",
+            '\r' => quoted += "\\r",
+            '\t' => quoted += "\\t",
+            c if (c as u32) < 0x20 => quoted += &format!("\\u{:0>4x}", c as u32),
+            c => quoted.push(c),
+        }
+    }
+    quoted + "\""
+}
+
+// Deeper documents are rejected instead of recursing further
+const MAX_DEPTH: usize = 128;
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> String {
+        format!("{} at character {}", message, self.position)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.position).is_some_and(|c| c.is_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.get(self.position).copied();
+        self.position += 1;
+        c
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        for c in expected.chars() {
+            if self.next() != Some(c) {
+                return Err(self.error(&format!("Expected '{}'", expected)));
+            }
+        }
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.chars.get(self.position) {
+            Some('n') => self.expect("null").map(|_| Json::Null),
+            Some('t') => self.expect("true").map(|_| Json::Bool(true)),
+            Some('f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some('"') => self.string().map(Json::String),
+            Some('[') | Some('{') => {
+                // Each level recurses, so hostile input could blow the stack
+                if self.depth == MAX_DEPTH {
+                    return Err(self.error("Too deeply nested"));
+                }
+                self.depth += 1;
+                let value = if self.next() == Some('[') { self.array() } else { self.object() };
+                self.depth -= 1;
+                value
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let start = self.position;
+                while self.chars.get(self.position).is_some_and(|c| c.is_ascii_digit()) {
+                    self.position += 1;
+                }
+                let digits: String = self.chars[start..self.position].iter().collect();
+                digits.parse().map(Json::Number).map_err(|_| self.error("Number out of range"))
+            }
+            _ => Err(self.error("Unexpected character")),
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.chars.get(self.position) == Some(&']') {
+            self.position += 1;
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Json::Array(values)),
+                _ => return Err(self.error("Expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.chars.get(self.position) == Some(&'}') {
+            self.position += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(":")?;
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Json::Object(members)),
+                _ => return Err(self.error("Expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect("\"")?;
+        let mut value = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(value),
+                Some('\\') => match self.next() {
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some('/') => value.push('/'),
+                    Some('n') => value.push('\n'),
+                    Some('r') => value.push('\r'),
+                    Some('t') => value.push('\t'),
+                    Some('b') => value.push('\u{8}'),
+                    Some('f') => value.push('\u{c}'),
+                    Some('u') => {
+                        let mut code = self.hex4()?;
+                        // Characters outside the BMP come as a surrogate pair
+                        if (0xd800..0xdc00).contains(&code) {
+                            self.expect("\\u")?;
+                            let low = self.hex4()?;
+                            if !(0xdc00..0xe000).contains(&low) {
+                                return Err(self.error("Unpaired surrogate"));
+                            }
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                        }
+                        value.push(char::from_u32(code).ok_or_else(|| self.error("Wrong escape"))?);
+                    }
+                    _ => return Err(self.error("Wrong escape")),
+                },
+                Some(c) => value.push(c),
+                None => return Err(self.error("Unterminated string")),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let end = self.position + 4;
+        let hex: String = self.chars.get(self.position..end).unwrap_or_default().iter().collect();
+        let code = u32::from_str_radix(&hex, 16).map_err(|_| self.error("Wrong escape"))?;
+        self.position = end;
+        Ok(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_round_trip() {
+        let text = r#"{"name":"a \"quoted\"\n\\ value","count":42,"flags":[true,false,null],"empty":{}}"#;
+        let value = Json::parse(text).unwrap();
+        assert_eq!(value.get("name").and_then(Json::as_str), Some("a \"quoted\"\n\\ value"));
+        assert_eq!(value.get("count").and_then(Json::as_u64), Some(42));
+        assert_eq!(value.get("flags").and_then(Json::as_array).map(|flags| flags.len()), Some(3));
+        assert_eq!(value.to_text(), text);
+        assert_eq!(Json::parse(" [ 1 , \"\\u0041\" ] ").unwrap(), Json::Array(vec![Json::Number(1), Json::String(String::from("A"))]));
+        assert!(Json::parse("{\"a\":1").is_err());
+        assert!(Json::parse("[1] x").is_err());
+    }
+
+    #[test]
+    fn json_writer_escapes() {
+        let value = Json::Object(vec![
+            (String::from("name"), Json::String(String::from("say \"hi\"\\\n\u{1}"))),
+            (String::from("count"), Json::Number(1 << 100)),
+            (String::from("note"), Json::Null),
+        ]);
+        assert_eq!(value.to_text(), "{\"name\":\"say \\\"hi\\\"\\\\\\n\\u0001\",\"count\":1267650600228229401496703205376,\"note\":null}");
+    }
+
+    #[test]
+    fn json_nesting_and_surrogates() {
+        let deep = "[".repeat(MAX_DEPTH) + &"]".repeat(MAX_DEPTH);
+        assert!(Json::parse(&deep).is_ok());
+        let too_deep = "[".repeat(MAX_DEPTH + 1) + &"]".repeat(MAX_DEPTH + 1);
+        assert!(Json::parse(&too_deep).is_err());
+        assert!(Json::parse(&"[{\"a\":".repeat(100_000)).is_err());
+        assert_eq!(Json::parse("\"\\ud83d\\ude00\"").unwrap(), Json::String(String::from("\u{1f600}")));
+        assert!(Json::parse("\"\\ud83d\"").is_err());
+        assert!(Json::parse("\"\\ud83d\\u0041\"").is_err());
+        assert!(Json::parse("\"\\ude00\"").is_err());
+    }
+}
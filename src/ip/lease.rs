@@ -0,0 +1,419 @@
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::address::FixedWidth;
+use super::range::AddressRange;
+use super::set::IpSet;
+use super::{Network, NetworkError};
+
+// Source of the current time in seconds, injectable so tests can move time
+pub trait Clock {
+    fn now(&self) -> u64;
+}
+
+// Seconds since the Unix epoch
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+// Clock that only moves when told to
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: Cell<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lease<A> {
+    pub address: A,
+    pub client: String,
+    pub start: u64,
+    pub expires: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LeaseError {
+    Exhausted,
+    NoLease(String),
+    OutsidePool(String),
+    Excluded(String),
+    AlreadyReserved(String),
+}
+
+// Address counts of the pool. Reserved addresses whose client holds a
+// lease are counted as leased.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    pub total: u128,
+    pub excluded: u128,
+    pub reserved: u128,
+    pub leased: u128,
+    pub available: u128,
+}
+
+// DHCP-like lease pool. Expired leases stay in the table until their address
+// is needed by another client, so a returning client gets its old address
+// back. Never used addresses are handed out before expired ones for that reason.
+pub struct LeasePool<A, C> {
+    range: AddressRange<A>,
+    duration: u64,
+    clock: C,
+    excluded: IpSet<A>,
+    // Static reservations, both ways
+    reserved: BTreeMap<u128, String>,
+    reservations: HashMap<String, u128>,
+    // Address -> lease, active or expired
+    leases: BTreeMap<u128, Lease<A>>,
+    clients: HashMap<String, u128>,
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs())
+    }
+}
+
+impl ManualClock {
+    pub fn new(now: u64) -> ManualClock {
+        ManualClock { now: Cell::new(now) }
+    }
+
+    pub fn set(&self, now: u64) {
+        self.now.set(now);
+    }
+
+    pub fn advance(&self, seconds: u64) {
+        self.now.set(self.now.get() + seconds);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.now.get()
+    }
+}
+
+impl<A> Lease<A> {
+    pub fn is_active(&self, now: u64) -> bool {
+        now < self.expires
+    }
+}
+
+impl Usage {
+    // Percentage of the assignable addresses that are leased
+    pub fn utilisation(&self) -> f64 {
+        let assignable = self.total - self.excluded;
+        if assignable == 0 {
+            return 100.0;
+        }
+        self.leased as f64 / assignable as f64 * 100.0
+    }
+}
+
+impl<A: FixedWidth, C: Clock> LeasePool<A, C> {
+    // `duration` is the lease time in seconds
+    pub fn new(range: AddressRange<A>, duration: u64, clock: C) -> LeasePool<A, C> {
+        LeasePool {
+            range,
+            duration,
+            clock,
+            excluded: IpSet::new(),
+            reserved: BTreeMap::new(),
+            reservations: HashMap::new(),
+            leases: BTreeMap::new(),
+            clients: HashMap::new(),
+        }
+    }
+
+    // Pool over the host addresses of the network
+    pub fn from_network(network: &Network, duration: u64, clock: C) -> Result<LeasePool<A, C>, NetworkError> {
+        if network.version() != A::VERSION {
+            return Err(NetworkError::VersionMismatch);
+        }
+        match (network.first_host(), network.last_host()) {
+            (Some(first), Some(last)) => Ok(LeasePool::new(AddressRange::from_bits(first.to_bits(), last.to_bits()), duration, clock)),
+            _ => Err(NetworkError::WrongPrefixLength(format!("{} has no host addresses", network))),
+        }
+    }
+
+    pub fn range(&self) -> AddressRange<A> {
+        self.range
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    // Keeps addresses out of the pool, e.g. routers or a static block.
+    // Leases already on them stay until they expire.
+    pub fn exclude(&mut self, range: AddressRange<A>) {
+        self.excluded.insert(range);
+    }
+
+    // The client always gets this address, and nobody else does
+    pub fn reserve(&mut self, client: &str, address: A) -> Result<(), LeaseError> {
+        let bits = address.to_bits();
+        self.check_in_pool(&address)?;
+        if let Some(holder) = self.reserved.get(&bits) {
+            if holder != client {
+                return Err(LeaseError::AlreadyReserved(address.to_string()));
+            }
+        }
+        if let Some(previous) = self.reservations.insert(client.to_string(), bits) {
+            self.reserved.remove(&previous);
+        }
+        self.reserved.insert(bits, client.to_string());
+        Ok(())
+    }
+
+    pub fn unreserve(&mut self, client: &str) -> Option<A> {
+        let bits = self.reservations.remove(client)?;
+        self.reserved.remove(&bits);
+        Some(A::from_bits(bits))
+    }
+
+    // Leases an address to the client, like DHCPDISCOVER + DHCPREQUEST.
+    // Preference order: the client's current or previous lease, its static
+    // reservation, the lowest never used address, the longest expired lease.
+    // A previous address excluded or reserved for somebody else since is
+    // given up for a fresh one.
+    pub fn acquire(&mut self, client: &str) -> Result<Lease<A>, LeaseError> {
+        let now = self.clock.now();
+        let reservation = self.reservations.get(client).copied();
+        if let Some(bits) = self.clients.get(client).copied() {
+            if (reservation.is_none() || reservation == Some(bits)) && self.check_sticky(client, bits).is_ok() {
+                return Ok(self.grant(client, bits, now));
+            }
+        }
+        if let Some(bits) = reservation {
+            if self.leases.get(&bits).is_some_and(|lease| lease.client != client && lease.is_active(now)) {
+                return Err(LeaseError::AlreadyReserved(A::from_bits(bits).to_string()));
+            }
+            return Ok(self.grant(client, bits, now));
+        }
+        let bits = self.first_unused()
+            .or_else(|| self.oldest_expired(now))
+            .ok_or(LeaseError::Exhausted)?;
+        Ok(self.grant(client, bits, now))
+    }
+
+    // Extends the client's lease, even an expired one as long as its
+    // address hasn't been given to somebody else, excluded or reserved for
+    // another client in the meantime
+    pub fn renew(&mut self, client: &str) -> Result<Lease<A>, LeaseError> {
+        let bits = *self.clients.get(client).ok_or_else(|| LeaseError::NoLease(client.to_string()))?;
+        self.check_sticky(client, bits)?;
+        let now = self.clock.now();
+        Ok(self.grant(client, bits, now))
+    }
+
+    // Ends the lease now. The address is remembered for the client.
+    pub fn release(&mut self, client: &str) -> Result<Lease<A>, LeaseError> {
+        let now = self.clock.now();
+        let lease = self.clients.get(client)
+            .and_then(|bits| self.leases.get_mut(bits))
+            .filter(|lease| lease.is_active(now))
+            .ok_or_else(|| LeaseError::NoLease(client.to_string()))?;
+        lease.expires = now;
+        Ok(lease.clone())
+    }
+
+    // Active lease of the client
+    pub fn lease_of(&self, client: &str) -> Option<&Lease<A>> {
+        let now = self.clock.now();
+        self.clients.get(client).and_then(|bits| self.leases.get(bits)).filter(|lease| lease.is_active(now))
+    }
+
+    // Active lease on the address
+    pub fn lease_at(&self, address: &A) -> Option<&Lease<A>> {
+        let now = self.clock.now();
+        self.leases.get(&address.to_bits()).filter(|lease| lease.is_active(now))
+    }
+
+    // Active leases sorted by address
+    pub fn leases(&self) -> impl Iterator<Item = &Lease<A>> {
+        let now = self.clock.now();
+        self.leases.values().filter(move |lease| lease.is_active(now))
+    }
+
+    // Drops expired leases and returns them, their clients lose the sticky address
+    pub fn purge_expired(&mut self) -> Vec<Lease<A>> {
+        let now = self.clock.now();
+        let expired: Vec<u128> = self.leases.iter()
+            .filter(|(_, lease)| !lease.is_active(now))
+            .map(|(bits, _)| *bits)
+            .collect();
+        expired.iter().filter_map(|bits| {
+            let lease = self.leases.remove(bits)?;
+            self.clients.remove(&lease.client);
+            Some(lease)
+        }).collect()
+    }
+
+    pub fn usage(&self) -> Usage {
+        let now = self.clock.now();
+        let total = self.range.count();
+        let excluded = self.excluded.intersection(&IpSet::from_ranges([self.range])).len();
+        let reserved = self.reserved.keys().filter(|bits| !self.excluded.contains(&A::from_bits(**bits))).count() as u128;
+        let leased = self.leases.values().filter(|lease| lease.is_active(now)).count() as u128;
+        let leased_reserved = self.leases.iter()
+            .filter(|(bits, lease)| lease.is_active(now) && self.reserved.contains_key(bits) && !self.excluded.contains(&lease.address))
+            .count() as u128;
+        let leased_excluded = self.leases.values().filter(|lease| lease.is_active(now) && self.excluded.contains(&lease.address)).count() as u128;
+        Usage {
+            total,
+            excluded,
+            reserved,
+            leased,
+            available: total - excluded - reserved - (leased - leased_reserved - leased_excluded),
+        }
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.usage().available == 0
+    }
+
+    fn check_in_pool(&self, address: &A) -> Result<(), LeaseError> {
+        if !self.range.contains(address) {
+            return Err(LeaseError::OutsidePool(address.to_string()));
+        }
+        if self.excluded.contains(address) {
+            return Err(LeaseError::Excluded(address.to_string()));
+        }
+        Ok(())
+    }
+
+    // Whether the client may keep the address it had
+    fn check_sticky(&self, client: &str, bits: u128) -> Result<(), LeaseError> {
+        let address = A::from_bits(bits);
+        if self.excluded.contains(&address) {
+            return Err(LeaseError::Excluded(address.to_string()));
+        }
+        if self.reserved.get(&bits).is_some_and(|holder| holder != client) {
+            return Err(LeaseError::AlreadyReserved(address.to_string()));
+        }
+        Ok(())
+    }
+
+    fn grant(&mut self, client: &str, bits: u128, now: u64) -> Lease<A> {
+        if let Some(previous) = self.clients.insert(client.to_string(), bits) {
+            if previous != bits {
+                self.leases.remove(&previous);
+            }
+        }
+        if let Some(lease) = self.leases.get(&bits) {
+            // Taking over an expired lease of another client
+            if lease.client != client {
+                self.clients.remove(&lease.client);
+            }
+        }
+        let start = match self.leases.get(&bits) {
+            Some(lease) if lease.client == client && lease.is_active(now) => lease.start,
+            _ => now,
+        };
+        let lease = Lease { address: A::from_bits(bits), client: client.to_string(), start, expires: now.saturating_add(self.duration) };
+        self.leases.insert(bits, lease.clone());
+        lease
+    }
+
+    fn first_unused(&self) -> Option<u128> {
+        let used = IpSet::from_ranges(self.leases.keys().chain(self.reserved.keys()).map(|bits| AddressRange::from_bits(*bits, *bits)));
+        let free = IpSet::from_ranges([self.range]).difference(&used.union(&self.excluded));
+        let first = free.ranges().next().map(|range| range.first().to_bits());
+        first
+    }
+
+    fn oldest_expired(&self, now: u64) -> Option<u128> {
+        self.leases.iter()
+            .filter(|(bits, lease)| {
+                !lease.is_active(now) && !self.reserved.contains_key(bits) && !self.excluded.contains(&lease.address)
+            })
+            .min_by_key(|(_, lease)| lease.expires)
+            .map(|(bits, _)| *bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::address::AddressV4;
+
+    fn v4(address: &str) -> AddressV4 {
+        AddressV4::from_string(address).unwrap()
+    }
+
+    fn pool() -> LeasePool<AddressV4, ManualClock> {
+        let network = Network::from_string("192.168.1.0/29").unwrap();
+        LeasePool::from_network(&network, 3600, ManualClock::new(1000)).unwrap()
+    }
+
+    #[test]
+    fn lease_acquire_and_renew() {
+        let mut pool = pool();
+        let lease = pool.acquire("aa:01").unwrap();
+        assert_eq!(lease, Lease { address: v4("192.168.1.1"), client: String::from("aa:01"), start: 1000, expires: 4600 });
+        assert_eq!(pool.acquire("aa:02").unwrap().address, v4("192.168.1.2"));
+        pool.clock().advance(1800);
+        let renewed = pool.acquire("aa:01").unwrap();
+        assert_eq!((renewed.address, renewed.start, renewed.expires), (v4("192.168.1.1"), 1000, 6400));
+        pool.clock().advance(4000);
+        assert_eq!(pool.lease_of("aa:02"), None);
+        assert_eq!(pool.renew("aa:02").unwrap().address, v4("192.168.1.2"));
+        assert_eq!(pool.renew("aa:03"), Err(LeaseError::NoLease(String::from("aa:03"))));
+    }
+
+    #[test]
+    fn lease_sticky_and_expiry() {
+        let mut pool = pool();
+        for client in ["a", "b", "c", "d", "e", "f"] {
+            pool.acquire(client).unwrap();
+        }
+        assert!(pool.is_exhausted());
+        assert_eq!(pool.acquire("g"), Err(LeaseError::Exhausted));
+        pool.release("b").unwrap();
+        pool.clock().advance(10);
+        pool.release("e").unwrap();
+        // The longest expired address goes first, the released clients keep theirs otherwise
+        assert_eq!(pool.acquire("g").unwrap().address, v4("192.168.1.2"));
+        assert_eq!(pool.acquire("e").unwrap().address, v4("192.168.1.5"));
+        assert_eq!(pool.acquire("b"), Err(LeaseError::Exhausted));
+        pool.clock().advance(3600);
+        assert_eq!(pool.leases().count(), 0);
+        assert_eq!(pool.acquire("c").unwrap().address, v4("192.168.1.3"));
+        assert_eq!(pool.purge_expired().len(), 5);
+        assert_eq!(pool.acquire("z").unwrap().address, v4("192.168.1.1"));
+    }
+
+    #[test]
+    fn lease_exclusions_and_reservations() {
+        let mut pool = pool();
+        pool.exclude(AddressRange::from_string("192.168.1.1-192.168.1.2").unwrap());
+        assert_eq!(pool.reserve("printer", v4("192.168.1.2")), Err(LeaseError::Excluded(String::from("192.168.1.2"))));
+        assert_eq!(pool.reserve("printer", v4("192.168.1.7")), Err(LeaseError::OutsidePool(String::from("192.168.1.7"))));
+        pool.reserve("printer", v4("192.168.1.3")).unwrap();
+        assert_eq!(pool.reserve("nas", v4("192.168.1.3")), Err(LeaseError::AlreadyReserved(String::from("192.168.1.3"))));
+        assert_eq!(pool.acquire("laptop").unwrap().address, v4("192.168.1.4"));
+        assert_eq!(pool.acquire("printer").unwrap().address, v4("192.168.1.3"));
+        assert_eq!(pool.usage(), Usage { total: 6, excluded: 2, reserved: 1, leased: 2, available: 2 });
+        assert!((pool.usage().utilisation() - 50.0).abs() < 1e-9);
+        assert_eq!(pool.unreserve("printer"), Some(v4("192.168.1.3")));
+        assert_eq!(pool.usage().available, 2);
+    }
+
+    #[test]
+    fn lease_sticky_address_taken_away() {
+        let mut pool = pool();
+        assert_eq!(pool.acquire("laptop").unwrap().address, v4("192.168.1.1"));
+        assert_eq!(pool.acquire("phone").unwrap().address, v4("192.168.1.2"));
+        // Excluded while leased: no renewal, a fresh address on the next request
+        pool.exclude(AddressRange::from_string("192.168.1.1-192.168.1.1").unwrap());
+        assert_eq!(pool.renew("laptop"), Err(LeaseError::Excluded(String::from("192.168.1.1"))));
+        assert_eq!(pool.acquire("laptop").unwrap().address, v4("192.168.1.3"));
+        assert_eq!(pool.lease_at(&v4("192.168.1.1")), None);
+        // Reserved for another client after the lease expired
+        pool.release("phone").unwrap();
+        pool.reserve("printer", v4("192.168.1.2")).unwrap();
+        assert_eq!(pool.renew("phone"), Err(LeaseError::AlreadyReserved(String::from("192.168.1.2"))));
+        assert_eq!(pool.acquire("phone").unwrap().address, v4("192.168.1.4"));
+        assert_eq!(pool.acquire("printer").unwrap().address, v4("192.168.1.2"));
+    }
+}
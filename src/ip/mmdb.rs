@@ -0,0 +1,718 @@
+use std::fs;
+use std::path::Path;
+
+use super::address::Address;
+use super::{Network, Version};
+
+// Decoded data section value, see the MaxMind DB format spec 2.0
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Double(f64),
+    Bytes(Vec<u8>),
+    Uint16(u16),
+    Uint32(u32),
+    Map(Vec<(String, Value)>),
+    Int32(i32),
+    Uint64(u64),
+    Uint128(u128),
+    Array(Vec<Value>),
+    Boolean(bool),
+    Float(f32),
+}
+
+#[derive(Debug)]
+pub enum MmdbError {
+    InvalidDatabase(String),
+    // IPv6 addresses can't be looked up in an IPv4-only database
+    Ipv6InIpv4Database,
+    Io(std::io::Error),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metadata {
+    pub node_count: u32,
+    pub record_size: u16,
+    pub ip_version: u16,
+    pub database_type: String,
+    pub languages: Vec<String>,
+    pub binary_format_major_version: u16,
+    pub binary_format_minor_version: u16,
+    pub build_epoch: u64,
+    pub description: Vec<(String, String)>,
+}
+
+pub struct Reader {
+    buffer: Vec<u8>,
+    metadata: Metadata,
+    // Data section bounds in `buffer`
+    data_start: usize,
+    data_end: usize,
+    // Record reached after 96 zero bits, where IPv4 lives in an IPv6 tree
+    ipv4_start: u32,
+    ipv4_start_depth: u8,
+}
+
+const METADATA_MARKER: &[u8] = b"\xab\xcd\xefMaxMind.com";
+// The marker is in the last 128KiB of the file
+const METADATA_MAX_SIZE: usize = 128 * 1024;
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    // Walks nested maps, e.g. ["country", "iso_code"]
+    pub fn path(&self, keys: &[&str]) -> Option<&Value> {
+        keys.iter().try_fold(self, |value, key| value.get(key))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    // Any unsigned integer that fits
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Uint16(value) => Some((*value).into()),
+            Value::Uint32(value) => Some((*value).into()),
+            Value::Uint64(value) => Some(*value),
+            Value::Uint128(value) => u64::try_from(*value).ok(),
+            Value::Int32(value) => u64::try_from(*value).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Double(value) => Some(*value),
+            Value::Float(value) => Some((*value).into()),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+// Maps, arrays and pointers nested deeper than this are rejected. Real
+// databases stay within a handful of levels, while a pointer loop or a
+// crafted file would otherwise overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+fn invalid(message: &str) -> MmdbError {
+    MmdbError::InvalidDatabase(message.to_string())
+}
+
+// Decodes values out of the data section or the metadata, pointers are
+// relative to the start of `buffer`
+struct Decoder<'a> {
+    buffer: &'a [u8],
+}
+
+impl Decoder<'_> {
+    fn bytes(&self, offset: usize, len: usize) -> Result<&[u8], MmdbError> {
+        offset.checked_add(len)
+            .and_then(|end| self.buffer.get(offset..end))
+            .ok_or_else(|| invalid("Value runs past the end of the data section"))
+    }
+
+    fn unsigned(&self, offset: usize, len: usize, max_len: usize) -> Result<u128, MmdbError> {
+        if len > max_len {
+            return Err(invalid("Integer is too long"));
+        }
+        Ok(self.bytes(offset, len)?.iter().fold(0, |value, byte| (value << 8) | u128::from(*byte)))
+    }
+
+    // Value at the offset and the offset right after it
+    fn decode(&self, offset: usize) -> Result<(Value, usize), MmdbError> {
+        self.decode_value(offset, true, 0)
+    }
+
+    fn decode_value(&self, offset: usize, follow_pointers: bool, depth: usize) -> Result<(Value, usize), MmdbError> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("Values are nested too deeply"));
+        }
+        let control = self.bytes(offset, 1)?[0];
+        let mut offset = offset + 1;
+        let mut kind = control >> 5;
+        if kind == 0 {
+            kind = self.bytes(offset, 1)?[0].checked_add(7).ok_or_else(|| invalid("Unknown extended type"))?;
+            offset += 1;
+        }
+        if kind == 1 {
+            if !follow_pointers {
+                return Err(invalid("Pointer to a pointer"));
+            }
+            let size = usize::from((control >> 3) & 0x3);
+            let value = usize::from(control & 0x7);
+            let bytes = self.unsigned(offset, size + 1, 4)? as usize;
+            let target = match size {
+                0 => (value << 8) | bytes,
+                1 => ((value << 16) | bytes) + 2048,
+                2 => ((value << 24) | bytes) + 526336,
+                _ => bytes,
+            };
+            let (decoded, _) = self.decode_value(target, false, depth + 1)?;
+            return Ok((decoded, offset + size + 1));
+        }
+        let mut size = usize::from(control & 0x1f);
+        if size >= 29 {
+            let extra = size - 28;
+            let value = self.unsigned(offset, extra, 3)? as usize;
+            size = match extra {
+                1 => 29 + value,
+                2 => 285 + value,
+                _ => 65821 + value,
+            };
+            offset += extra;
+        }
+        let end = offset + size;
+        let value = match kind {
+            2 => Value::String(String::from_utf8(self.bytes(offset, size)?.to_vec()).map_err(|_| invalid("String isn't UTF-8"))?),
+            3 => Value::Double(f64::from_be_bytes(self.bytes(offset, size)?.try_into().map_err(|_| invalid("Double isn't 8 bytes"))?)),
+            4 => Value::Bytes(self.bytes(offset, size)?.to_vec()),
+            5 => Value::Uint16(self.unsigned(offset, size, 2)? as u16),
+            6 => Value::Uint32(self.unsigned(offset, size, 4)? as u32),
+            7 => {
+                // Every entry takes at least a byte, the size can't be trusted beyond that
+                let mut entries = Vec::with_capacity(size.min(self.buffer.len().saturating_sub(offset)));
+                let mut next = offset;
+                for _ in 0..size {
+                    let (key, after_key) = self.decode_value(next, true, depth + 1)?;
+                    let Value::String(key) = key else {
+                        return Err(invalid("Map key isn't a string"));
+                    };
+                    let (value, after_value) = self.decode_value(after_key, true, depth + 1)?;
+                    entries.push((key, value));
+                    next = after_value;
+                }
+                return Ok((Value::Map(entries), next));
+            }
+            8 => Value::Int32(self.unsigned(offset, size, 4)? as u32 as i32),
+            9 => Value::Uint64(self.unsigned(offset, size, 8)? as u64),
+            10 => Value::Uint128(self.unsigned(offset, size, 16)?),
+            11 => {
+                let mut values = Vec::with_capacity(size.min(self.buffer.len().saturating_sub(offset)));
+                let mut next = offset;
+                for _ in 0..size {
+                    let (value, after) = self.decode_value(next, true, depth + 1)?;
+                    values.push(value);
+                    next = after;
+                }
+                return Ok((Value::Array(values), next));
+            }
+            14 => return Ok((Value::Boolean(size != 0), offset)),
+            15 => Value::Float(f32::from_be_bytes(self.bytes(offset, size)?.try_into().map_err(|_| invalid("Float isn't 4 bytes"))?)),
+            _ => return Err(invalid(&format!("Unexpected data type {}", kind))),
+        };
+        Ok((value, end))
+    }
+}
+
+impl Metadata {
+    fn from_value(value: &Value) -> Result<Metadata, MmdbError> {
+        let number = |key: &str| value.get(key).and_then(Value::as_u64).ok_or_else(|| invalid(&format!("Metadata has no '{}'", key)));
+        let short = |key: &str| u16::try_from(number(key)?).map_err(|_| invalid(&format!("Metadata '{}' is out of range", key)));
+        let strings = |key: &str| -> Vec<String> {
+            value.get(key).and_then(Value::as_array).unwrap_or_default().iter().filter_map(Value::as_str).map(String::from).collect()
+        };
+        let description = match value.get("description") {
+            Some(Value::Map(entries)) => entries.iter()
+                .filter_map(|(language, text)| Some((language.clone(), text.as_str()?.to_string())))
+                .collect(),
+            _ => Vec::new(),
+        };
+        Ok(Metadata {
+            node_count: u32::try_from(number("node_count")?).map_err(|_| invalid("Too many nodes"))?,
+            record_size: short("record_size")?,
+            ip_version: short("ip_version")?,
+            database_type: value.get("database_type").and_then(Value::as_str).unwrap_or_default().to_string(),
+            languages: strings("languages"),
+            binary_format_major_version: short("binary_format_major_version")?,
+            binary_format_minor_version: short("binary_format_minor_version")?,
+            build_epoch: number("build_epoch")?,
+            description,
+        })
+    }
+}
+
+impl Reader {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Reader, MmdbError> {
+        Reader::from_bytes(fs::read(path).map_err(MmdbError::Io)?)
+    }
+
+    pub fn from_bytes(buffer: Vec<u8>) -> Result<Reader, MmdbError> {
+        let search_from = buffer.len().saturating_sub(METADATA_MAX_SIZE);
+        let marker = buffer[search_from..].windows(METADATA_MARKER.len())
+            .rposition(|window| window == METADATA_MARKER)
+            .map(|position| search_from + position)
+            .ok_or_else(|| invalid("No metadata marker"))?;
+        let metadata_start = marker + METADATA_MARKER.len();
+        let (value, _) = Decoder { buffer: &buffer[metadata_start..] }.decode(0)?;
+        let metadata = Metadata::from_value(&value)?;
+        if metadata.binary_format_major_version != 2 {
+            return Err(invalid("Only version 2 databases are supported"));
+        }
+        if ![24, 28, 32].contains(&metadata.record_size) {
+            return Err(invalid("Record size must be 24, 28 or 32 bits"));
+        }
+        if ![4, 6].contains(&metadata.ip_version) {
+            return Err(invalid("IP version must be 4 or 6"));
+        }
+        let tree_size = metadata.node_count as usize * usize::from(metadata.record_size) / 4;
+        let data_start = tree_size + 16;
+        if data_start > marker {
+            return Err(invalid("Search tree is larger than the file"));
+        }
+        let mut reader = Reader { buffer, metadata, data_start, data_end: marker, ipv4_start: 0, ipv4_start_depth: 0 };
+        if reader.metadata.ip_version == 6 {
+            let mut node = 0;
+            let mut depth = 0;
+            while depth < 96 && node < reader.metadata.node_count {
+                node = reader.record(node, 0)?;
+                depth += 1;
+            }
+            reader.ipv4_start = node;
+            reader.ipv4_start_depth = depth;
+        }
+        Ok(reader)
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn version(&self) -> Version {
+        if self.metadata.ip_version == 6 { Version::IpV6 } else { Version::IpV4 }
+    }
+
+    // Left (0) or right (1) record of the node
+    fn record(&self, node: u32, side: u8) -> Result<u32, MmdbError> {
+        let record_size = usize::from(self.metadata.record_size);
+        let start = node as usize * record_size / 4;
+        let bytes = self.buffer.get(start..start + record_size / 4).ok_or_else(|| invalid("Node outside the search tree"))?;
+        let read = |bytes: &[u8]| bytes.iter().fold(0_u32, |value, byte| (value << 8) | u32::from(*byte));
+        Ok(match (record_size, side) {
+            (24, 0) => read(&bytes[0..3]),
+            (24, _) => read(&bytes[3..6]),
+            // The middle byte holds the high nibbles of both records
+            (28, 0) => (u32::from(bytes[3] & 0xf0) << 20) | read(&bytes[0..3]),
+            (28, _) => (u32::from(bytes[3] & 0x0f) << 24) | read(&bytes[4..7]),
+            (_, 0) => read(&bytes[0..4]),
+            (_, _) => read(&bytes[4..8]),
+        })
+    }
+
+    fn resolve(&self, record: u32) -> Result<Value, MmdbError> {
+        let offset = (record - self.metadata.node_count) as usize;
+        let offset = offset.checked_sub(16).ok_or_else(|| invalid("Record points into the separator"))?;
+        let decoder = Decoder { buffer: &self.buffer[self.data_start..self.data_end] };
+        Ok(decoder.decode(offset)?.0)
+    }
+
+    // Record of the most specific network holding the address, and that network
+    pub fn lookup(&self, address: &dyn Address) -> Result<Option<(Network, Value)>, MmdbError> {
+        let address = address.as_some();
+        let bits_number = address.version().address_bits();
+        let (mut node, mut depth) = match (address.version(), self.metadata.ip_version) {
+            (Version::IpV6, 4) => return Err(MmdbError::Ipv6InIpv4Database),
+            (Version::IpV4, 6) => (self.ipv4_start, 0),
+            _ => (0, 0),
+        };
+        let bits = address.to_bits();
+        while depth < bits_number && node < self.metadata.node_count {
+            node = self.record(node, ((bits >> (bits_number - 1 - depth)) & 1) as u8)?;
+            depth += 1;
+        }
+        match node.cmp(&self.metadata.node_count) {
+            std::cmp::Ordering::Equal => Ok(None),
+            std::cmp::Ordering::Less => Err(invalid("Search tree is deeper than the address")),
+            std::cmp::Ordering::Greater => Ok(Some((Network::from_bits(address.version(), bits, depth), self.resolve(node)?))),
+        }
+    }
+
+    // Every network with a record, depth first in address order. In IPv6
+    // databases IPv4 space shows up once, as ::a.b.c.d/len, even when the
+    // tree aliases it under other prefixes.
+    pub fn networks(&self) -> Networks<'_> {
+        Networks { reader: self, stack: vec![(0, 0, 0)] }
+    }
+}
+
+pub struct Networks<'a> {
+    reader: &'a Reader,
+    // Record reached, its first address and the prefix length it stands for
+    stack: Vec<(u32, u128, u8)>,
+}
+
+impl Networks<'_> {
+    // The IPv4 subtree of an IPv6 database hangs under ::/96 and is often
+    // aliased under ::ffff:0:0/96 and 2002::/16 as well
+    fn is_alias(&self, record: u32, bits: u128, depth: u8) -> bool {
+        let reader = self.reader;
+        reader.metadata.ip_version == 6 && reader.ipv4_start_depth == 96 && reader.ipv4_start < reader.metadata.node_count
+            && record == reader.ipv4_start && !(bits == 0 && depth == 96)
+    }
+}
+
+impl Iterator for Networks<'_> {
+    type Item = Result<(Network, Value), MmdbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let reader = self.reader;
+        let width = reader.version().address_bits();
+        while let Some((record, bits, depth)) = self.stack.pop() {
+            if record > reader.metadata.node_count {
+                let network = Network::from_bits(reader.version(), bits, depth);
+                return Some(reader.resolve(record).map(|value| (network, value)));
+            }
+            if record == reader.metadata.node_count {
+                continue;
+            }
+            if depth >= width {
+                return Some(Err(invalid("Search tree is deeper than the address")));
+            }
+            // Right first so the left half comes out first
+            for side in [1, 0] {
+                let child = match reader.record(record, side) {
+                    Ok(child) => child,
+                    Err(error) => return Some(Err(error)),
+                };
+                let child_bits = bits | (u128::from(side) << (width - 1 - depth));
+                if !self.is_alias(child, child_bits, depth + 1) {
+                    self.stack.push((child, child_bits, depth + 1));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::address::{AddressV4, AddressV6};
+
+    // Minimal MMDB writer for fixtures. Strings longer than three bytes are
+    // written once and pointed to afterwards, like real databases do.
+    #[derive(Default)]
+    struct Writer {
+        data: Vec<u8>,
+        strings: Vec<(String, usize)>,
+    }
+
+    fn trimmed(bytes: &[u8]) -> &[u8] {
+        &bytes[bytes.iter().position(|byte| *byte != 0).unwrap_or(bytes.len())..]
+    }
+
+    impl Writer {
+        fn control(&mut self, kind: u8, size: usize) {
+            let (bits, extra) = match size {
+                0..=28 => (size as u8, Vec::new()),
+                29..=284 => (29, vec![(size - 29) as u8]),
+                285..=65820 => (30, ((size - 285) as u16).to_be_bytes().to_vec()),
+                _ => (31, ((size - 65821) as u32).to_be_bytes()[1..].to_vec()),
+            };
+            if kind <= 7 {
+                self.data.push((kind << 5) | bits);
+            } else {
+                self.data.extend([bits, kind - 7]);
+            }
+            self.data.extend(extra);
+        }
+
+        fn pointer(&mut self, target: usize) {
+            if target < 2048 {
+                self.data.extend([0x20 | (target >> 8) as u8, target as u8]);
+            } else {
+                let target = target - 2048;
+                self.data.extend([0x28 | (target >> 16) as u8, (target >> 8) as u8, target as u8]);
+            }
+        }
+
+        fn write(&mut self, value: &Value) {
+            match value {
+                Value::String(text) => {
+                    if text.len() > 3 {
+                        if let Some((_, offset)) = self.strings.iter().find(|(seen, _)| seen == text) {
+                            let offset = *offset;
+                            return self.pointer(offset);
+                        }
+                        self.strings.push((text.clone(), self.data.len()));
+                    }
+                    self.control(2, text.len());
+                    self.data.extend(text.as_bytes());
+                }
+                Value::Double(value) => {
+                    self.control(3, 8);
+                    self.data.extend(value.to_be_bytes());
+                }
+                Value::Bytes(bytes) => {
+                    self.control(4, bytes.len());
+                    self.data.extend(bytes);
+                }
+                Value::Uint16(value) => self.integer(5, trimmed(&value.to_be_bytes())),
+                Value::Uint32(value) => self.integer(6, trimmed(&value.to_be_bytes())),
+                Value::Map(entries) => {
+                    self.control(7, entries.len());
+                    for (key, value) in entries {
+                        self.write(&Value::String(key.clone()));
+                        self.write(value);
+                    }
+                }
+                Value::Int32(value) => self.integer(8, &value.to_be_bytes()),
+                Value::Uint64(value) => self.integer(9, trimmed(&value.to_be_bytes())),
+                Value::Uint128(value) => self.integer(10, trimmed(&value.to_be_bytes())),
+                Value::Array(values) => {
+                    self.control(11, values.len());
+                    for value in values {
+                        self.write(value);
+                    }
+                }
+                Value::Boolean(value) => self.control(14, usize::from(*value)),
+                Value::Float(value) => {
+                    self.control(15, 4);
+                    self.data.extend(value.to_be_bytes());
+                }
+            }
+        }
+
+        fn integer(&mut self, kind: u8, bytes: &[u8]) {
+            self.control(kind, bytes.len());
+            self.data.extend(bytes);
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    enum Slot {
+        Empty,
+        Node(usize),
+        Data(usize),
+    }
+
+    fn insert(nodes: &mut Vec<[Slot; 2]>, bits: u128, len: u8, slot: Slot) {
+        let mut node = 0;
+        for depth in 0..len {
+            let side = ((bits >> (127 - depth)) & 1) as usize;
+            if depth + 1 == len {
+                nodes[node][side] = slot;
+            } else if let Slot::Node(next) = nodes[node][side] {
+                node = next;
+            } else {
+                nodes.push([Slot::Empty; 2]);
+                nodes[node][side] = Slot::Node(nodes.len() - 1);
+                node = nodes.len() - 1;
+            }
+        }
+    }
+
+    fn string(text: &str) -> Value {
+        Value::String(text.to_string())
+    }
+
+    fn map(entries: Vec<(&str, Value)>) -> Value {
+        Value::Map(entries.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    // Database of non-overlapping networks. In IPv6 databases IPv4 networks
+    // go under ::/96 and `ipv4_alias` also links them under ::ffff:0:0/96.
+    fn build(ip_version: u16, record_size: u16, entries: &[(&str, Value)], ipv4_alias: bool) -> Vec<u8> {
+        let mut nodes = vec![[Slot::Empty; 2]];
+        let mut writer = Writer::default();
+        let mut offsets = Vec::new();
+        for (index, (network, value)) in entries.iter().enumerate() {
+            let network = Network::from_string(network).unwrap();
+            let width = if ip_version == 6 { 128 } else { 32 };
+            let len = network.prefix_len() + width - network.version().address_bits();
+            // Left align so the insert always walks from bit 127
+            insert(&mut nodes, network.first_bits() << (128 - u32::from(width)), len, Slot::Data(index));
+            offsets.push(writer.data.len());
+            writer.write(value);
+        }
+        if ipv4_alias {
+            let mut ipv4 = 0;
+            for _ in 0..96 {
+                let Slot::Node(next) = nodes[ipv4][0] else { panic!("No IPv4 subtree") };
+                ipv4 = next;
+            }
+            insert(&mut nodes, 0xffff << 32, 96, Slot::Node(ipv4));
+        }
+
+        let node_count = nodes.len() as u32;
+        let record = |slot: Slot| match slot {
+            Slot::Empty => node_count,
+            Slot::Node(node) => node as u32,
+            Slot::Data(index) => node_count + 16 + offsets[index] as u32,
+        };
+        let mut file = Vec::new();
+        for [left, right] in nodes {
+            let (left, right) = (record(left).to_be_bytes(), record(right).to_be_bytes());
+            match record_size {
+                24 => file.extend(left[1..].iter().chain(&right[1..])),
+                28 => {
+                    file.extend(&left[1..]);
+                    file.push((left[0] << 4) | right[0]);
+                    file.extend(&right[1..]);
+                }
+                _ => file.extend(left.iter().chain(&right)),
+            }
+        }
+        file.extend([0; 16]);
+        file.extend(writer.data);
+        file.extend(METADATA_MARKER);
+        let mut metadata = Writer::default();
+        metadata.write(&map(vec![
+            ("node_count", Value::Uint32(node_count)),
+            ("record_size", Value::Uint16(record_size)),
+            ("ip_version", Value::Uint16(ip_version)),
+            ("database_type", string("Test-City")),
+            ("languages", Value::Array(vec![string("en"), string("de")])),
+            ("binary_format_major_version", Value::Uint16(2)),
+            ("binary_format_minor_version", Value::Uint16(0)),
+            ("build_epoch", Value::Uint64(1_700_000_000)),
+            ("description", map(vec![("en", string("Test database"))])),
+        ]));
+        file.extend(metadata.data);
+        file
+    }
+
+    fn city(code: &str, name: &str) -> Value {
+        map(vec![
+            ("country", map(vec![("iso_code", string(code)), ("names", map(vec![("en", string(name))]))])),
+            ("location", map(vec![("latitude", Value::Double(-33.5)), ("accuracy_radius", Value::Uint16(100))])),
+        ])
+    }
+
+    #[test]
+    fn mmdb_ipv4_lookup() {
+        let entries = [
+            ("1.0.0.0/24", city("AU", "Australia")),
+            ("10.0.0.0/8", city("ZZ", "Private")),
+            ("192.168.1.128/25", city("DE", "Germany")),
+        ];
+        for record_size in [24, 28, 32] {
+            let reader = Reader::from_bytes(build(4, record_size, &entries, false)).unwrap();
+            assert_eq!(reader.metadata().record_size, record_size);
+            let (network, record) = reader.lookup(&AddressV4::from_string("1.0.0.77").unwrap()).unwrap().unwrap();
+            assert_eq!(network, Network::from_string("1.0.0.0/24").unwrap());
+            assert_eq!(record.path(&["country", "iso_code"]).and_then(Value::as_str), Some("AU"));
+            assert_eq!(record, city("AU", "Australia"));
+            let (network, record) = reader.lookup(&AddressV4::from_string("192.168.1.200").unwrap()).unwrap().unwrap();
+            assert_eq!(network.to_string(), "192.168.1.128/25");
+            assert_eq!(record.path(&["location", "accuracy_radius"]).and_then(Value::as_u64), Some(100));
+            assert!(reader.lookup(&AddressV4::from_string("192.168.1.1").unwrap()).unwrap().is_none());
+            assert!(matches!(reader.lookup(&AddressV6::from_string("::1").unwrap()), Err(MmdbError::Ipv6InIpv4Database)));
+            let networks: Vec<String> = reader.networks().map(|entry| entry.unwrap().0.to_string()).collect();
+            assert_eq!(networks, vec!["1.0.0.0/24", "10.0.0.0/8", "192.168.1.128/25"]);
+        }
+    }
+
+    #[test]
+    fn mmdb_ipv6_with_ipv4_subtree() {
+        let entries = [
+            ("10.0.0.0/8", city("ZZ", "Private")),
+            ("2001:db8::/32", city("ZZ", "Documentation")),
+            ("2a00::/12", city("EU", "Europe")),
+        ];
+        let reader = Reader::from_bytes(build(6, 28, &entries, true)).unwrap();
+        let (network, record) = reader.lookup(&AddressV4::from_string("10.2.3.4").unwrap()).unwrap().unwrap();
+        assert_eq!(network, Network::from_string("10.0.0.0/8").unwrap());
+        assert_eq!(record.path(&["country", "names", "en"]).and_then(Value::as_str), Some("Private"));
+        let mapped = AddressV6::from_string("::ffff:a02:304").unwrap();
+        assert_eq!(reader.lookup(&mapped).unwrap().unwrap().0, Network::from_string("::ffff:a00:0/104").unwrap());
+        let (network, _) = reader.lookup(&AddressV6::from_string("2001:db8:1::1").unwrap()).unwrap().unwrap();
+        assert_eq!(network, Network::from_string("2001:db8::/32").unwrap());
+        assert!(reader.lookup(&AddressV6::from_string("2001:db9::1").unwrap()).unwrap().is_none());
+        assert!(reader.lookup(&AddressV4::from_string("11.0.0.1").unwrap()).unwrap().is_none());
+        // The alias under ::ffff:0:0/96 is skipped
+        let networks: Vec<Network> = reader.networks().map(|entry| entry.unwrap().0).collect();
+        assert_eq!(networks, vec![
+            Network::from_string("::a00:0/104").unwrap(),
+            Network::from_string("2001:db8::/32").unwrap(),
+            Network::from_string("2a00::/12").unwrap(),
+        ]);
+    }
+
+    #[test]
+    fn mmdb_data_types_and_metadata() {
+        let long = "x".repeat(300);
+        let record = map(vec![
+            ("string", string("Zürich")),
+            ("repeated", string("Zürich")),
+            ("long", string(&long)),
+            ("double", Value::Double(47.376)),
+            ("bytes", Value::Bytes(vec![0, 1, 254, 255])),
+            ("uint16", Value::Uint16(0)),
+            ("uint32", Value::Uint32(70_000)),
+            ("int32", Value::Int32(-42)),
+            ("uint64", Value::Uint64(u64::MAX)),
+            ("uint128", Value::Uint128(1 << 100)),
+            ("array", Value::Array(vec![Value::Boolean(true), Value::Boolean(false), Value::Float(1.5)])),
+            ("empty", map(Vec::new())),
+        ]);
+        let file = build(4, 24, &[("0.0.0.0/1", record.clone())], false);
+        let reader = Reader::from_bytes(file).unwrap();
+        let (network, decoded) = reader.lookup(&AddressV4::from_string("127.0.0.1").unwrap()).unwrap().unwrap();
+        assert_eq!(network.to_string(), "0.0.0.0/1");
+        assert_eq!(decoded, record);
+        assert_eq!(decoded.get("int32").and_then(Value::as_u64), None);
+        assert_eq!(decoded.get("uint128").and_then(Value::as_u64), None);
+        assert_eq!(decoded.get("array").and_then(Value::as_array).map(|values| values[2].as_f64()), Some(Some(1.5)));
+        let metadata = reader.metadata();
+        assert_eq!((metadata.node_count, metadata.ip_version), (1, 4));
+        assert_eq!(metadata.database_type, "Test-City");
+        assert_eq!(metadata.languages, vec!["en", "de"]);
+        assert_eq!(metadata.build_epoch, 1_700_000_000);
+        assert_eq!(metadata.description, vec![(String::from("en"), String::from("Test database"))]);
+    }
+
+    #[test]
+    fn mmdb_open_and_invalid() {
+        let path = std::env::temp_dir().join(format!("ip-network-mmdb-{}.mmdb", std::process::id()));
+        fs::write(&path, build(4, 32, &[("1.0.0.0/24", city("AU", "Australia"))], false)).unwrap();
+        let reader = Reader::open(&path).unwrap();
+        assert_eq!(reader.networks().count(), 1);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(Reader::open(&path), Err(MmdbError::Io(_))));
+        assert!(matches!(Reader::from_bytes(b"not a database".to_vec()), Err(MmdbError::InvalidDatabase(_))));
+        let mut file = build(4, 24, &[("1.0.0.0/24", city("AU", "Australia"))], false);
+        // Point the first record past the data section
+        file[0..3].copy_from_slice(&[0xff, 0xff, 0xff]);
+        let reader = Reader::from_bytes(file).unwrap();
+        assert!(matches!(reader.lookup(&AddressV4::from_string("1.0.0.1").unwrap()), Err(MmdbError::InvalidDatabase(_))));
+    }
+
+    #[test]
+    fn mmdb_rejects_loops_and_deep_nesting() {
+        // A map whose value points back at the map itself
+        let mut file = build(4, 24, &[("1.0.0.0/24", map(vec![("a", Value::Uint16(1))]))], false);
+        let data = file.windows(5).position(|window| window == [0xe1, 0x41, 0x61, 0xa1, 0x01]).unwrap();
+        file[data + 3..data + 5].copy_from_slice(&[0x20, 0x00]);
+        let reader = Reader::from_bytes(file).unwrap();
+        assert!(matches!(reader.lookup(&AddressV4::from_string("1.0.0.1").unwrap()), Err(MmdbError::InvalidDatabase(_))));
+
+        let nested = |levels: usize| (0..levels).fold(Value::Boolean(true), |value, _| Value::Array(vec![value]));
+        let reader = Reader::from_bytes(build(4, 24, &[("1.0.0.0/24", nested(MAX_DEPTH))], false)).unwrap();
+        assert_eq!(reader.lookup(&AddressV4::from_string("1.0.0.1").unwrap()).unwrap().unwrap().1, nested(MAX_DEPTH));
+        let reader = Reader::from_bytes(build(4, 24, &[("1.0.0.0/24", nested(MAX_DEPTH + 1))], false)).unwrap();
+        assert!(matches!(reader.lookup(&AddressV4::from_string("1.0.0.1").unwrap()), Err(MmdbError::InvalidDatabase(_))));
+    }
+}
@@ -1,8 +1,30 @@
+pub mod acl;
 pub mod address;
+pub mod aggregate;
 pub mod bitmask;
+pub mod compiled;
+pub mod conflicts;
+pub mod delegation;
+pub mod free_space;
+pub mod hierarchy;
+pub mod ipam;
+pub mod iter;
+pub mod lease;
+pub mod mmdb;
 pub mod network;
+pub mod planner;
+pub mod prefix_map;
+pub mod range;
+pub mod range_map;
+pub mod registry;
+pub mod set;
+pub mod summarise;
+pub mod summary;
 pub mod types;
 pub mod header;
+mod json;
+#[cfg(test)]
+mod test_helpers;
 
 pub use network::*;
 pub use types::*;
\ No newline at end of file
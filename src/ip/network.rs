@@ -1,50 +1,571 @@
-use super::{address::Address, bitmask::Bitmask};
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use super::aggregate::{merge_ranges, range_to_networks};
+use super::summary::Summary;
+use super::{address::{Address, AddressV4, AddressV6, SomeAddress}, bitmask::Bitmask, iter::{AddressIter, Addresses, Subnets}, Version};
 
 pub struct Network {
     name: Box<dyn Address>,
     mask: Bitmask
 }
 
+#[derive(Debug, PartialEq)]
+pub enum NetworkError {
+    VersionMismatch,
+    WrongPrefixLength(String),
+    SubnetOutOfRange,
+    WrongFormat(String),
+}
+
+// How two networks are placed relative to each other. CIDR blocks can't
+// partially overlap, so they are either nested or disjoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relation {
+    Equal,
+    Contains,
+    Contained,
+    Adjacent,
+    Disjoint,
+}
+
 impl Network {
     pub fn new(name: Box<dyn Address>, mask: Bitmask) -> Network {
-        Network { 
-            name: Network::address_to_name(name, &mask), 
+        Network {
+            name: Network::address_to_name(name, &mask),
             mask
         }
     }
+    // Parses "192.168.0.0/24" and "2001:db8::/32", IPv6 in full or compressed form.
+    // Host bits are cleared, like in `new`.
+    pub fn from_string(network: &str) -> Result<Network, NetworkError> {
+        let wrong = || NetworkError::WrongFormat(format!("Can't parse network '{}'", network));
+        let (address, prefix_len) = network.trim().split_once('/').ok_or_else(wrong)?;
+        let prefix_len: u8 = prefix_len.parse().map_err(|_| wrong())?;
+        let address = if address.contains(':') {
+            SomeAddress::V6(AddressV6::from_string(address).map_err(|_| wrong())?)
+        } else {
+            if address.split('.').count() != 4 {
+                return Err(wrong());
+            }
+            SomeAddress::V4(AddressV4::from_string(address).map_err(|_| wrong())?)
+        };
+        if prefix_len > address.version().address_bits() {
+            return Err(NetworkError::WrongPrefixLength(format!("/{} is too long for {}", prefix_len, address.to_string())));
+        }
+        Ok(Network::from_bits(address.version(), address.to_bits(), prefix_len))
+    }
+
+    // Network holding the single given address, /32 for IPv4 and /128 for IPv6
+    pub fn host(address: &dyn Address) -> Network {
+        let address = address.as_some();
+        Network::from_bits(address.version(), address.to_bits(), address.version().address_bits())
+    }
+
+    pub(crate) fn from_bits(version: Version, bits: u128, prefix_len: u8) -> Network {
+        Network::new(Box::new(SomeAddress::from_bits(version, bits)), Bitmask::from_bits_number(version, prefix_len))
+    }
+
     pub fn address_to_name(addr: Box<dyn Address>, bitmask: &Bitmask) -> Box<dyn Address> {
-        return addr.apply_bitmask(bitmask);
+        addr.apply_bitmask(bitmask)
     }
 
     pub fn name(&self) -> String {
-        return self.name.to_string();
+        self.name.to_string()
     }
 
     pub fn name_as_bits(&self) -> String {
-        return self.name.to_bitstring();
+        self.name.to_bitstring()
     }
 
     pub fn mask(&self) -> String {
-        return self.mask.to_bitstring();
+        self.mask.to_bitstring()
     }
 
     pub fn addresses_number(&self) -> u128 {
-        return self.mask.addresses_number();
+        self.mask.addresses_number()
+    }
+
+    pub fn version(&self) -> Version {
+        self.mask.version()
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.mask.bits_number()
+    }
+
+    pub fn network_address(&self) -> SomeAddress {
+        self.name.as_some()
+    }
+
+    // IPv6 has no broadcast address
+    pub fn broadcast_address(&self) -> Option<SomeAddress> {
+        match self.version() {
+            Version::IpV4 => Some(SomeAddress::from_bits(Version::IpV4, self.last_bits())),
+            Version::IpV6 => None,
+        }
+    }
+
+    pub fn first_host(&self) -> Option<SomeAddress> {
+        self.hosts().next()
+    }
+
+    pub fn last_host(&self) -> Option<SomeAddress> {
+        self.hosts().next_back()
+    }
+
+    pub fn netmask(&self) -> SomeAddress {
+        SomeAddress::from_bits(self.version(), self.version().max_bits() & !self.hostmask().to_bits())
+    }
+
+    pub fn hostmask(&self) -> SomeAddress {
+        SomeAddress::from_bits(self.version(), self.last_bits() - self.first_bits())
     }
 
-    pub fn to_string(&self) -> String {
-        self.name.to_string() + "/" + &self.mask.bits_number().to_string()
+    // Saturates at u128::MAX for ::/0
+    pub fn total_addresses(&self) -> u128 {
+        (self.last_bits() - self.first_bits()).saturating_add(1)
+    }
+
+    pub fn usable_hosts(&self) -> u128 {
+        self.hosts().remaining()
+    }
+
+    pub fn summary(&self) -> Summary {
+        Summary::new(self)
+    }
+
+    // Every address of the network, including network and broadcast ones
+    pub fn addresses(&self) -> AddressIter {
+        self.iter_between(self.first_bits(), self.last_bits())
+    }
+
+    // Addresses that can be assigned to hosts. For IPv4 the network and the
+    // broadcast addresses are skipped, IPv6 has no broadcast so nothing is.
+    pub fn hosts(&self) -> AddressIter {
+        match self.version() {
+            Version::IpV4 if self.last_bits() - self.first_bits() < 2 => AddressIter::V4(Addresses::empty()),
+            Version::IpV4 => self.iter_between(self.first_bits() + 1, self.last_bits() - 1),
+            Version::IpV6 => self.addresses(),
+        }
     }
 
-    pub fn get_available_addresses(&self) -> Vec<Box<dyn Address>> {
-        let mut available_addresses: Vec<Box<dyn Address>> = Vec::new();
-        let number = self.addresses_number();
-        let mut current_address = self.name.next();
-        for _ in 0..number {
-            let next = current_address.next();
-            available_addresses.push(current_address);
-            current_address = next;
+    // Typed `addresses` and `hosts` of an IPv4 network, None for IPv6. Unlike
+    // AddressIter they have an exact length, IPv4 never exceeds 2^32 addresses.
+    pub fn addresses_v4(&self) -> Option<Addresses<AddressV4>> {
+        match self.addresses() {
+            AddressIter::V4(iter) => Some(iter),
+            AddressIter::V6(_) => None,
         }
-        return available_addresses;
     }
-}
\ No newline at end of file
+
+    pub fn hosts_v4(&self) -> Option<Addresses<AddressV4>> {
+        match self.hosts() {
+            AddressIter::V4(iter) => Some(iter),
+            AddressIter::V6(_) => None,
+        }
+    }
+
+    pub fn contains(&self, address: &dyn Address) -> Result<bool, NetworkError> {
+        let address = address.as_some();
+        if address.version() != self.version() {
+            return Err(NetworkError::VersionMismatch);
+        }
+        Ok(self.first_bits() <= address.to_bits() && address.to_bits() <= self.last_bits())
+    }
+
+    pub fn contains_network(&self, other: &Network) -> Result<bool, NetworkError> {
+        self.check_version(other)?;
+        Ok(self.first_bits() <= other.first_bits() && other.last_bits() <= self.last_bits())
+    }
+
+    pub fn overlaps(&self, other: &Network) -> Result<bool, NetworkError> {
+        self.check_version(other)?;
+        Ok(self.first_bits() <= other.last_bits() && other.first_bits() <= self.last_bits())
+    }
+
+    // Like in most tools, a network counts as a subnet and a supernet of itself
+    pub fn is_subnet_of(&self, other: &Network) -> Result<bool, NetworkError> {
+        other.contains_network(self)
+    }
+
+    pub fn is_supernet_of(&self, other: &Network) -> Result<bool, NetworkError> {
+        self.contains_network(other)
+    }
+
+    // True when the networks don't overlap and no address lies between them
+    pub fn is_adjacent_to(&self, other: &Network) -> Result<bool, NetworkError> {
+        self.check_version(other)?;
+        Ok(self.last_bits().checked_add(1) == Some(other.first_bits())
+            || other.last_bits().checked_add(1) == Some(self.first_bits()))
+    }
+
+    // All subnets with the given longer prefix, in address order
+    pub fn subnets(&self, prefix_len: u8) -> Result<Subnets, NetworkError> {
+        if prefix_len < self.mask.bits_number() || prefix_len > self.version().address_bits() {
+            return Err(NetworkError::WrongPrefixLength(format!(
+                "Prefix length must be between {} and {}", self.mask.bits_number(), self.version().address_bits()
+            )));
+        }
+        let count_bits = prefix_len - self.mask.bits_number();
+        let last_index = if count_bits == 128 { u128::MAX } else { (1_u128 << count_bits) - 1 };
+        Ok(Subnets::new(self.version(), self.first_bits(), prefix_len, last_index))
+    }
+
+    // Splits into at least `parts` equal subnets. The number of parts is
+    // rounded up to the next power of two.
+    pub fn split(&self, parts: u128) -> Result<Subnets, NetworkError> {
+        if parts == 0 {
+            return Err(NetworkError::WrongPrefixLength(format!("Network {} can't be split into 0 parts", self)));
+        }
+        let extra_bits = match parts.checked_next_power_of_two() {
+            Some(rounded) => rounded.trailing_zeros() as u8,
+            None => 128,
+        };
+        let prefix_len = self.mask.bits_number().saturating_add(extra_bits);
+        if prefix_len > self.version().address_bits() {
+            return Err(NetworkError::WrongPrefixLength(format!("Network {} can't be split into {} parts", self, parts)));
+        }
+        self.subnets(prefix_len)
+    }
+
+    // The subnet number `index` among the subnets with the given prefix length
+    pub fn subnet(&self, prefix_len: u8, index: u128) -> Result<Network, NetworkError> {
+        self.subnets(prefix_len)?.get(index).ok_or(NetworkError::SubnetOutOfRange)
+    }
+
+    pub fn supernet(&self) -> Option<Network> {
+        let prefix_len = self.mask.bits_number().checked_sub(1)?;
+        self.supernet_of_len(prefix_len).ok()
+    }
+
+    pub fn supernet_of_len(&self, prefix_len: u8) -> Result<Network, NetworkError> {
+        if prefix_len > self.mask.bits_number() {
+            return Err(NetworkError::WrongPrefixLength(format!(
+                "Supernet prefix length can't be greater than {}", self.mask.bits_number()
+            )));
+        }
+        Ok(Network::from_bits(self.version(), self.first_bits(), prefix_len))
+    }
+
+    // The adjacent network of the same size that follows this one
+    pub fn next_sibling(&self) -> Option<Network> {
+        let first = self.last_bits().checked_add(1)?;
+        if first > self.version().max_bits() {
+            return None;
+        }
+        Some(Network::from_bits(self.version(), first, self.mask.bits_number()))
+    }
+
+    // The adjacent network of the same size that precedes this one
+    pub fn prev_sibling(&self) -> Option<Network> {
+        let last = self.first_bits().checked_sub(1)?;
+        Some(Network::from_bits(self.version(), last, self.mask.bits_number()))
+    }
+
+    // Minimal sorted list of networks covering this one without `other`
+    pub fn exclude(&self, other: &Network) -> Result<Vec<Network>, NetworkError> {
+        self.exclude_all(std::slice::from_ref(other))
+    }
+
+    // Same as `exclude`, for several networks at once. Works on ranges, so
+    // IPv6 is as cheap as IPv4.
+    pub fn exclude_all(&self, others: &[Network]) -> Result<Vec<Network>, NetworkError> {
+        let mut excluded = Vec::with_capacity(others.len());
+        for other in others {
+            self.check_version(other)?;
+            excluded.push((other.version(), other.first_bits(), other.last_bits()));
+        }
+        excluded.sort_unstable();
+        let mut networks = Vec::new();
+        let mut current = Some(self.first_bits());
+        for (_, first, last) in merge_ranges(excluded) {
+            let Some(start) = current else {
+                break;
+            };
+            if last < start || first > self.last_bits() {
+                continue;
+            }
+            if first > start {
+                networks.extend(range_to_networks(self.version(), start, first - 1));
+            }
+            current = last.checked_add(1);
+        }
+        if let Some(start) = current {
+            if start <= self.last_bits() {
+                networks.extend(range_to_networks(self.version(), start, self.last_bits()));
+            }
+        }
+        Ok(networks)
+    }
+
+    fn check_version(&self, other: &Network) -> Result<(), NetworkError> {
+        if self.version() != other.version() {
+            return Err(NetworkError::VersionMismatch);
+        }
+        Ok(())
+    }
+
+    fn iter_between(&self, first: u128, last: u128) -> AddressIter {
+        match self.version() {
+            Version::IpV4 => AddressIter::V4(Addresses::from_bits(first, last)),
+            Version::IpV6 => AddressIter::V6(Addresses::from_bits(first, last)),
+        }
+    }
+
+    pub(crate) fn first_bits(&self) -> u128 {
+        self.name.as_some().to_bits()
+    }
+
+    pub(crate) fn last_bits(&self) -> u128 {
+        let host_bits = self.version().address_bits() - self.mask.bits_number();
+        if host_bits == 128 {
+            return u128::MAX;
+        }
+        self.first_bits() | ((1_u128 << host_bits) - 1)
+    }
+}
+
+pub fn relation(a: &Network, b: &Network) -> Result<Relation, NetworkError> {
+    a.check_version(b)?;
+    if a.first_bits() == b.first_bits() && a.last_bits() == b.last_bits() {
+        Ok(Relation::Equal)
+    } else if a.contains_network(b)? {
+        Ok(Relation::Contains)
+    } else if b.contains_network(a)? {
+        Ok(Relation::Contained)
+    } else if a.is_adjacent_to(b)? {
+        Ok(Relation::Adjacent)
+    } else {
+        Ok(Relation::Disjoint)
+    }
+}
+
+impl Clone for Network {
+    fn clone(&self) -> Network {
+        Network::from_bits(self.version(), self.first_bits(), self.mask.bits_number())
+    }
+}
+
+impl From<SomeAddress> for Network {
+    fn from(address: SomeAddress) -> Network {
+        Network::host(&address)
+    }
+}
+
+// Networks are equal when they cover the same addresses. IPv4 sorts before
+// IPv6, then by address, then shorter prefixes first.
+impl PartialEq for Network {
+    fn eq(&self, other: &Network) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Network {}
+
+impl PartialOrd for Network {
+    fn partial_cmp(&self, other: &Network) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Network {
+    fn cmp(&self, other: &Network) -> Ordering {
+        (self.version(), self.first_bits(), self.prefix_len()).cmp(&(other.version(), other.first_bits(), other.prefix_len()))
+    }
+}
+
+impl Hash for Network {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.version(), self.first_bits(), self.prefix_len()).hash(state);
+    }
+}
+
+impl fmt::Debug for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Network({})", self)
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.name.to_string(), self.mask.bits_number())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{address::*, bitmask::*};
+    use super::super::test_helpers::network_v4;
+
+    #[test]
+    fn network_hosts_v4() {
+        let network = network_v4("192.168.0.0", 28);
+        let hosts: Vec<String> = network.hosts().map(|address| address.to_string()).collect();
+        assert_eq!(hosts.len() as u128, network.addresses_number());
+        assert_eq!(hosts.first().unwrap(), "192.168.0.1");
+        assert_eq!(hosts.last().unwrap(), "192.168.0.14");
+        assert_eq!(network.addresses().count(), 16);
+        assert_eq!(network.hosts().next_back(), Some(SomeAddress::V4(AddressV4::from_string("192.168.0.14").unwrap())));
+        assert_eq!(network_v4("10.0.0.0", 31).hosts().count(), 0);
+        assert_eq!(network.addresses_v4().unwrap().len(), 16);
+        let mut hosts = network.hosts_v4().unwrap();
+        assert_eq!(hosts.next_back(), Some(AddressV4::from_string("192.168.0.14").unwrap()));
+        assert_eq!(hosts.len(), 13);
+        assert_eq!(network_v4("10.0.0.0", 31).hosts_v4().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn network_hosts_large() {
+        let network = network_v4("10.0.0.0", 8);
+        let mut hosts = network.hosts();
+        assert_eq!(hosts.size_hint(), (16777214, Some(16777214)));
+        assert_eq!(hosts.nth(16777213), Some(SomeAddress::V4(AddressV4::from_string("10.255.255.254").unwrap())));
+
+        let network = Network::new(Box::new(AddressV6::from_u128(0x20010db8 << 96)), Bitmask::V6(BitmaskV6::new(32).unwrap()));
+        assert!(network.hosts_v4().is_none());
+        let mut hosts = network.hosts();
+        assert_eq!(hosts.remaining(), 1 << 96);
+        assert_eq!(hosts.next_back(), Some(SomeAddress::V6(AddressV6::from_u128((0x20010db8 << 96) | ((1 << 96) - 1)))));
+    }
+
+    #[test]
+    fn network_contains() {
+        let network = network_v4("10.0.0.0", 24);
+        assert_eq!(network.contains(&AddressV4::from_string("10.0.0.255").unwrap()), Ok(true));
+        assert_eq!(network.contains(&AddressV4::from_string("10.0.1.0").unwrap()), Ok(false));
+        assert_eq!(network.contains(&AddressV6::from_u128(0)), Err(NetworkError::VersionMismatch));
+        assert_eq!(network.contains_network(&network_v4("10.0.0.128", 25)), Ok(true));
+        assert_eq!(network.is_supernet_of(&network_v4("10.0.0.0", 24)), Ok(true));
+        assert_eq!(network.is_subnet_of(&network_v4("10.0.0.0", 16)), Ok(true));
+        assert_eq!(network.overlaps(&network_v4("10.0.0.0", 8)), Ok(true));
+        assert_eq!(network.overlaps(&network_v4("10.0.1.0", 24)), Ok(false));
+    }
+
+    #[test]
+    fn network_relation() {
+        let network = network_v4("10.0.0.0", 24);
+        assert_eq!(relation(&network, &network_v4("10.0.0.0", 24)), Ok(Relation::Equal));
+        assert_eq!(relation(&network, &network_v4("10.0.0.64", 26)), Ok(Relation::Contains));
+        assert_eq!(relation(&network, &network_v4("10.0.0.0", 23)), Ok(Relation::Contained));
+        assert_eq!(relation(&network, &network_v4("10.0.1.0", 24)), Ok(Relation::Adjacent));
+        assert_eq!(relation(&network_v4("9.255.255.0", 24), &network), Ok(Relation::Adjacent));
+        assert_eq!(relation(&network, &network_v4("10.0.2.0", 24)), Ok(Relation::Disjoint));
+        let v6 = Network::new(Box::new(AddressV6::from_u128(0)), Bitmask::V6(BitmaskV6::new(0).unwrap()));
+        assert_eq!(relation(&network, &v6), Err(NetworkError::VersionMismatch));
+    }
+
+    #[test]
+    fn network_subnets() {
+        let network = network_v4("10.0.0.0", 24);
+        let subnets: Vec<String> = network.subnets(26).unwrap().map(|subnet| subnet.to_string()).collect();
+        assert_eq!(subnets, vec!["10.0.0.0/26", "10.0.0.64/26", "10.0.0.128/26", "10.0.0.192/26"]);
+        assert_eq!(network.subnets(24).unwrap().count(), 1);
+        assert_eq!(network.subnets(32).unwrap().next_back().unwrap().to_string(), "10.0.0.255/32");
+        assert!(network.subnets(23).is_err());
+        assert!(network.subnets(33).is_err());
+        let parts: Vec<String> = network.split(3).unwrap().map(|subnet| subnet.to_string()).collect();
+        assert_eq!(parts, vec!["10.0.0.0/26", "10.0.0.64/26", "10.0.0.128/26", "10.0.0.192/26"]);
+        assert_eq!(network.split(1).unwrap().count(), 1);
+        assert!(network.split(512).is_err());
+        assert!(matches!(network.split(0), Err(NetworkError::WrongPrefixLength(_))));
+        assert_eq!(network.subnet(28, 5).unwrap().to_string(), "10.0.0.80/28");
+        assert_eq!(network.subnet(28, 16).err(), Some(NetworkError::SubnetOutOfRange));
+    }
+
+    #[test]
+    fn network_subnets_v6() {
+        let network = Network::new(Box::new(AddressV6::from_u128(0xfd123456 << 96)), Bitmask::V6(BitmaskV6::new(32).unwrap()));
+        let mut subnets = network.subnets(64).unwrap();
+        assert_eq!(subnets.remaining(), 1 << 32);
+        assert_eq!(subnets.nth(0xffff_fffe).unwrap().to_string(), "fd12:3456:ffff:fffe:0000:0000:0000:0000/64");
+        assert_eq!(subnets.next().unwrap().to_string(), "fd12:3456:ffff:ffff:0000:0000:0000:0000/64");
+        assert!(subnets.next().is_none());
+    }
+
+    #[test]
+    fn network_navigation() {
+        let network = network_v4("10.0.1.0", 24);
+        assert_eq!(network.supernet().unwrap().to_string(), "10.0.0.0/23");
+        assert_eq!(network.supernet_of_len(8).unwrap().to_string(), "10.0.0.0/8");
+        assert!(network.supernet_of_len(25).is_err());
+        assert_eq!(network.next_sibling().unwrap().to_string(), "10.0.2.0/24");
+        assert_eq!(network.prev_sibling().unwrap().to_string(), "10.0.0.0/24");
+        assert!(network_v4("0.0.0.0", 0).supernet().is_none());
+        assert!(network_v4("255.255.255.0", 24).next_sibling().is_none());
+        assert!(network_v4("0.0.0.0", 24).prev_sibling().is_none());
+    }
+
+    #[test]
+    fn network_exclude() {
+        let network = network_v4("10.0.0.0", 24);
+        let rest: Vec<String> = network.exclude(&network_v4("10.0.0.64", 26)).unwrap().iter().map(|n| n.to_string()).collect();
+        assert_eq!(rest, vec!["10.0.0.0/26", "10.0.0.128/25"]);
+        assert_eq!(network.exclude(&network_v4("10.0.0.0", 8)).unwrap(), vec![]);
+        assert_eq!(network.exclude(&network_v4("10.0.1.0", 24)).unwrap(), vec![network.clone()]);
+        assert_eq!(network.exclude(&Network::from_bits(Version::IpV6, 0, 64)), Err(NetworkError::VersionMismatch));
+    }
+
+    #[test]
+    fn network_exclude_all() {
+        let everything = network_v4("0.0.0.0", 0);
+        let private = [network_v4("192.168.0.0", 16), network_v4("10.0.0.0", 8), network_v4("172.16.0.0", 12)];
+        let routes: Vec<String> = everything.exclude_all(&private).unwrap().iter().map(|n| n.to_string()).collect();
+        assert_eq!(routes, vec![
+            "0.0.0.0/5", "8.0.0.0/7", "11.0.0.0/8", "12.0.0.0/6", "16.0.0.0/4", "32.0.0.0/3", "64.0.0.0/2",
+            "128.0.0.0/3", "160.0.0.0/5", "168.0.0.0/6", "172.0.0.0/12", "172.32.0.0/11", "172.64.0.0/10",
+            "172.128.0.0/9", "173.0.0.0/8", "174.0.0.0/7", "176.0.0.0/4", "192.0.0.0/9", "192.128.0.0/11",
+            "192.160.0.0/13", "192.169.0.0/16", "192.170.0.0/15", "192.172.0.0/14", "192.176.0.0/12",
+            "192.192.0.0/10", "193.0.0.0/8", "194.0.0.0/7", "196.0.0.0/6", "200.0.0.0/5", "208.0.0.0/4",
+            "224.0.0.0/3",
+        ]);
+
+        let v6 = Network::from_bits(Version::IpV6, 0, 0);
+        let rest = v6.exclude_all(&[Network::from_bits(Version::IpV6, u128::MAX, 128), Network::from_bits(Version::IpV6, 0, 1)]).unwrap();
+        assert_eq!(rest.len(), 127);
+        assert_eq!(rest[0], Network::from_bits(Version::IpV6, 1 << 127, 2));
+        assert_eq!(rest[126], Network::from_bits(Version::IpV6, u128::MAX - 1, 128));
+    }
+
+    #[test]
+    fn network_accessors() {
+        let network = network_v4("192.168.10.77", 22);
+        assert_eq!(network.network_address().to_string(), "192.168.8.0");
+        assert_eq!(network.broadcast_address().unwrap().to_string(), "192.168.11.255");
+        assert_eq!(network.first_host().unwrap().to_string(), "192.168.8.1");
+        assert_eq!(network.last_host().unwrap().to_string(), "192.168.11.254");
+        assert_eq!(network.netmask().to_string(), "255.255.252.0");
+        assert_eq!(network.hostmask().to_string(), "0.0.3.255");
+        assert_eq!(network.prefix_len(), 22);
+        assert_eq!(network.total_addresses(), 1024);
+        assert_eq!(network.usable_hosts(), 1022);
+        assert_eq!(network_v4("10.0.0.0", 31).first_host(), None);
+
+        let v6 = Network::from_bits(Version::IpV6, 0xfd00 << 112, 64);
+        assert_eq!(v6.broadcast_address(), None);
+        assert_eq!(v6.netmask().to_bits(), 0xffff_ffff_ffff_ffff_0000_0000_0000_0000);
+        assert_eq!(v6.usable_hosts(), 1 << 64);
+        assert_eq!(Network::from_bits(Version::IpV6, 0, 0).total_addresses(), u128::MAX);
+    }
+
+    #[test]
+    fn network_from_string() {
+        assert_eq!(Network::from_string("192.168.1.77/24").unwrap(), network_v4("192.168.1.0", 24));
+        assert_eq!(Network::from_string("10.0.0.1/32").unwrap().to_string(), "10.0.0.1/32");
+        let v6 = Network::from_string("2001:0db8:0000:0000:0000:0000:0000:0000/32").unwrap();
+        assert_eq!(v6, Network::from_bits(Version::IpV6, 0x20010db8 << 96, 32));
+        assert_eq!(Network::from_string(&v6.to_string()).unwrap(), v6);
+        assert!(matches!(Network::from_string("10.0.0.0"), Err(NetworkError::WrongFormat(_))));
+        assert!(matches!(Network::from_string("10.0.0/8"), Err(NetworkError::WrongFormat(_))));
+        assert_eq!(Network::from_string("2001:db8::/32").unwrap(), v6);
+        assert!(matches!(Network::from_string("2001:db8:::/32"), Err(NetworkError::WrongFormat(_))));
+        assert!(matches!(Network::from_string("10.0.0.0/33"), Err(NetworkError::WrongPrefixLength(_))));
+    }
+
+    #[test]
+    fn network_to_string() {
+        assert_eq!(network_v4("192.168.0.17", 28).to_string(), "192.168.0.16/28");
+    }
+}
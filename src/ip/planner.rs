@@ -0,0 +1,301 @@
+use super::{bitmask::Bitmask, Network, NetworkError, Version};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Size {
+    Hosts(u128),
+    // `count` subnets of the same prefix length, e.g. point-to-point links
+    Prefix { prefix_len: u8, count: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Requirement {
+    pub name: String,
+    pub size: Size,
+}
+
+#[derive(Debug)]
+pub enum PlanError {
+    WrongRequirement(String),
+    DoesNotFit(String),
+    Network(NetworkError),
+}
+
+#[derive(Debug, Clone)]
+pub struct Allocation {
+    pub name: String,
+    pub network: Network,
+    pub pinned: bool,
+}
+
+#[derive(Debug)]
+pub struct Plan {
+    pub parent: Network,
+    pub allocations: Vec<Allocation>,
+    pub free: Vec<Network>,
+}
+
+// VLSM planner. Requirements are placed largest first, each one into the
+// smallest free aligned block it fits in, so big blocks stay unfragmented.
+pub struct Planner {
+    parent: Network,
+    requirements: Vec<Requirement>,
+    pinned: Vec<(String, Network)>,
+    headroom: u32,
+}
+
+impl Requirement {
+    pub fn hosts(name: &str, hosts: u128) -> Requirement {
+        Requirement { name: name.to_string(), size: Size::Hosts(hosts) }
+    }
+
+    pub fn prefixes(name: &str, prefix_len: u8, count: u32) -> Requirement {
+        Requirement { name: name.to_string(), size: Size::Prefix { prefix_len, count } }
+    }
+
+    // Accepts "office: 500 hosts" and "p2p links: 12 x /31" ('×' works as well)
+    pub fn from_string(requirement: &str) -> Result<Requirement, PlanError> {
+        let wrong = || PlanError::WrongRequirement(format!("Can't parse requirement '{}'", requirement));
+        let (name, size) = requirement.rsplit_once(':').ok_or_else(wrong)?;
+        let name = name.trim();
+        let size = size.trim();
+        if name.is_empty() {
+            return Err(wrong());
+        }
+        if let Some(hosts) = size.strip_suffix("hosts").or_else(|| size.strip_suffix("host")) {
+            let hosts = hosts.trim().parse().map_err(|_| wrong())?;
+            return Ok(Requirement::hosts(name, hosts));
+        }
+        let (count, prefix_len) = size.split_once(['x', '×']).ok_or_else(wrong)?;
+        let count = count.trim().parse().map_err(|_| wrong())?;
+        let prefix_len = prefix_len.trim().strip_prefix('/').ok_or_else(wrong)?.parse().map_err(|_| wrong())?;
+        Ok(Requirement::prefixes(name, prefix_len, count))
+    }
+}
+
+impl Plan {
+    // Share of the parent network taken by allocations, in percent
+    pub fn utilisation(&self) -> f64 {
+        let used: f64 = self.allocations.iter().map(|allocation| block_size(&allocation.network)).sum();
+        used / block_size(&self.parent) * 100.0
+    }
+}
+
+impl Planner {
+    pub fn new(parent: Network) -> Planner {
+        Planner { parent, requirements: Vec::new(), pinned: Vec::new(), headroom: 0 }
+    }
+
+    pub fn require(mut self, requirement: Requirement) -> Planner {
+        self.requirements.push(requirement);
+        self
+    }
+
+    // Keeps an already assigned subnet where it is
+    pub fn pin(mut self, name: &str, network: Network) -> Planner {
+        self.pinned.push((name.to_string(), network));
+        self
+    }
+
+    // Spare capacity added to every host requirement, in percent
+    pub fn headroom(mut self, percent: u32) -> Planner {
+        self.headroom = percent;
+        self
+    }
+
+    pub fn plan(&self) -> Result<Plan, PlanError> {
+        let version = self.parent.version();
+        let mut free = vec![(self.parent.first_bits(), self.parent.prefix_len())];
+        let mut allocations = Vec::new();
+
+        for (name, network) in &self.pinned {
+            if !self.parent.contains_network(network).map_err(PlanError::Network)? {
+                return Err(PlanError::DoesNotFit(format!("Pinned {} ({}) is outside of {}", name, network, self.parent)));
+            }
+            if !carve(&mut free, version, network.first_bits(), network.prefix_len()) {
+                return Err(PlanError::DoesNotFit(format!("Pinned {} ({}) overlaps another pinned subnet", name, network)));
+            }
+            allocations.push(Allocation { name: name.clone(), network: network.clone(), pinned: true });
+        }
+
+        let mut wanted = Vec::new();
+        for requirement in &self.requirements {
+            match requirement.size {
+                Size::Hosts(hosts) => {
+                    let hosts = hosts.checked_mul(self.headroom as u128)
+                        .and_then(|extra| hosts.checked_add(extra.div_ceil(100)))
+                        .ok_or_else(|| PlanError::DoesNotFit(format!(
+                            "{} needs {} hosts plus {}% headroom, which is more than any network can hold", requirement.name, hosts, self.headroom
+                        )))?;
+                    let prefix_len = prefix_for_hosts(version, hosts).ok_or_else(|| PlanError::DoesNotFit(format!(
+                        "{} needs {} hosts, which is more than any {:?} network can hold", requirement.name, hosts, version
+                    )))?;
+                    wanted.push((requirement.name.clone(), prefix_len));
+                }
+                Size::Prefix { prefix_len, count } => {
+                    if prefix_len > version.address_bits() {
+                        return Err(PlanError::WrongRequirement(format!("{} asks for /{} {:?} networks", requirement.name, prefix_len, version)));
+                    }
+                    // Checked before the subnets get a name each, so a huge
+                    // count fails here instead of running out of memory
+                    let capacity = free.iter()
+                        .filter(|(_, free_len)| *free_len <= prefix_len)
+                        .map(|(_, free_len)| 1_u128.checked_shl((prefix_len - free_len).into()).unwrap_or(u128::MAX))
+                        .fold(0, u128::saturating_add);
+                    if u128::from(count) > capacity {
+                        return Err(PlanError::DoesNotFit(format!(
+                            "{} needs {} x /{} but only {} fit in the free space of {}", requirement.name, count, prefix_len, capacity, self.parent
+                        )));
+                    }
+                    for i in 0..count {
+                        wanted.push((format!("{} #{}", requirement.name, i + 1), prefix_len));
+                    }
+                }
+            }
+        }
+        // Largest first, stable so equal sizes keep the caller's order
+        wanted.sort_by_key(|(_, prefix_len)| *prefix_len);
+
+        for (name, prefix_len) in wanted {
+            let best = free.iter()
+                .enumerate()
+                .filter(|(_, (_, free_len))| *free_len <= prefix_len)
+                .max_by_key(|(_, (start, free_len))| (*free_len, std::cmp::Reverse(*start)))
+                .map(|(index, _)| index);
+            let index = match best {
+                Some(index) => index,
+                None => {
+                    let largest = free.iter().map(|(_, free_len)| *free_len).min();
+                    let reason = match largest {
+                        Some(largest) => format!("the largest free block is /{}", largest),
+                        None => String::from("no free space is left"),
+                    };
+                    return Err(PlanError::DoesNotFit(format!("{} needs a /{} but {} in {}", name, prefix_len, reason, self.parent)));
+                }
+            };
+            let (start, _) = free[index];
+            carve(&mut free, version, start, prefix_len);
+            allocations.push(Allocation { name, network: Network::from_bits(version, start, prefix_len), pinned: false });
+        }
+
+        allocations.sort_by_key(|allocation| allocation.network.first_bits());
+        free.sort();
+        Ok(Plan {
+            parent: self.parent.clone(),
+            allocations,
+            free: free.into_iter().map(|(start, prefix_len)| Network::from_bits(version, start, prefix_len)).collect(),
+        })
+    }
+}
+
+// Smallest prefix length whose network has room for `hosts` hosts
+fn prefix_for_hosts(version: Version, hosts: u128) -> Option<u8> {
+    (0..=version.address_bits()).rev()
+        .find(|prefix_len| Bitmask::from_bits_number(version, *prefix_len).addresses_number() >= hosts)
+}
+
+fn block_size(network: &Network) -> f64 {
+    2_f64.powi((network.version().address_bits() - network.prefix_len()).into())
+}
+
+// Removes the block (start, prefix_len) from the free list, splitting the
+// free block that holds it in halves. Returns false if it isn't free.
+fn carve(free: &mut Vec<(u128, u8)>, version: Version, start: u128, prefix_len: u8) -> bool {
+    let holder = free.iter().position(|(free_start, free_len)| {
+        let host_bits = version.address_bits() - free_len;
+        *free_len <= prefix_len && (host_bits == 128 || start >> host_bits == free_start >> host_bits)
+    });
+    let Some(holder) = holder else {
+        return false;
+    };
+    let (mut block_start, mut block_len) = free.swap_remove(holder);
+    while block_len < prefix_len {
+        block_len += 1;
+        let half = 1_u128 << (version.address_bits() - block_len);
+        if start >= block_start + half {
+            free.push((block_start, block_len));
+            block_start += half;
+        } else {
+            free.push((block_start + half, block_len));
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_helpers::network_v4;
+
+    #[test]
+    fn requirement_from_string() {
+        assert_eq!(Requirement::from_string("office: 500 hosts").unwrap(), Requirement::hosts("office", 500));
+        assert_eq!(Requirement::from_string("p2p links: 12 × /31").unwrap(), Requirement::prefixes("p2p links", 31, 12));
+        assert_eq!(Requirement::from_string("p2p: 2 x /30").unwrap(), Requirement::prefixes("p2p", 30, 2));
+        assert!(Requirement::from_string("office 500 hosts").is_err());
+        assert!(Requirement::from_string("office: many hosts").is_err());
+    }
+
+    #[test]
+    fn plan_largest_first() {
+        let plan = Planner::new(network_v4("10.0.0.0", 22))
+            .require(Requirement::from_string("dmz: 30 hosts").unwrap())
+            .require(Requirement::from_string("office: 500 hosts").unwrap())
+            .require(Requirement::from_string("p2p: 2 x /31").unwrap())
+            .plan()
+            .unwrap();
+        let layout: Vec<(String, String)> = plan.allocations.iter()
+            .map(|allocation| (allocation.name.clone(), allocation.network.to_string()))
+            .collect();
+        assert_eq!(layout, vec![
+            (String::from("office"), String::from("10.0.0.0/23")),
+            (String::from("dmz"), String::from("10.0.2.0/27")),
+            (String::from("p2p #1"), String::from("10.0.2.32/31")),
+            (String::from("p2p #2"), String::from("10.0.2.34/31")),
+        ]);
+        let free: Vec<String> = plan.free.iter().map(|network| network.to_string()).collect();
+        assert_eq!(free, vec!["10.0.2.36/30", "10.0.2.40/29", "10.0.2.48/28", "10.0.2.64/26", "10.0.2.128/25", "10.0.3.0/24"]);
+        assert!((plan.utilisation() - 53.515625).abs() < 1e-9);
+    }
+
+    #[test]
+    fn plan_pinned_and_headroom() {
+        let plan = Planner::new(network_v4("10.0.0.0", 24))
+            .pin("servers", network_v4("10.0.0.0", 26))
+            .require(Requirement::hosts("office", 100))
+            .headroom(20)
+            .plan()
+            .unwrap();
+        assert_eq!(plan.allocations[0].network.to_string(), "10.0.0.0/26");
+        assert!(plan.allocations[0].pinned);
+        assert_eq!(plan.allocations[1].network.to_string(), "10.0.0.128/25");
+        assert_eq!(plan.free.iter().map(|network| network.to_string()).collect::<Vec<String>>(), vec!["10.0.0.64/26"]);
+    }
+
+    #[test]
+    fn plan_does_not_fit() {
+        let error = Planner::new(network_v4("10.0.0.0", 24))
+            .require(Requirement::hosts("office", 200))
+            .require(Requirement::hosts("lab", 100))
+            .plan()
+            .unwrap_err();
+        match error {
+            PlanError::DoesNotFit(reason) => assert_eq!(reason, "lab needs a /25 but no free space is left in 10.0.0.0/24"),
+            other => panic!("unexpected error {:?}", other),
+        }
+        let pinned = Planner::new(network_v4("10.0.0.0", 24)).pin("other", network_v4("10.0.1.0", 24)).plan();
+        assert!(matches!(pinned, Err(PlanError::DoesNotFit(_))));
+        let huge = Planner::new(network_v4("10.0.0.0", 24)).require(Requirement::hosts("huge", u128::MAX / 2)).headroom(300).plan();
+        assert!(matches!(huge, Err(PlanError::DoesNotFit(_))));
+        let links = Planner::new(network_v4("10.0.0.0", 24))
+            .pin("servers", network_v4("10.0.0.0", 25))
+            .require(Requirement::from_string("links: 4000000000 x /31").unwrap())
+            .plan()
+            .unwrap_err();
+        match links {
+            PlanError::DoesNotFit(reason) => assert_eq!(reason, "links needs 4000000000 x /31 but only 64 fit in the free space of 10.0.0.0/24"),
+            other => panic!("unexpected error {:?}", other),
+        }
+        let exact = Planner::new(network_v4("10.0.0.0", 24)).pin("servers", network_v4("10.0.0.0", 25)).require(Requirement::prefixes("links", 31, 64)).plan();
+        assert_eq!(exact.unwrap().allocations.len(), 65);
+    }
+}
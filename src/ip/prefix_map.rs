@@ -0,0 +1,344 @@
+use std::marker::PhantomData;
+
+use super::address::{AddressV4, AddressV6, FixedWidth};
+use super::{Network, NetworkError};
+
+// Map from networks of one family to values with longest-prefix-match
+// lookups. Stored as a path-compressed binary (Patricia) trie: every node
+// either holds a value or has two children, so the depth is bounded by the
+// number of distinct prefix lengths on a path, not by the address width.
+pub struct PrefixMap<A, V> {
+    root: Option<Box<Node<V>>>,
+    len: usize,
+    family: PhantomData<A>,
+}
+
+pub type PrefixMapV4<V> = PrefixMap<AddressV4, V>;
+pub type PrefixMapV6<V> = PrefixMap<AddressV6, V>;
+
+// Keys are aligned to the top of u128 so both families walk the same bits
+struct Node<V> {
+    key: u128,
+    len: u8,
+    value: Option<V>,
+    children: [Option<Box<Node<V>>>; 2],
+}
+
+impl<V> Node<V> {
+    fn leaf(key: u128, len: u8, value: V) -> Box<Node<V>> {
+        Box::new(Node { key, len, value: Some(value), children: [None, None] })
+    }
+}
+
+fn mask(len: u8) -> u128 {
+    if len == 0 { 0 } else { u128::MAX << (128 - len) }
+}
+
+fn bit(key: u128, position: u8) -> usize {
+    ((key >> (127 - position)) & 1) as usize
+}
+
+fn common_len(a: u128, a_len: u8, b: u128, b_len: u8) -> u8 {
+    ((a ^ b).leading_zeros() as u8).min(a_len).min(b_len)
+}
+
+impl<A: FixedWidth, V> PrefixMap<A, V> {
+    pub fn new() -> PrefixMap<A, V> {
+        PrefixMap { root: None, len: 0, family: PhantomData }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Returns the previous value stored for exactly this network
+    pub fn insert(&mut self, network: &Network, value: V) -> Result<Option<V>, NetworkError> {
+        let (key, len) = Self::key(network)?;
+        let previous = Self::insert_at(&mut self.root, key, len, value);
+        if previous.is_none() {
+            self.len += 1;
+        }
+        Ok(previous)
+    }
+
+    fn insert_at(slot: &mut Option<Box<Node<V>>>, key: u128, len: u8, value: V) -> Option<V> {
+        let Some(node) = slot else {
+            *slot = Some(Node::leaf(key, len, value));
+            return None;
+        };
+        let common = common_len(node.key, node.len, key, len);
+        if common == node.len && common == len {
+            return node.value.replace(value);
+        }
+        if common == node.len {
+            return Self::insert_at(&mut node.children[bit(key, node.len)], key, len, value);
+        }
+        // The new prefix branches off in the middle of this node's path
+        let old = slot.take().unwrap();
+        let old_side = bit(old.key, common);
+        let mut branch = Box::new(Node { key: key & mask(common), len: common, value: None, children: [None, None] });
+        if common == len {
+            branch.value = Some(value);
+        } else {
+            branch.children[bit(key, common)] = Some(Node::leaf(key, len, value));
+        }
+        branch.children[old_side] = Some(old);
+        *slot = Some(branch);
+        None
+    }
+
+    pub fn remove(&mut self, network: &Network) -> Result<Option<V>, NetworkError> {
+        let (key, len) = Self::key(network)?;
+        let removed = Self::remove_at(&mut self.root, key, len);
+        if removed.is_some() {
+            self.len -= 1;
+        }
+        Ok(removed)
+    }
+
+    fn remove_at(slot: &mut Option<Box<Node<V>>>, key: u128, len: u8) -> Option<V> {
+        let node = slot.as_mut()?;
+        if node.len > len || (node.key ^ key) & mask(node.len) != 0 {
+            return None;
+        }
+        let removed = if node.len == len {
+            node.value.take()
+        } else {
+            Self::remove_at(&mut node.children[bit(key, node.len)], key, len)
+        };
+        // Nodes without a value are only kept while they branch
+        if node.value.is_none() {
+            match (node.children[0].is_some(), node.children[1].is_some()) {
+                (false, false) => *slot = None,
+                (true, false) => *slot = node.children[0].take(),
+                (false, true) => *slot = node.children[1].take(),
+                (true, true) => {}
+            }
+        }
+        removed
+    }
+
+    pub fn get(&self, network: &Network) -> Result<Option<&V>, NetworkError> {
+        let (key, len) = Self::key(network)?;
+        let mut current = self.root.as_deref();
+        while let Some(node) = current {
+            if node.len > len || (node.key ^ key) & mask(node.len) != 0 {
+                return Ok(None);
+            }
+            if node.len == len {
+                return Ok(node.value.as_ref());
+            }
+            current = node.children[bit(key, node.len)].as_deref();
+        }
+        Ok(None)
+    }
+
+    pub fn contains_key(&self, network: &Network) -> Result<bool, NetworkError> {
+        Ok(self.get(network)?.is_some())
+    }
+
+    // The most specific network that holds the address
+    pub fn longest_match(&self, address: &A) -> Option<(Network, &V)> {
+        let key = address.to_bits() << (128 - A::BITS);
+        let mut best = None;
+        let mut current = self.root.as_deref();
+        while let Some(node) = current {
+            if (node.key ^ key) & mask(node.len) != 0 {
+                break;
+            }
+            if let Some(value) = &node.value {
+                best = Some((node, value));
+            }
+            if node.len == 128 {
+                break;
+            }
+            current = node.children[bit(key, node.len)].as_deref();
+        }
+        best.map(|(node, value)| (Self::network(node), value))
+    }
+
+    // Every stored network that contains the given one, shortest first
+    pub fn covering(&self, network: &Network) -> Result<impl Iterator<Item = (Network, &V)>, NetworkError> {
+        let (key, len) = Self::key(network)?;
+        let mut found = Vec::new();
+        let mut current = self.root.as_deref();
+        while let Some(node) = current {
+            if node.len > len || (node.key ^ key) & mask(node.len) != 0 {
+                break;
+            }
+            if let Some(value) = &node.value {
+                found.push((Self::network(node), value));
+            }
+            if node.len == len {
+                break;
+            }
+            current = node.children[bit(key, node.len)].as_deref();
+        }
+        Ok(found.into_iter())
+    }
+
+    // Every stored network inside the given one, in order
+    pub fn covered(&self, network: &Network) -> Result<Iter<'_, A, V>, NetworkError> {
+        let (key, len) = Self::key(network)?;
+        let mut current = self.root.as_deref();
+        while let Some(node) = current {
+            if node.len >= len {
+                if (node.key ^ key) & mask(len) == 0 {
+                    return Ok(Iter::new(Some(node)));
+                }
+                break;
+            }
+            if (node.key ^ key) & mask(node.len) != 0 {
+                break;
+            }
+            current = node.children[bit(key, node.len)].as_deref();
+        }
+        Ok(Iter::new(None))
+    }
+
+    // All entries ordered like networks are: by address, shorter prefixes first
+    pub fn iter(&self) -> Iter<'_, A, V> {
+        Iter::new(self.root.as_deref())
+    }
+
+    fn key(network: &Network) -> Result<(u128, u8), NetworkError> {
+        if network.version() != A::VERSION {
+            return Err(NetworkError::VersionMismatch);
+        }
+        Ok((network.first_bits() << (128 - A::BITS), network.prefix_len()))
+    }
+
+    fn network(node: &Node<V>) -> Network {
+        Network::from_bits(A::VERSION, node.key >> (128 - A::BITS), node.len)
+    }
+}
+
+impl<A: FixedWidth, V> Default for PrefixMap<A, V> {
+    fn default() -> PrefixMap<A, V> {
+        PrefixMap::new()
+    }
+}
+
+// Pre-order walk, the lower child first
+pub struct Iter<'a, A, V> {
+    stack: Vec<&'a Node<V>>,
+    family: PhantomData<A>,
+}
+
+impl<'a, A, V> Iter<'a, A, V> {
+    fn new(root: Option<&'a Node<V>>) -> Iter<'a, A, V> {
+        Iter { stack: root.into_iter().collect(), family: PhantomData }
+    }
+}
+
+impl<'a, A: FixedWidth, V> Iterator for Iter<'a, A, V> {
+    type Item = (Network, &'a V);
+
+    fn next(&mut self) -> Option<(Network, &'a V)> {
+        while let Some(node) = self.stack.pop() {
+            self.stack.extend(node.children.iter().rev().flatten().map(|child| child.as_ref()));
+            if let Some(value) = &node.value {
+                return Some((PrefixMap::<A, V>::network(node), value));
+            }
+        }
+        None
+    }
+}
+
+impl<'a, A: FixedWidth, V> IntoIterator for &'a PrefixMap<A, V> {
+    type Item = (Network, &'a V);
+    type IntoIter = Iter<'a, A, V>;
+
+    fn into_iter(self) -> Iter<'a, A, V> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{bitmask::*, Version};
+    use super::super::test_helpers::network_v4;
+
+    fn v4(address: &str) -> AddressV4 {
+        AddressV4::from_string(address).unwrap()
+    }
+
+    fn routes() -> PrefixMapV4<&'static str> {
+        let mut map = PrefixMapV4::new();
+        map.insert(&network_v4("0.0.0.0", 0), "default").unwrap();
+        map.insert(&network_v4("10.0.0.0", 8), "corp").unwrap();
+        map.insert(&network_v4("10.1.0.0", 16), "site").unwrap();
+        map.insert(&network_v4("10.1.2.0", 24), "lan").unwrap();
+        map.insert(&network_v4("10.1.2.3", 32), "host").unwrap();
+        map.insert(&network_v4("192.168.0.0", 16), "home").unwrap();
+        map
+    }
+
+    #[test]
+    fn prefix_map_insert_get() {
+        let mut map = routes();
+        assert_eq!(map.len(), 6);
+        assert_eq!(map.get(&network_v4("10.1.0.0", 16)), Ok(Some(&"site")));
+        assert_eq!(map.get(&network_v4("10.1.0.0", 17)), Ok(None));
+        assert_eq!(map.get(&network_v4("10.0.0.0", 7)), Ok(None));
+        assert_eq!(map.insert(&network_v4("10.1.0.0", 16), "branch"), Ok(Some("site")));
+        assert_eq!(map.len(), 6);
+        assert_eq!(map.insert(&Network::from_bits(Version::IpV6, 0, 0), "v6"), Err(NetworkError::VersionMismatch));
+    }
+
+    #[test]
+    fn prefix_map_longest_match() {
+        let map = routes();
+        let lookup = |address: &str| map.longest_match(&v4(address)).map(|(network, value)| (network.to_string(), *value));
+        assert_eq!(lookup("10.1.2.3"), Some((String::from("10.1.2.3/32"), "host")));
+        assert_eq!(lookup("10.1.2.4"), Some((String::from("10.1.2.0/24"), "lan")));
+        assert_eq!(lookup("10.1.3.4"), Some((String::from("10.1.0.0/16"), "site")));
+        assert_eq!(lookup("10.2.0.0"), Some((String::from("10.0.0.0/8"), "corp")));
+        assert_eq!(lookup("8.8.8.8"), Some((String::from("0.0.0.0/0"), "default")));
+        assert_eq!(PrefixMapV4::<u8>::new().longest_match(&v4("8.8.8.8")), None);
+    }
+
+    #[test]
+    fn prefix_map_remove() {
+        let mut map = routes();
+        assert_eq!(map.remove(&network_v4("10.1.0.0", 16)), Ok(Some("site")));
+        assert_eq!(map.remove(&network_v4("10.1.0.0", 16)), Ok(None));
+        assert_eq!(map.longest_match(&v4("10.1.3.4")).unwrap().1, &"corp");
+        assert_eq!(map.longest_match(&v4("10.1.2.3")).unwrap().1, &"host");
+        for network in [network_v4("0.0.0.0", 0), network_v4("10.0.0.0", 8), network_v4("10.1.2.0", 24), network_v4("10.1.2.3", 32), network_v4("192.168.0.0", 16)] {
+            assert!(map.remove(&network).unwrap().is_some());
+        }
+        assert!(map.is_empty());
+        assert!(map.root.is_none());
+    }
+
+    #[test]
+    fn prefix_map_iteration() {
+        let map = routes();
+        let all: Vec<String> = map.iter().map(|(network, _)| network.to_string()).collect();
+        assert_eq!(all, vec!["0.0.0.0/0", "10.0.0.0/8", "10.1.0.0/16", "10.1.2.0/24", "10.1.2.3/32", "192.168.0.0/16"]);
+        let covering: Vec<&str> = map.covering(&network_v4("10.1.2.0", 25)).unwrap().map(|(_, value)| *value).collect();
+        assert_eq!(covering, vec!["default", "corp", "site", "lan"]);
+        let covered: Vec<&str> = map.covered(&network_v4("10.0.0.0", 8)).unwrap().map(|(_, value)| *value).collect();
+        assert_eq!(covered, vec!["corp", "site", "lan", "host"]);
+        let covered: Vec<&str> = map.covered(&network_v4("10.1.2.0", 23)).unwrap().map(|(_, value)| *value).collect();
+        assert_eq!(covered, vec!["lan", "host"]);
+        assert_eq!(map.covered(&network_v4("172.16.0.0", 12)).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn prefix_map_v6() {
+        let mut map = PrefixMapV6::new();
+        let network = Network::new(Box::new(AddressV6::from_u128(0xfd12 << 112)), Bitmask::V6(BitmaskV6::new(16).unwrap()));
+        map.insert(&network, 1).unwrap();
+        map.insert(&Network::host(&AddressV6::from_u128(u128::MAX)), 2).unwrap();
+        assert_eq!(map.longest_match(&AddressV6::from_u128((0xfd12 << 112) | 1)).map(|(_, value)| *value), Some(1));
+        assert_eq!(map.longest_match(&AddressV6::from_u128(u128::MAX)).map(|(_, value)| *value), Some(2));
+        assert_eq!(map.longest_match(&AddressV6::from_u128(0)), None);
+    }
+}
@@ -0,0 +1,163 @@
+use std::fmt;
+
+use super::address::{AddressV4, AddressV6, FixedWidth};
+use super::aggregate::range_to_networks;
+use super::iter::Addresses;
+use super::{Network, NetworkError};
+
+// Inclusive range of addresses that doesn't have to be CIDR aligned,
+// like the `first-last` ranges of DHCP pools or GeoIP exports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AddressRange<A> {
+    first: A,
+    last: A,
+}
+
+pub type AddressRangeV4 = AddressRange<AddressV4>;
+pub type AddressRangeV6 = AddressRange<AddressV6>;
+
+#[derive(Debug, PartialEq)]
+pub enum RangeError {
+    WrongFormat(String),
+    FirstAfterLast,
+}
+
+impl<A: FixedWidth> AddressRange<A> {
+    pub fn new(first: A, last: A) -> Result<AddressRange<A>, RangeError> {
+        if first > last {
+            return Err(RangeError::FirstAfterLast);
+        }
+        Ok(AddressRange { first, last })
+    }
+
+    // Parses "10.0.0.5-10.0.0.77", spaces around the dash are allowed
+    pub fn from_string(range: &str) -> Result<AddressRange<A>, RangeError> {
+        let (first, last) = range.split_once('-')
+            .ok_or_else(|| RangeError::WrongFormat(format!("'{}' has no '-' between addresses", range)))?;
+        let parse = |address: &str| A::parse(address.trim())
+            .map_err(|error| RangeError::WrongFormat(format!("'{}' isn't an address: {}", address.trim(), error)));
+        AddressRange::new(parse(first)?, parse(last)?)
+    }
+
+    pub(crate) fn from_bits(first: u128, last: u128) -> AddressRange<A> {
+        AddressRange { first: A::from_bits(first), last: A::from_bits(last) }
+    }
+
+    pub fn first(&self) -> A {
+        self.first
+    }
+
+    pub fn last(&self) -> A {
+        self.last
+    }
+
+    // Number of addresses, never zero as `new` rejects reversed ends.
+    // Saturates at u128::MAX for the whole IPv6 space.
+    pub fn count(&self) -> u128 {
+        (self.last.to_bits() - self.first.to_bits()).saturating_add(1)
+    }
+
+    pub fn contains(&self, address: &A) -> bool {
+        self.first <= *address && *address <= self.last
+    }
+
+    pub fn contains_range(&self, other: &AddressRange<A>) -> bool {
+        self.first <= other.first && other.last <= self.last
+    }
+
+    pub fn overlaps(&self, other: &AddressRange<A>) -> bool {
+        self.first <= other.last && other.first <= self.last
+    }
+
+    pub fn intersection(&self, other: &AddressRange<A>) -> Option<AddressRange<A>> {
+        if !self.overlaps(other) {
+            return None;
+        }
+        Some(AddressRange { first: self.first.max(other.first), last: self.last.min(other.last) })
+    }
+
+    pub fn iter(&self) -> Addresses<A> {
+        Addresses::new(self.first, self.last)
+    }
+
+    // Minimal sorted list of networks covering exactly this range
+    pub fn to_networks(&self) -> Vec<Network> {
+        range_to_networks(A::VERSION, self.first.to_bits(), self.last.to_bits())
+    }
+}
+
+impl<A: FixedWidth> IntoIterator for AddressRange<A> {
+    type Item = A;
+    type IntoIter = Addresses<A>;
+
+    fn into_iter(self) -> Addresses<A> {
+        self.iter()
+    }
+}
+
+impl<A: FixedWidth> TryFrom<&Network> for AddressRange<A> {
+    type Error = NetworkError;
+
+    fn try_from(network: &Network) -> Result<AddressRange<A>, NetworkError> {
+        if network.version() != A::VERSION {
+            return Err(NetworkError::VersionMismatch);
+        }
+        Ok(AddressRange::from_bits(network.first_bits(), network.last_bits()))
+    }
+}
+
+impl<A: FixedWidth> fmt::Display for AddressRange<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.first.to_string(), self.last.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{bitmask::*, Version};
+
+    fn v4(address: &str) -> AddressV4 {
+        AddressV4::from_string(address).unwrap()
+    }
+
+    #[test]
+    fn range_from_string() {
+        let range = AddressRangeV4::from_string("10.0.0.5 - 10.0.0.77").unwrap();
+        assert_eq!(range.first(), v4("10.0.0.5"));
+        assert_eq!(range.last(), v4("10.0.0.77"));
+        assert_eq!(range.count(), 73);
+        assert_eq!(range.to_string(), "10.0.0.5-10.0.0.77");
+        assert_eq!(AddressRangeV4::from_string("10.0.0.77-10.0.0.5"), Err(RangeError::FirstAfterLast));
+        assert!(matches!(AddressRangeV4::from_string("10.0.0.5"), Err(RangeError::WrongFormat(_))));
+        assert!(matches!(AddressRangeV4::from_string("10.0.0.5-10.0.x.7"), Err(RangeError::WrongFormat(_))));
+    }
+
+    #[test]
+    fn range_queries() {
+        let range = AddressRangeV4::from_string("10.0.0.5-10.0.0.77").unwrap();
+        let other = AddressRangeV4::from_string("10.0.0.70-10.0.1.0").unwrap();
+        assert!(range.contains(&v4("10.0.0.77")));
+        assert!(!range.contains(&v4("10.0.0.78")));
+        assert!(!range.contains_range(&other));
+        assert_eq!(range.intersection(&other), Some(AddressRangeV4::from_string("10.0.0.70-10.0.0.77").unwrap()));
+        assert_eq!(range.intersection(&AddressRangeV4::from_string("10.0.0.78-10.0.0.80").unwrap()), None);
+        assert_eq!(range.iter().nth(2), Some(v4("10.0.0.7")));
+        assert_eq!(range.into_iter().len(), 73);
+    }
+
+    #[test]
+    fn range_networks() {
+        let range = AddressRangeV4::from_string("10.0.0.5-10.0.0.77").unwrap();
+        let networks: Vec<String> = range.to_networks().iter().map(|network| network.to_string()).collect();
+        assert_eq!(networks, vec!["10.0.0.5/32", "10.0.0.6/31", "10.0.0.8/29", "10.0.0.16/28", "10.0.0.32/27", "10.0.0.64/29", "10.0.0.72/30", "10.0.0.76/31"]);
+
+        let network = Network::new(Box::new(v4("192.168.1.0")), Bitmask::V4(BitmaskV4::new(24).unwrap()));
+        let range = AddressRangeV4::try_from(&network).unwrap();
+        assert_eq!(range.to_string(), "192.168.1.0-192.168.1.255");
+        assert_eq!(range.to_networks(), vec![network.clone()]);
+        assert_eq!(AddressRangeV6::try_from(&network), Err(NetworkError::VersionMismatch));
+        let whole = AddressRangeV6::try_from(&Network::from_bits(Version::IpV6, 0, 0)).unwrap();
+        assert_eq!(whole.count(), u128::MAX);
+    }
+}
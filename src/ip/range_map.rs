@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+use super::address::{AddressV4, AddressV6, FixedWidth};
+use super::aggregate::range_to_networks;
+use super::range::AddressRange;
+use super::{Network, NetworkError};
+
+// Map from disjoint address ranges to values where the last write wins.
+// Inserting over existing ranges splits or overwrites them, and touching
+// ranges with equal values are merged, so the map stays as small as it can.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeMap<A, V> {
+    // First address -> (last address, value)
+    ranges: BTreeMap<u128, (u128, V)>,
+    family: PhantomData<A>,
+}
+
+pub type RangeMapV4<V> = RangeMap<AddressV4, V>;
+pub type RangeMapV6<V> = RangeMap<AddressV6, V>;
+
+impl<A: FixedWidth, V: Clone + PartialEq> RangeMap<A, V> {
+    pub fn new() -> RangeMap<A, V> {
+        RangeMap { ranges: BTreeMap::new(), family: PhantomData }
+    }
+
+    // Number of stored ranges after merging
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn insert(&mut self, range: AddressRange<A>, value: V) {
+        let (first, last) = (range.first().to_bits(), range.last().to_bits());
+        self.cut(first, last);
+        let mut first = first;
+        let mut last = last;
+        // Merge with the neighbours holding the same value
+        if let Some(before) = first.checked_sub(1) {
+            if let Some((&start, (end, previous))) = self.ranges.range(..=before).next_back() {
+                if *end == before && *previous == value {
+                    first = start;
+                    self.ranges.remove(&start);
+                }
+            }
+        }
+        if let Some(after) = last.checked_add(1) {
+            if let Some((end, next)) = self.ranges.get(&after) {
+                if *next == value {
+                    last = *end;
+                    self.ranges.remove(&after);
+                }
+            }
+        }
+        self.ranges.insert(first, (last, value));
+    }
+
+    pub fn insert_network(&mut self, network: &Network, value: V) -> Result<(), NetworkError> {
+        self.insert(AddressRange::try_from(network)?, value);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, range: AddressRange<A>) {
+        self.cut(range.first().to_bits(), range.last().to_bits());
+    }
+
+    // Drops everything between first and last, trimming ranges that stick out
+    fn cut(&mut self, first: u128, last: u128) {
+        if let Some((&start, (end, value))) = self.ranges.range(..first).next_back() {
+            if *end >= first {
+                let (end, value) = (*end, value.clone());
+                self.ranges.insert(start, (first - 1, value.clone()));
+                if end > last {
+                    self.ranges.insert(last + 1, (end, value));
+                }
+            }
+        }
+        let inside: Vec<u128> = self.ranges.range(first..=last).map(|(start, _)| *start).collect();
+        for start in inside {
+            let (end, value) = self.ranges.remove(&start).unwrap();
+            if end > last {
+                self.ranges.insert(last + 1, (end, value));
+            }
+        }
+    }
+
+    pub fn get(&self, address: &A) -> Option<&V> {
+        let bits = address.to_bits();
+        let (_, (end, value)) = self.ranges.range(..=bits).next_back()?;
+        if *end >= bits {
+            return Some(value);
+        }
+        None
+    }
+
+    // Stored ranges overlapping the given one, clipped to it
+    pub fn overlapping(&self, range: AddressRange<A>) -> impl Iterator<Item = (AddressRange<A>, &V)> + '_ {
+        let (first, last) = (range.first().to_bits(), range.last().to_bits());
+        let before = self.ranges.range(..first).next_back().filter(|(_, (end, _))| *end >= first);
+        before.into_iter()
+            .chain(self.ranges.range(first..=last))
+            .map(move |(start, (end, value))| (AddressRange::from_bits((*start).max(first), (*end).min(last)), value))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (AddressRange<A>, &V)> + '_ {
+        self.ranges.iter().map(|(start, (end, value))| (AddressRange::from_bits(*start, *end), value))
+    }
+
+    // Minimal sorted list of networks with their values
+    pub fn to_networks(&self) -> Vec<(Network, V)> {
+        self.ranges.iter()
+            .flat_map(|(start, (end, value))| {
+                range_to_networks(A::VERSION, *start, *end).into_iter().map(move |network| (network, value.clone()))
+            })
+            .collect()
+    }
+}
+
+impl<A: FixedWidth, V: Clone + PartialEq> Default for RangeMap<A, V> {
+    fn default() -> RangeMap<A, V> {
+        RangeMap::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Version;
+
+    fn range(range: &str) -> AddressRange<AddressV4> {
+        AddressRange::from_string(range).unwrap()
+    }
+
+    fn v4(address: &str) -> AddressV4 {
+        AddressV4::from_string(address).unwrap()
+    }
+
+    fn contents(map: &RangeMapV4<&'static str>) -> Vec<(String, &'static str)> {
+        map.iter().map(|(range, value)| (range.to_string(), *value)).collect()
+    }
+
+    #[test]
+    fn range_map_overwrite_and_split() {
+        let mut map = RangeMapV4::new();
+        map.insert(range("10.0.0.0-10.0.0.255"), "alice");
+        map.insert(range("10.0.0.100-10.0.0.199"), "bob");
+        assert_eq!(contents(&map), vec![
+            (String::from("10.0.0.0-10.0.0.99"), "alice"),
+            (String::from("10.0.0.100-10.0.0.199"), "bob"),
+            (String::from("10.0.0.200-10.0.0.255"), "alice"),
+        ]);
+        map.insert(range("10.0.0.50-10.0.0.220"), "carol");
+        assert_eq!(contents(&map), vec![
+            (String::from("10.0.0.0-10.0.0.49"), "alice"),
+            (String::from("10.0.0.50-10.0.0.220"), "carol"),
+            (String::from("10.0.0.221-10.0.0.255"), "alice"),
+        ]);
+        assert_eq!(map.get(&v4("10.0.0.49")), Some(&"alice"));
+        assert_eq!(map.get(&v4("10.0.0.50")), Some(&"carol"));
+        assert_eq!(map.get(&v4("10.0.1.0")), None);
+    }
+
+    #[test]
+    fn range_map_coalescing() {
+        let mut map = RangeMapV4::new();
+        map.insert(range("10.0.0.0-10.0.0.99"), "alice");
+        map.insert(range("10.0.0.200-10.0.0.255"), "alice");
+        map.insert(range("10.0.0.100-10.0.0.199"), "bob");
+        assert_eq!(map.len(), 3);
+        map.insert(range("10.0.0.100-10.0.0.199"), "alice");
+        assert_eq!(contents(&map), vec![(String::from("10.0.0.0-10.0.0.255"), "alice")]);
+        let networks: Vec<(String, &str)> = map.to_networks().iter().map(|(network, value)| (network.to_string(), *value)).collect();
+        assert_eq!(networks, vec![(String::from("10.0.0.0/24"), "alice")]);
+    }
+
+    #[test]
+    fn range_map_queries_and_removal() {
+        let mut map = RangeMapV4::new();
+        map.insert_network(&Network::from_bits(Version::IpV4, 0, 0), "iana").unwrap();
+        map.insert(range("10.0.0.5-10.0.0.77"), "customer");
+        let found: Vec<(String, &str)> = map.overlapping(range("10.0.0.0-10.0.0.10")).map(|(range, value)| (range.to_string(), *value)).collect();
+        assert_eq!(found, vec![(String::from("10.0.0.0-10.0.0.4"), "iana"), (String::from("10.0.0.5-10.0.0.10"), "customer")]);
+        let networks: Vec<String> = map.to_networks().iter().filter(|(_, value)| *value == "customer").map(|(network, _)| network.to_string()).collect();
+        assert_eq!(networks, vec!["10.0.0.5/32", "10.0.0.6/31", "10.0.0.8/29", "10.0.0.16/28", "10.0.0.32/27", "10.0.0.64/29", "10.0.0.72/30", "10.0.0.76/31"]);
+        map.remove(range("10.0.0.0-10.0.0.255"));
+        assert_eq!(map.get(&v4("10.0.0.6")), None);
+        assert_eq!(map.get(&v4("255.255.255.255")), Some(&"iana"));
+        assert_eq!(map.len(), 2);
+        assert!(map.insert_network(&Network::from_bits(Version::IpV6, 0, 0), "v6").is_err());
+    }
+}
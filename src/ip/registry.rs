@@ -0,0 +1,508 @@
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use super::address::{Address, AddressV4, AddressV6, FixedWidth, SomeAddress};
+use super::set::IpSet;
+use super::{Network, NetworkError, Version};
+
+// Regional Internet Registries, as named in the IANA registries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Rir {
+    Afrinic,
+    Apnic,
+    Arin,
+    Lacnic,
+    RipeNcc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Allocated,
+    Legacy,
+    Reserved,
+    Unallocated,
+}
+
+// A row of the IPv4 address space or the IPv6 unicast assignments registry
+#[derive(Debug, Clone, PartialEq)]
+pub struct Allocation {
+    pub network: Network,
+    pub designation: String,
+    // Registry running the WHOIS for the block, legacy space included
+    pub rir: Option<Rir>,
+    pub status: Status,
+}
+
+// A row of the IPv4 or IPv6 special-purpose address registry
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpecialBlock {
+    pub network: Network,
+    pub name: String,
+    // None where the registry says N/A or leaves it empty
+    pub globally_reachable: Option<bool>,
+}
+
+// Multicast blocks aren't always CIDR aligned, so they are ranges
+#[derive(Debug, Clone, PartialEq)]
+pub struct MulticastBlock {
+    pub first: SomeAddress,
+    pub last: SomeAddress,
+    pub name: String,
+}
+
+#[derive(Debug)]
+pub enum RegistryError {
+    Csv(String),
+    MissingColumn(String),
+    WrongValue(String),
+    Network(NetworkError),
+    Io(std::io::Error),
+}
+
+// CSV text of every registry, in the format of the IANA exports
+#[derive(Debug, Clone, Copy)]
+pub struct Sources<'a> {
+    pub ipv4_address_space: &'a str,
+    pub ipv6_unicast_assignments: &'a str,
+    pub ipv4_special: &'a str,
+    pub ipv6_special: &'a str,
+    pub multicast: &'a str,
+    pub ipv6_multicast_scopes: &'a str,
+}
+
+// Snapshot shipped with the crate, dates and notes trimmed. Use
+// `Registry::from_directory` on fresh exports to get current data.
+pub const EMBEDDED: Sources<'static> = Sources {
+    ipv4_address_space: include_str!("data/ipv4-address-space.csv"),
+    ipv6_unicast_assignments: include_str!("data/ipv6-unicast-address-assignments.csv"),
+    ipv4_special: include_str!("data/iana-ipv4-special-registry-1.csv"),
+    ipv6_special: include_str!("data/iana-ipv6-special-registry-1.csv"),
+    multicast: include_str!("data/multicast-address-space.csv"),
+    ipv6_multicast_scopes: include_str!("data/ipv6-multicast-address-scopes.csv"),
+};
+
+// File names `from_directory` reads, the ones IANA publishes the CSVs under
+pub const FILE_NAMES: [&str; 6] = [
+    "ipv4-address-space.csv",
+    "ipv6-unicast-address-assignments.csv",
+    "iana-ipv4-special-registry-1.csv",
+    "iana-ipv6-special-registry-1.csv",
+    "multicast-address-space.csv",
+    "ipv6-multicast-address-scopes.csv",
+];
+
+#[derive(Debug, Clone)]
+pub struct Registry {
+    allocations: Vec<Allocation>,
+    special: Vec<SpecialBlock>,
+    multicast: Vec<MulticastBlock>,
+    bogons_v4: IpSet<AddressV4>,
+    bogons_v6: IpSet<AddressV6>,
+}
+
+impl Rir {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rir::Afrinic => "AFRINIC",
+            Rir::Apnic => "APNIC",
+            Rir::Arin => "ARIN",
+            Rir::Lacnic => "LACNIC",
+            Rir::RipeNcc => "RIPE NCC",
+        }
+    }
+
+    // "whois.ripe.net" -> RipeNcc
+    pub fn from_whois(server: &str) -> Option<Rir> {
+        match server.trim() {
+            "whois.afrinic.net" => Some(Rir::Afrinic),
+            "whois.apnic.net" => Some(Rir::Apnic),
+            "whois.arin.net" => Some(Rir::Arin),
+            "whois.lacnic.net" => Some(Rir::Lacnic),
+            "whois.ripe.net" => Some(Rir::RipeNcc),
+            _ => None,
+        }
+    }
+}
+
+impl Registry {
+    // Parsed once, on first use
+    pub fn embedded() -> &'static Registry {
+        static EMBEDDED_REGISTRY: OnceLock<Registry> = OnceLock::new();
+        EMBEDDED_REGISTRY.get_or_init(|| Registry::parse(&EMBEDDED).expect("Embedded registry data is broken"))
+    }
+
+    // Regeneration path: reads the CSV exports saved under `FILE_NAMES`
+    pub fn from_directory<P: AsRef<Path>>(directory: P) -> Result<Registry, RegistryError> {
+        let read = |name: &str| fs::read_to_string(directory.as_ref().join(name)).map_err(RegistryError::Io);
+        let texts = [
+            read(FILE_NAMES[0])?,
+            read(FILE_NAMES[1])?,
+            read(FILE_NAMES[2])?,
+            read(FILE_NAMES[3])?,
+            read(FILE_NAMES[4])?,
+            read(FILE_NAMES[5])?,
+        ];
+        Registry::parse(&Sources {
+            ipv4_address_space: &texts[0],
+            ipv6_unicast_assignments: &texts[1],
+            ipv4_special: &texts[2],
+            ipv6_special: &texts[3],
+            multicast: &texts[4],
+            ipv6_multicast_scopes: &texts[5],
+        })
+    }
+
+    pub fn parse(sources: &Sources) -> Result<Registry, RegistryError> {
+        let mut allocations = parse_allocations(sources.ipv4_address_space)?;
+        allocations.extend(parse_allocations(sources.ipv6_unicast_assignments)?);
+        let mut special = parse_special(sources.ipv4_special)?;
+        special.extend(parse_special(sources.ipv6_special)?);
+        let mut multicast = parse_multicast(sources.multicast)?;
+        multicast.extend(parse_multicast_scopes(sources.ipv6_multicast_scopes)?);
+        Ok(Registry {
+            bogons_v4: bogons(&allocations, &special).map_err(RegistryError::Network)?,
+            bogons_v6: bogons(&allocations, &special).map_err(RegistryError::Network)?,
+            allocations,
+            special,
+            multicast,
+        })
+    }
+
+    pub fn allocations(&self) -> &[Allocation] {
+        &self.allocations
+    }
+
+    pub fn special_blocks(&self) -> &[SpecialBlock] {
+        &self.special
+    }
+
+    pub fn multicast_blocks(&self) -> &[MulticastBlock] {
+        &self.multicast
+    }
+
+    // Most specific allocation holding the whole network
+    pub fn allocation(&self, network: &Network) -> Option<&Allocation> {
+        self.allocations.iter()
+            .filter(|allocation| allocation.network.contains_network(network) == Ok(true))
+            .max_by_key(|allocation| allocation.network.prefix_len())
+    }
+
+    // Which RIR runs the space, e.g. for a /8
+    pub fn rir(&self, network: &Network) -> Option<Rir> {
+        self.allocation(network).and_then(|allocation| allocation.rir)
+    }
+
+    // Every block the RIR runs, legacy space included
+    pub fn allocations_of(&self, rir: Rir) -> impl Iterator<Item = &Allocation> {
+        self.allocations.iter().filter(move |allocation| allocation.rir == Some(rir))
+    }
+
+    // Most specific special-purpose block holding the whole network
+    pub fn special(&self, network: &Network) -> Option<&SpecialBlock> {
+        self.special.iter()
+            .filter(|block| block.network.contains_network(network) == Ok(true))
+            .max_by_key(|block| block.network.prefix_len())
+    }
+
+    pub fn multicast(&self, address: &dyn Address) -> Option<&MulticastBlock> {
+        let address = address.as_some();
+        self.multicast.iter().find(|block| block.first <= address && address <= block.last)
+    }
+
+    // Minimal sorted bogon list: space that is reserved, unallocated or not
+    // globally reachable, without the globally reachable blocks inside it
+    pub fn bogons(&self, version: Version) -> Vec<Network> {
+        match version {
+            Version::IpV4 => self.bogons_v4.networks().collect(),
+            Version::IpV6 => self.bogons_v6.networks().collect(),
+        }
+    }
+
+    // True if the whole network lies in bogon space
+    pub fn is_bogon(&self, network: &Network) -> bool {
+        match network.version() {
+            Version::IpV4 => self.bogons_v4.contains_network(network) == Ok(true),
+            Version::IpV6 => self.bogons_v6.contains_network(network) == Ok(true),
+        }
+    }
+}
+
+fn bogons<A: FixedWidth>(allocations: &[Allocation], special: &[SpecialBlock]) -> Result<IpSet<A>, NetworkError> {
+    let family = |network: &&Network| network.version() == A::VERSION;
+    // IPv6 registries only list what is handed out, the rest is unallocated
+    let in_use = allocations.iter()
+        .filter(|allocation| matches!(allocation.status, Status::Allocated | Status::Legacy))
+        .map(|allocation| &allocation.network)
+        .filter(family);
+    let unused = IpSet::from_networks(in_use)?.complement();
+    let unreachable = special.iter().filter(|block| block.globally_reachable == Some(false)).map(|block| &block.network).filter(family);
+    let reachable = special.iter().filter(|block| block.globally_reachable != Some(false) && !block.name.starts_with("Deprecated"))
+        .map(|block| &block.network)
+        .filter(family);
+    Ok(unused.union(&IpSet::from_networks(unreachable)?).difference(&IpSet::from_networks(reachable)?))
+}
+
+// Rows of a CSV file, header included. Quoted fields may hold commas,
+// doubled quotes and line breaks, like the RDAP column of IANA exports.
+fn parse_csv(text: &str) -> Result<Vec<Vec<String>>, RegistryError> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut field));
+                if row.iter().any(|field| !field.is_empty()) {
+                    rows.push(std::mem::take(&mut row));
+                }
+                row.clear();
+            }
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(RegistryError::Csv(String::from("Unterminated quoted field")));
+    }
+    row.push(field);
+    if row.iter().any(|field| !field.is_empty()) {
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+// Rows as (header, fields) with a lookup of columns by name. Headers carry
+// footnote marks at times, "Status [1]" is found as "Status".
+struct Table {
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    fn parse(text: &str) -> Result<Table, RegistryError> {
+        let mut rows = parse_csv(text)?.into_iter();
+        let header = rows.next().ok_or_else(|| RegistryError::Csv(String::from("Empty file")))?;
+        Ok(Table { header: header.iter().map(|name| without_footnotes(name).to_string()).collect(), rows: rows.collect() })
+    }
+
+    fn column(&self, name: &str) -> Result<usize, RegistryError> {
+        self.header.iter().position(|column| column == name).ok_or_else(|| RegistryError::MissingColumn(name.to_string()))
+    }
+}
+
+fn field(row: &[String], column: usize) -> &str {
+    row.get(column).map_or("", |value| without_footnotes(value))
+}
+
+// "192.0.0.0/24 [2]" -> "192.0.0.0/24"
+fn without_footnotes(value: &str) -> &str {
+    value.split('[').next().unwrap_or_default().trim()
+}
+
+// IPv4 address space rows use "003/8" for 3.0.0.0/8
+fn parse_prefix(prefix: &str) -> Result<Network, RegistryError> {
+    let network = match prefix.split_once('/') {
+        Some((octet, prefix_len)) if !octet.contains(['.', ':']) => {
+            let octet: u8 = octet.parse().map_err(|_| RegistryError::WrongValue(prefix.to_string()))?;
+            Network::from_string(&format!("{}.0.0.0/{}", octet, prefix_len))
+        }
+        _ => Network::from_string(prefix),
+    };
+    network.map_err(RegistryError::Network)
+}
+
+fn parse_allocations(text: &str) -> Result<Vec<Allocation>, RegistryError> {
+    let table = Table::parse(text)?;
+    let (prefix, designation, whois, status) =
+        (table.column("Prefix")?, table.column("Designation")?, table.column("WHOIS")?, table.column("Status")?);
+    table.rows.iter().map(|row| {
+        let status = match field(row, status).to_uppercase().as_str() {
+            "ALLOCATED" => Status::Allocated,
+            "LEGACY" => Status::Legacy,
+            "RESERVED" => Status::Reserved,
+            "UNALLOCATED" => Status::Unallocated,
+            other => return Err(RegistryError::WrongValue(other.to_string())),
+        };
+        Ok(Allocation {
+            network: parse_prefix(field(row, prefix))?,
+            designation: field(row, designation).to_string(),
+            rir: Rir::from_whois(field(row, whois)),
+            status,
+        })
+    }).collect()
+}
+
+fn parse_special(text: &str) -> Result<Vec<SpecialBlock>, RegistryError> {
+    let table = Table::parse(text)?;
+    let (block, name, reachable) = (table.column("Address Block")?, table.column("Name")?, table.column("Globally Reachable")?);
+    let mut blocks = Vec::new();
+    for row in &table.rows {
+        let globally_reachable = match field(row, reachable) {
+            "True" => Some(true),
+            "False" => Some(false),
+            _ => None,
+        };
+        // Some rows list several prefixes, "192.0.0.170/32, 192.0.0.171/32"
+        for prefix in field(row, block).split(',') {
+            blocks.push(SpecialBlock {
+                network: parse_prefix(prefix.trim())?,
+                name: field(row, name).to_string(),
+                globally_reachable,
+            });
+        }
+    }
+    Ok(blocks)
+}
+
+// Blocks are prefixes or "first-last" ranges
+fn parse_multicast(text: &str) -> Result<Vec<MulticastBlock>, RegistryError> {
+    let table = Table::parse(text)?;
+    let (block, name) = (table.column("Address Block")?, table.column("Name")?);
+    table.rows.iter().map(|row| {
+        let (first, last) = match field(row, block).split_once('-') {
+            Some((first, last)) => (parse_address(first)?, parse_address(last)?),
+            None => {
+                let network = parse_prefix(field(row, block))?;
+                (network.network_address(), SomeAddress::from_bits(network.version(), network.last_bits()))
+            }
+        };
+        if first.version() != last.version() || first > last {
+            return Err(RegistryError::WrongValue(field(row, block).to_string()));
+        }
+        Ok(MulticastBlock { first, last, name: field(row, name).to_string() })
+    }).collect()
+}
+
+// Rows give the scope nibble, "2" or a "9-D" range. The scope is the low
+// nibble of the second byte whatever the flags are, so scope 2 covers
+// ff02::/16, ff12::/16 and so on up to fff2::/16.
+fn parse_multicast_scopes(text: &str) -> Result<Vec<MulticastBlock>, RegistryError> {
+    let table = Table::parse(text)?;
+    let (value, name) = (table.column("Value")?, table.column("Scope Name")?);
+    let nibble = |value: &str| {
+        u128::from_str_radix(value.trim(), 16).ok().filter(|nibble| *nibble < 16).ok_or_else(|| RegistryError::WrongValue(value.to_string()))
+    };
+    let mut blocks = Vec::new();
+    for row in &table.rows {
+        let (first, last) = match field(row, value).split_once('-') {
+            Some((first, last)) => (nibble(first)?, nibble(last)?),
+            None => (nibble(field(row, value))?, nibble(field(row, value))?),
+        };
+        if first > last {
+            return Err(RegistryError::WrongValue(field(row, value).to_string()));
+        }
+        for flags in 0..16 {
+            for scope in first..=last {
+                let bits = (0xff00 | flags << 4 | scope) << 112;
+                blocks.push(MulticastBlock {
+                    first: SomeAddress::from_bits(Version::IpV6, bits),
+                    last: SomeAddress::from_bits(Version::IpV6, bits | ((1 << 112) - 1)),
+                    name: field(row, name).to_string(),
+                });
+            }
+        }
+    }
+    blocks.sort_by_key(|block| block.first);
+    Ok(blocks)
+}
+
+fn parse_address(address: &str) -> Result<SomeAddress, RegistryError> {
+    let address_bits = if address.contains(':') { Version::IpV6 } else { Version::IpV4 }.address_bits();
+    let host = Network::from_string(&format!("{}/{}", address.trim(), address_bits)).map_err(RegistryError::Network)?;
+    Ok(host.network_address())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_helpers::network;
+
+    #[test]
+    fn registry_allocations() {
+        let registry = Registry::embedded();
+        let v4 = registry.allocations().iter().filter(|allocation| allocation.network.version() == Version::IpV4);
+        assert_eq!(v4.map(|allocation| allocation.network.total_addresses()).sum::<u128>(), 1 << 32);
+        assert_eq!(registry.rir(&network("8.8.8.0/24")), Some(Rir::Arin));
+        assert_eq!(registry.rir(&network("193.0.0.0/21")), Some(Rir::RipeNcc));
+        assert_eq!(registry.rir(&network("41.0.0.0/8")), Some(Rir::Afrinic));
+        assert_eq!(registry.rir(&network("2a00:1450::/32")), Some(Rir::RipeNcc));
+        assert_eq!(registry.rir(&network("10.0.0.0/8")), None);
+        let legacy = registry.allocation(&network("17.0.0.0/8")).unwrap();
+        assert_eq!((legacy.designation.as_str(), legacy.status), ("Apple Computer Inc.", Status::Legacy));
+        assert_eq!(registry.allocations_of(Rir::Lacnic).filter(|allocation| allocation.network.version() == Version::IpV4).count(), 10);
+        assert_eq!(registry.special(&network("192.0.0.9/32")).unwrap().name, "Port Control Protocol Anycast");
+    }
+
+    #[test]
+    fn registry_shared_between_threads() {
+        let address = |registry: &'static Registry| registry as *const Registry as usize;
+        let other = std::thread::spawn(move || address(Registry::embedded())).join().unwrap();
+        assert_eq!(address(Registry::embedded()), other);
+    }
+
+    #[test]
+    fn registry_bogons() {
+        let registry = Registry::embedded();
+        for bogon in ["10.1.0.0/16", "0.0.0.0/8", "192.0.0.8/32", "224.0.0.1/32", "255.255.255.255/32", "fe80::/64", "2001:db8::/48", "3fff::/20", "fc00::/7", "ff02::1/128"] {
+            assert!(registry.is_bogon(&network(bogon)), "{}", bogon);
+        }
+        for routable in ["8.8.8.0/24", "192.0.0.9/32", "192.88.99.0/24", "2001:4860::/32", "64:ff9b::/96", "2001::/32"] {
+            assert!(!registry.is_bogon(&network(routable)), "{}", routable);
+        }
+        let bogons: Vec<String> = registry.bogons(Version::IpV4).iter().take(4).map(Network::to_string).collect();
+        assert_eq!(bogons, vec!["0.0.0.0/8", "10.0.0.0/8", "100.64.0.0/10", "127.0.0.0/8"]);
+    }
+
+    #[test]
+    fn registry_multicast() {
+        let registry = Registry::embedded();
+        let name = |address: &str| registry.multicast(&parse_address(address).unwrap()).map(|block| block.name.as_str());
+        assert_eq!(name("224.0.0.251"), Some("Local Network Control Block"));
+        assert_eq!(name("224.3.1.1"), Some("AD-HOC Block II"));
+        assert_eq!(name("233.252.0.1"), Some("AD-HOC Block III"));
+        assert_eq!(name("ff02::1"), Some("Link-Local scope"));
+        assert_eq!(name("ff15::1:3"), Some("Site-Local scope"));
+        assert_eq!(name("ff3e:40:2001:db8::1"), Some("Global scope"));
+        assert_eq!(name("ff0a::1"), Some("Unassigned"));
+        assert_eq!(registry.multicast_blocks().iter().filter(|block| block.first.version() == Version::IpV6).count(), 256);
+        assert_eq!(name("10.0.0.1"), None);
+    }
+
+    #[test]
+    fn registry_from_directory() {
+        let directory = std::env::temp_dir().join(format!("ip-network-registry-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let texts = [
+            "Prefix,Designation,Date,WHOIS,RDAP,Status [1],Note\n010/8,IANA - Private Use,,,,RESERVED,\n011/8,\"Example, Inc.\",,whois.arin.net,\"https://a\nhttps://b\",LEGACY,\n",
+            "Prefix,Designation,Date,WHOIS,RDAP,Status,Note\r\n2001:0200::/23,APNIC,1999-07-01,whois.apnic.net,,ALLOCATED,\r\n",
+            "Address Block,Name,Globally Reachable\n10.0.0.0/8,Private-Use,False [1]\n",
+            "Address Block,Name,Globally Reachable\n",
+            "Address Block,Name\n224.0.0.0-224.0.0.255,Local\n",
+            "Value,Scope Name,Reference\n2,Link-Local scope,\n",
+        ];
+        for (name, text) in FILE_NAMES.iter().zip(texts) {
+            fs::write(directory.join(name), text).unwrap();
+        }
+        let registry = Registry::from_directory(&directory).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(registry.allocation(&network("11.1.0.0/16")).unwrap().designation, "Example, Inc.");
+        assert_eq!(registry.rir(&network("2001:0200::/24")), Some(Rir::Apnic));
+        assert!(registry.is_bogon(&network("12.0.0.0/8")));
+        assert!(!registry.is_bogon(&network("11.0.0.0/8")));
+        assert_eq!(registry.bogons(Version::IpV6).len(), 23);
+        assert_eq!(registry.multicast(&parse_address("ff32::1").unwrap()).unwrap().name, "Link-Local scope");
+        assert!(matches!(parse_multicast_scopes("Value,Scope Name\nG,Broken\n"), Err(RegistryError::WrongValue(_))));
+        assert!(matches!(Table::parse("Name\n").unwrap().column("Prefix"), Err(RegistryError::MissingColumn(_))));
+        assert!(matches!(parse_csv("a,\"b\n"), Err(RegistryError::Csv(_))));
+    }
+}
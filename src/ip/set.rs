@@ -0,0 +1,323 @@
+use std::borrow::Borrow;
+use std::marker::PhantomData;
+
+use super::address::{AddressV4, AddressV6, FixedWidth};
+use super::aggregate::merge_ranges;
+use super::range::AddressRange;
+use super::{Network, NetworkError};
+
+// Arbitrary set of addresses of one family, kept as a sorted list of
+// disjoint, non-touching ranges. Two sets with the same addresses are
+// always stored the same way, so they compare equal.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IpSet<A> {
+    ranges: Vec<(u128, u128)>,
+    family: PhantomData<A>,
+}
+
+pub type IpSetV4 = IpSet<AddressV4>;
+pub type IpSetV6 = IpSet<AddressV6>;
+
+impl<A: FixedWidth> IpSet<A> {
+    pub fn new() -> IpSet<A> {
+        IpSet { ranges: Vec::new(), family: PhantomData }
+    }
+
+    // Sorts once and merges, O(n log n) even for a full routing table
+    pub fn from_networks<I>(networks: I) -> Result<IpSet<A>, NetworkError>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Network>,
+    {
+        let mut ranges = Vec::new();
+        for network in networks {
+            let network = network.borrow();
+            if network.version() != A::VERSION {
+                return Err(NetworkError::VersionMismatch);
+            }
+            ranges.push((network.first_bits(), network.last_bits()));
+        }
+        Ok(IpSet::from_unsorted(ranges))
+    }
+
+    pub fn from_ranges<I: IntoIterator<Item = AddressRange<A>>>(ranges: I) -> IpSet<A> {
+        IpSet::from_unsorted(ranges.into_iter().map(|range| (range.first().to_bits(), range.last().to_bits())).collect())
+    }
+
+    fn from_unsorted(mut ranges: Vec<(u128, u128)>) -> IpSet<A> {
+        ranges.sort_unstable();
+        let merged = merge_ranges(ranges.into_iter().map(|(first, last)| (A::VERSION, first, last)).collect());
+        IpSet::from_sorted(merged.into_iter().map(|(_, first, last)| (first, last)).collect())
+    }
+
+    fn from_sorted(ranges: Vec<(u128, u128)>) -> IpSet<A> {
+        IpSet { ranges, family: PhantomData }
+    }
+
+    // The whole address space of the family
+    pub fn all() -> IpSet<A> {
+        IpSet::from_sorted(vec![(0, A::VERSION.max_bits())])
+    }
+
+    // Splices the range in where a binary search puts it, the rest of the
+    // set is left alone
+    pub fn insert(&mut self, range: AddressRange<A>) {
+        self.insert_bits(range.first().to_bits(), range.last().to_bits());
+    }
+
+    pub fn insert_network(&mut self, network: &Network) -> Result<(), NetworkError> {
+        if network.version() != A::VERSION {
+            return Err(NetworkError::VersionMismatch);
+        }
+        self.insert_bits(network.first_bits(), network.last_bits());
+        Ok(())
+    }
+
+    pub fn remove(&mut self, range: AddressRange<A>) {
+        let (first, last) = (range.first().to_bits(), range.last().to_bits());
+        let start = self.ranges.partition_point(|(_, range_last)| *range_last < first);
+        let end = self.ranges.partition_point(|(range_first, _)| *range_first <= last);
+        if start >= end {
+            return;
+        }
+        // Only the two outer ranges can stick out of the removed one
+        let mut kept = Vec::with_capacity(2);
+        if self.ranges[start].0 < first {
+            kept.push((self.ranges[start].0, first - 1));
+        }
+        if self.ranges[end - 1].1 > last {
+            kept.push((last + 1, self.ranges[end - 1].1));
+        }
+        self.ranges.splice(start..end, kept);
+    }
+
+    fn insert_bits(&mut self, first: u128, last: u128) {
+        // Ranges overlapping or touching [first, last] get merged into it
+        let start = self.ranges.partition_point(|(_, range_last)| range_last.saturating_add(1) < first);
+        let end = self.ranges.partition_point(|(range_first, _)| *range_first <= last.saturating_add(1));
+        let merged = if start < end {
+            (first.min(self.ranges[start].0), last.max(self.ranges[end - 1].1))
+        } else {
+            (first, last)
+        };
+        self.ranges.splice(start..end, [merged]);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    // Number of addresses. Saturates at u128::MAX for the whole IPv6 space.
+    pub fn len(&self) -> u128 {
+        self.ranges.iter().fold(0_u128, |total, (first, last)| total.saturating_add((last - first).saturating_add(1)))
+    }
+
+    pub fn contains(&self, address: &A) -> bool {
+        let bits = address.to_bits();
+        let index = self.ranges.partition_point(|(_, last)| *last < bits);
+        self.ranges.get(index).is_some_and(|(first, _)| *first <= bits)
+    }
+
+    pub fn contains_range(&self, range: &AddressRange<A>) -> bool {
+        let (first, last) = (range.first().to_bits(), range.last().to_bits());
+        let index = self.ranges.partition_point(|(_, range_last)| *range_last < first);
+        self.ranges.get(index).is_some_and(|(range_first, range_last)| *range_first <= first && last <= *range_last)
+    }
+
+    pub fn contains_network(&self, network: &Network) -> Result<bool, NetworkError> {
+        let range = AddressRange::try_from(network)?;
+        Ok(self.contains_range(&range))
+    }
+
+    pub fn is_subset(&self, other: &IpSet<A>) -> bool {
+        self.difference(other).is_empty()
+    }
+
+    pub fn is_superset(&self, other: &IpSet<A>) -> bool {
+        other.is_subset(self)
+    }
+
+    pub fn is_disjoint(&self, other: &IpSet<A>) -> bool {
+        self.intersection(other).is_empty()
+    }
+
+    pub fn union(&self, other: &IpSet<A>) -> IpSet<A> {
+        let mut ranges: Vec<(u128, u128)> = Vec::with_capacity(self.ranges.len() + other.ranges.len());
+        let (mut left, mut right) = (self.ranges.iter().peekable(), other.ranges.iter().peekable());
+        // Merge step of a merge sort, then the usual joining of touching ranges
+        loop {
+            let next = match (left.peek(), right.peek()) {
+                (Some(a), Some(b)) if a <= b => left.next(),
+                (Some(_), Some(_)) => right.next(),
+                (Some(_), None) => left.next(),
+                (None, _) => right.next(),
+            };
+            let Some(&(first, last)) = next else {
+                break;
+            };
+            match ranges.last_mut() {
+                Some((_, previous_last)) if first <= previous_last.saturating_add(1) => {
+                    *previous_last = (*previous_last).max(last);
+                }
+                _ => ranges.push((first, last)),
+            }
+        }
+        IpSet::from_sorted(ranges)
+    }
+
+    pub fn intersection(&self, other: &IpSet<A>) -> IpSet<A> {
+        let mut ranges = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.ranges.len() && j < other.ranges.len() {
+            let (a_first, a_last) = self.ranges[i];
+            let (b_first, b_last) = other.ranges[j];
+            let first = a_first.max(b_first);
+            let last = a_last.min(b_last);
+            if first <= last {
+                ranges.push((first, last));
+            }
+            if a_last < b_last {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        IpSet::from_sorted(ranges)
+    }
+
+    pub fn complement(&self) -> IpSet<A> {
+        let mut ranges = Vec::with_capacity(self.ranges.len() + 1);
+        let mut next = Some(0_u128);
+        for &(first, last) in &self.ranges {
+            if let Some(start) = next {
+                if start < first {
+                    ranges.push((start, first - 1));
+                }
+            }
+            next = last.checked_add(1);
+        }
+        if let Some(start) = next {
+            if start <= A::VERSION.max_bits() {
+                ranges.push((start, A::VERSION.max_bits()));
+            }
+        }
+        IpSet::from_sorted(ranges)
+    }
+
+    pub fn difference(&self, other: &IpSet<A>) -> IpSet<A> {
+        self.intersection(&other.complement())
+    }
+
+    pub fn symmetric_difference(&self, other: &IpSet<A>) -> IpSet<A> {
+        self.difference(other).union(&other.difference(self))
+    }
+
+    pub fn ranges(&self) -> impl Iterator<Item = AddressRange<A>> + '_ {
+        self.ranges.iter().map(|&(first, last)| AddressRange::from_bits(first, last))
+    }
+
+    // Minimal sorted list of networks covering the set
+    pub fn networks(&self) -> impl Iterator<Item = Network> + '_ {
+        self.ranges().flat_map(|range| range.to_networks())
+    }
+}
+
+impl<A: FixedWidth> Default for IpSet<A> {
+    fn default() -> IpSet<A> {
+        IpSet::new()
+    }
+}
+
+impl<A: FixedWidth> FromIterator<AddressRange<A>> for IpSet<A> {
+    fn from_iter<I: IntoIterator<Item = AddressRange<A>>>(ranges: I) -> IpSet<A> {
+        IpSet::from_ranges(ranges)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Version;
+    use super::super::test_helpers::network_v4;
+
+    fn range_v4(range: &str) -> AddressRange<AddressV4> {
+        AddressRange::from_string(range).unwrap()
+    }
+
+    fn ranges(set: &IpSetV4) -> Vec<String> {
+        set.ranges().map(|range| range.to_string()).collect()
+    }
+
+    #[test]
+    fn set_from_networks_is_normalised() {
+        let set = IpSetV4::from_networks([
+            network_v4("10.0.1.0", 24),
+            network_v4("10.0.0.0", 24),
+            network_v4("10.0.0.128", 25),
+            network_v4("192.168.0.0", 16),
+        ]).unwrap();
+        assert_eq!(ranges(&set), vec!["10.0.0.0-10.0.1.255", "192.168.0.0-192.168.255.255"]);
+        assert_eq!(set.len(), 512 + 65536);
+        let networks: Vec<String> = set.networks().map(|network| network.to_string()).collect();
+        assert_eq!(networks, vec!["10.0.0.0/23", "192.168.0.0/16"]);
+        assert_eq!(IpSetV4::from_networks([Network::from_bits(Version::IpV6, 0, 0)]), Err(NetworkError::VersionMismatch));
+    }
+
+    #[test]
+    fn set_algebra() {
+        let a: IpSetV4 = [range_v4("10.0.0.0-10.0.0.99"), range_v4("10.0.0.200-10.0.0.255")].into_iter().collect();
+        let b: IpSetV4 = [range_v4("10.0.0.50-10.0.0.210")].into_iter().collect();
+        assert_eq!(ranges(&a.union(&b)), vec!["10.0.0.0-10.0.0.255"]);
+        assert_eq!(ranges(&a.intersection(&b)), vec!["10.0.0.50-10.0.0.99", "10.0.0.200-10.0.0.210"]);
+        assert_eq!(ranges(&a.difference(&b)), vec!["10.0.0.0-10.0.0.49", "10.0.0.211-10.0.0.255"]);
+        assert_eq!(ranges(&a.symmetric_difference(&b)), vec!["10.0.0.0-10.0.0.49", "10.0.0.100-10.0.0.199", "10.0.0.211-10.0.0.255"]);
+        assert_eq!(ranges(&b.complement()), vec!["0.0.0.0-10.0.0.49", "10.0.0.211-255.255.255.255"]);
+        assert_eq!(IpSetV4::new().complement(), IpSetV4::all());
+        assert!(IpSetV4::all().complement().is_empty());
+        assert_eq!(a.union(&b), b.union(&a));
+        assert!(a.intersection(&b).is_subset(&a));
+        assert!(a.is_disjoint(&a.complement()));
+    }
+
+    #[test]
+    fn set_contains() {
+        let mut set = IpSetV4::new();
+        set.insert(range_v4("10.0.0.5-10.0.0.77"));
+        set.insert_network(&network_v4("10.0.0.64", 26)).unwrap();
+        set.remove(range_v4("10.0.0.100-10.0.0.100"));
+        assert_eq!(ranges(&set), vec!["10.0.0.5-10.0.0.99", "10.0.0.101-10.0.0.127"]);
+        assert!(set.contains(&AddressV4::from_string("10.0.0.5").unwrap()));
+        assert!(!set.contains(&AddressV4::from_string("10.0.0.100").unwrap()));
+        assert!(set.contains_range(&range_v4("10.0.0.10-10.0.0.99")));
+        assert!(!set.contains_range(&range_v4("10.0.0.10-10.0.0.101")));
+        assert_eq!(set.contains_network(&network_v4("10.0.0.8", 29)), Ok(true));
+        assert_eq!(IpSetV6::all().len(), u128::MAX);
+    }
+
+    #[test]
+    fn set_insert_and_remove_match_algebra() {
+        let mut set = IpSetV4::new();
+        let mut expected = IpSetV4::new();
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        for step in 0..2000 {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            let first = (seed % 1000) as u128;
+            let last = first + (seed >> 32) as u128 % 40;
+            let range = AddressRange::from_bits(first, last);
+            if step % 3 == 2 {
+                set.remove(range);
+                expected = expected.difference(&IpSet::from_ranges([range]));
+            } else {
+                set.insert(range);
+                expected = expected.union(&IpSet::from_ranges([range]));
+            }
+            assert_eq!(set, expected);
+        }
+        set.insert(AddressRange::from_bits(0, u32::MAX as u128));
+        assert_eq!(set, IpSetV4::all());
+        set.remove(AddressRange::from_bits(u32::MAX as u128, u32::MAX as u128));
+        assert_eq!(ranges(&set), vec!["0.0.0.0-255.255.255.254"]);
+    }
+}
@@ -0,0 +1,216 @@
+use std::borrow::Borrow;
+
+use super::aggregate::collapse;
+use super::{Network, Version};
+
+// How far the summary may drift from the exact aggregate
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    // At most this many prefixes, however much space that adds
+    Prefixes(usize),
+    // At most this much extra space, in percent of the input addresses
+    ExtraPercent(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SummariseError {
+    // Fewer prefixes asked for than address families in the input
+    TooFewPrefixes(String),
+    WrongPercent(String),
+}
+
+// One prefix of the summary, the exact input prefixes it replaces and the
+// addresses it covers on top of them
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregate {
+    pub network: Network,
+    pub members: Vec<Network>,
+    pub added: Vec<Network>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summarised {
+    pub aggregates: Vec<Aggregate>,
+    // Addresses of the input, overlaps counted once
+    pub input_addresses: u128,
+    // Prefixes of the exact aggregate, before over-aggregation
+    pub exact_prefixes: usize,
+}
+
+impl Aggregate {
+    pub fn added_addresses(&self) -> u128 {
+        self.added.iter().fold(0, |total, network| total.saturating_add(network.total_addresses()))
+    }
+}
+
+impl Summarised {
+    pub fn networks(&self) -> Vec<Network> {
+        self.aggregates.iter().map(|aggregate| aggregate.network.clone()).collect()
+    }
+
+    // Every block covered by the summary but not by the input, sorted
+    pub fn added(&self) -> Vec<Network> {
+        self.aggregates.iter().flat_map(|aggregate| aggregate.added.iter().cloned()).collect()
+    }
+
+    pub fn added_addresses(&self) -> u128 {
+        self.aggregates.iter().fold(0, |total, aggregate| total.saturating_add(aggregate.added_addresses()))
+    }
+
+    // Extra space in percent of the input addresses
+    pub fn extra_percent(&self) -> f64 {
+        if self.input_addresses == 0 {
+            return 0.0;
+        }
+        self.added_addresses() as f64 / self.input_addresses as f64 * 100.0
+    }
+}
+
+// A merge of the run of current prefixes inside `network`
+struct Merge {
+    start: usize,
+    end: usize,
+    network: Network,
+    cost: u128,
+}
+
+// Smallest aligned network holding both
+fn common_supernet(first: &Network, second: &Network) -> Network {
+    let width = first.version().address_bits();
+    let same = (first.first_bits() ^ second.last_bits()).leading_zeros() as u8 - (128 - width);
+    Network::from_bits(first.version(), first.first_bits(), same.min(first.prefix_len()).min(second.prefix_len()))
+}
+
+// Collapses the networks exactly, then greedily replaces runs of prefixes
+// by their common supernet, always taking the merge that adds the fewest
+// addresses per prefix saved, until the limit is met.
+pub fn summarise<I>(networks: I, limit: Limit) -> Result<Summarised, SummariseError>
+where
+    I: IntoIterator,
+    I::Item: Borrow<Network>,
+{
+    if let Limit::ExtraPercent(percent) = limit {
+        if percent.is_nan() || percent < 0.0 {
+            return Err(SummariseError::WrongPercent(format!("Extra space must be a positive percent, got {}", percent)));
+        }
+    }
+    let exact = collapse(networks);
+    let input_addresses = exact.iter().fold(0_u128, |total, network| total.saturating_add(network.total_addresses()));
+    if let Limit::Prefixes(prefixes) = limit {
+        let families = [Version::IpV4, Version::IpV6].iter()
+            .filter(|version| exact.iter().any(|network| network.version() == **version))
+            .count();
+        if prefixes < families {
+            return Err(SummariseError::TooFewPrefixes(format!("The input has {} address families, {} prefixes can't hold them", families, prefixes)));
+        }
+    }
+
+    let mut current: Vec<(Network, Vec<Network>)> = exact.iter().map(|network| (network.clone(), vec![network.clone()])).collect();
+    let mut added: u128 = 0;
+    loop {
+        if let Limit::Prefixes(prefixes) = limit {
+            if current.len() <= prefixes {
+                break;
+            }
+        }
+        let budget = match limit {
+            Limit::Prefixes(_) => u128::MAX,
+            Limit::ExtraPercent(percent) => (input_addresses as f64 * percent / 100.0) as u128,
+        };
+        let best = (0..current.len().saturating_sub(1))
+            .filter(|index| current[*index].0.version() == current[index + 1].0.version())
+            .map(|index| merge(&current, index))
+            .filter(|merge| added.saturating_add(merge.cost) <= budget)
+            .min_by(|a, b| {
+                let ratio = |merge: &Merge| merge.cost as f64 / (merge.end - merge.start) as f64;
+                ratio(a).total_cmp(&ratio(b)).then(a.cost.cmp(&b.cost))
+            });
+        let Some(best) = best else {
+            break;
+        };
+        added = added.saturating_add(best.cost);
+        let members = current.drain(best.start..=best.end).flat_map(|(_, members)| members).collect();
+        current.insert(best.start, (best.network, members));
+    }
+
+    let aggregates = current.into_iter().map(|(network, members)| {
+        // Members lie inside the network, so this can't fail
+        let added = network.exclude_all(&members).unwrap_or_default();
+        Aggregate { network, members, added }
+    }).collect();
+    Ok(Summarised { aggregates, input_addresses, exact_prefixes: exact.len() })
+}
+
+// Merge of the prefixes at index and index + 1 with everything else that
+// falls into their common supernet. The list is sorted and disjoint, so
+// those form a contiguous run.
+fn merge(current: &[(Network, Vec<Network>)], index: usize) -> Merge {
+    let network = common_supernet(&current[index].0, &current[index + 1].0);
+    let inside = |other: &Network| other.version() == network.version()
+        && other.first_bits() >= network.first_bits() && other.last_bits() <= network.last_bits();
+    let mut start = index;
+    while start > 0 && inside(&current[start - 1].0) {
+        start -= 1;
+    }
+    let mut end = index + 1;
+    while end + 1 < current.len() && inside(&current[end + 1].0) {
+        end += 1;
+    }
+    let covered = current[start..=end].iter().fold(0_u128, |total, (other, _)| total.saturating_add(other.total_addresses()));
+    Merge { start, end, cost: network.total_addresses().saturating_sub(covered), network }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_helpers::networks;
+
+    fn to_strings(networks: &[Network]) -> Vec<String> {
+        networks.iter().map(Network::to_string).collect()
+    }
+
+    fn routes() -> Vec<Network> {
+        networks(&["10.0.0.0/24", "10.0.1.0/24", "10.0.2.0/24", "10.0.4.0/24", "10.0.5.0/24", "10.0.6.0/24", "10.0.7.0/24", "192.168.0.0/24"])
+    }
+
+    #[test]
+    fn summarise_to_prefix_count() {
+        // Filling the 10.0.3.0/24 hole saves two prefixes for the price of
+        // one, so asking for three gets two
+        let summary = summarise(routes(), Limit::Prefixes(3)).unwrap();
+        assert_eq!(summary.exact_prefixes, 4);
+        assert_eq!(to_strings(&summary.networks()), vec!["10.0.0.0/21", "192.168.0.0/24"]);
+        let summary = summarise(routes(), Limit::Prefixes(2)).unwrap();
+        assert_eq!(to_strings(&summary.networks()), vec!["10.0.0.0/21", "192.168.0.0/24"]);
+        assert_eq!(to_strings(&summary.added()), vec!["10.0.3.0/24"]);
+        assert_eq!(to_strings(&summary.aggregates[0].members), vec!["10.0.0.0/23", "10.0.2.0/24", "10.0.4.0/22"]);
+        assert_eq!(summary.added_addresses(), 256);
+        assert!((summary.extra_percent() - 256.0 / 2048.0 * 100.0).abs() < 1e-9);
+        // Everything into one prefix costs most of the address space
+        let summary = summarise(routes(), Limit::Prefixes(1)).unwrap();
+        assert_eq!(to_strings(&summary.networks()), vec!["0.0.0.0/0"]);
+        assert_eq!(summary.added_addresses(), (1_u128 << 32) - 2048);
+    }
+
+    #[test]
+    fn summarise_to_extra_percent() {
+        let exact = summarise(routes(), Limit::ExtraPercent(0.0)).unwrap();
+        assert_eq!(to_strings(&exact.networks()), vec!["10.0.0.0/23", "10.0.2.0/24", "10.0.4.0/22", "192.168.0.0/24"]);
+        assert!(exact.added().is_empty());
+        let summary = summarise(routes(), Limit::ExtraPercent(12.0)).unwrap();
+        assert_eq!(summary.networks().len(), 4);
+        let summary = summarise(routes(), Limit::ExtraPercent(12.5)).unwrap();
+        assert_eq!(to_strings(&summary.networks()), vec!["10.0.0.0/21", "192.168.0.0/24"]);
+        assert!(matches!(summarise(routes(), Limit::ExtraPercent(-1.0)), Err(SummariseError::WrongPercent(_))));
+    }
+
+    #[test]
+    fn summarise_families_apart() {
+        let mixed = networks(&["10.0.0.0/24", "10.0.2.0/24", "2001:db8::/48", "2001:db8:2::/48"]);
+        let summary = summarise(&mixed, Limit::Prefixes(2)).unwrap();
+        assert_eq!(summary.networks(), networks(&["10.0.0.0/22", "2001:db8::/46"]));
+        assert_eq!(summary.added(), networks(&["10.0.1.0/24", "10.0.3.0/24", "2001:db8:1::/48", "2001:db8:3::/48"]));
+        assert!(matches!(summarise(&mixed, Limit::Prefixes(1)), Err(SummariseError::TooFewPrefixes(_))));
+        assert!(summarise(Vec::<Network>::new(), Limit::Prefixes(0)).unwrap().aggregates.is_empty());
+    }
+}
@@ -0,0 +1,266 @@
+use std::cmp::Reverse;
+
+use super::address::{Address, SomeAddress};
+use super::json::Json;
+use super::registry::{Registry, SpecialBlock};
+use super::{Network, Version};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ipv4Class {
+    A,
+    B,
+    C,
+    D,
+    E,
+}
+
+// Special-purpose blocks from the IANA registries (RFC 6890 and updates).
+// Networks outside all of them are global unicast.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecialPurpose {
+    ThisNetwork,
+    Private,
+    SharedAddressSpace,
+    Loopback,
+    LinkLocal,
+    ProtocolAssignments,
+    Documentation,
+    Relay6to4,
+    Benchmarking,
+    Multicast,
+    Reserved,
+    LimitedBroadcast,
+    Unspecified,
+    Ipv4Mapped,
+    Translation,
+    DiscardOnly,
+    SixToFour,
+    UniqueLocal,
+}
+
+impl Ipv4Class {
+    // Historic classful split by the leading bits of the first octet
+    pub fn of(address: &SomeAddress) -> Option<Ipv4Class> {
+        let SomeAddress::V4(address) = address else {
+            return None;
+        };
+        let class = match address.as_bytes()[0] {
+            0..=127 => Ipv4Class::A,
+            128..=191 => Ipv4Class::B,
+            192..=223 => Ipv4Class::C,
+            224..=239 => Ipv4Class::D,
+            240..=255 => Ipv4Class::E,
+        };
+        Some(class)
+    }
+}
+
+impl SpecialPurpose {
+    // Purpose of the most specific block of the embedded IANA registries
+    // holding the whole network. Blocks without a variant of their own, like
+    // AMT or AS112, fall back to the block around them. Multicast isn't in
+    // the special-purpose registries, it's part of the address architecture.
+    pub fn of(network: &Network) -> Option<SpecialPurpose> {
+        let mut blocks: Vec<&SpecialBlock> = Registry::embedded().special_blocks().iter()
+            .filter(|block| block.network.contains_network(network) == Ok(true))
+            .collect();
+        blocks.sort_by_key(|block| Reverse(block.network.prefix_len()));
+        blocks.iter().find_map(|block| SpecialPurpose::from_name(&block.name)).or_else(|| {
+            let multicast = match network.version() {
+                Version::IpV4 => Network::from_bits(Version::IpV4, 0xe000_0000, 4),
+                Version::IpV6 => Network::from_bits(Version::IpV6, 0xff00 << 112, 8),
+            };
+            (multicast.contains_network(network) == Ok(true)).then_some(SpecialPurpose::Multicast)
+        })
+    }
+
+    // Registry block names, as IANA spells them
+    fn from_name(name: &str) -> Option<SpecialPurpose> {
+        let name = name.trim_matches('"');
+        let purpose = match name {
+            "This network" | "This host on this network" => SpecialPurpose::ThisNetwork,
+            "Private-Use" => SpecialPurpose::Private,
+            "Shared Address Space" => SpecialPurpose::SharedAddressSpace,
+            "Loopback" | "Loopback Address" => SpecialPurpose::Loopback,
+            "Link Local" | "Link-Local Unicast" => SpecialPurpose::LinkLocal,
+            "IETF Protocol Assignments" => SpecialPurpose::ProtocolAssignments,
+            "Deprecated (6to4 Relay Anycast)" => SpecialPurpose::Relay6to4,
+            "Benchmarking" => SpecialPurpose::Benchmarking,
+            "Reserved" => SpecialPurpose::Reserved,
+            "Limited Broadcast" => SpecialPurpose::LimitedBroadcast,
+            "Unspecified Address" => SpecialPurpose::Unspecified,
+            "IPv4-mapped Address" => SpecialPurpose::Ipv4Mapped,
+            "IPv4-IPv6 Translat." => SpecialPurpose::Translation,
+            "Discard-Only Address Block" => SpecialPurpose::DiscardOnly,
+            "6to4" => SpecialPurpose::SixToFour,
+            "Unique-Local" => SpecialPurpose::UniqueLocal,
+            name if name.starts_with("Documentation") => SpecialPurpose::Documentation,
+            _ => return None,
+        };
+        Some(purpose)
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            SpecialPurpose::ThisNetwork => "This network",
+            SpecialPurpose::Private => "Private-use",
+            SpecialPurpose::SharedAddressSpace => "Shared address space",
+            SpecialPurpose::Loopback => "Loopback",
+            SpecialPurpose::LinkLocal => "Link-local",
+            SpecialPurpose::ProtocolAssignments => "IETF protocol assignments",
+            SpecialPurpose::Documentation => "Documentation",
+            SpecialPurpose::Relay6to4 => "6to4 relay anycast",
+            SpecialPurpose::Benchmarking => "Benchmarking",
+            SpecialPurpose::Multicast => "Multicast",
+            SpecialPurpose::Reserved => "Reserved",
+            SpecialPurpose::LimitedBroadcast => "Limited broadcast",
+            SpecialPurpose::Unspecified => "Unspecified",
+            SpecialPurpose::Ipv4Mapped => "IPv4-mapped",
+            SpecialPurpose::Translation => "IPv4-IPv6 translation",
+            SpecialPurpose::DiscardOnly => "Discard-only",
+            SpecialPurpose::SixToFour => "6to4",
+            SpecialPurpose::UniqueLocal => "Unique local",
+        }
+    }
+}
+
+// Everything ipcalc-like tools print about a network
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub network: Network,
+    pub network_address: SomeAddress,
+    pub broadcast_address: Option<SomeAddress>,
+    pub first_host: Option<SomeAddress>,
+    pub last_host: Option<SomeAddress>,
+    pub netmask: SomeAddress,
+    pub hostmask: SomeAddress,
+    pub prefix_len: u8,
+    pub total_addresses: u128,
+    pub usable_hosts: u128,
+    pub class: Option<Ipv4Class>,
+    pub special: Option<SpecialPurpose>,
+}
+
+impl Summary {
+    pub fn new(network: &Network) -> Summary {
+        Summary {
+            network: network.clone(),
+            network_address: network.network_address(),
+            broadcast_address: network.broadcast_address(),
+            first_host: network.first_host(),
+            last_host: network.last_host(),
+            netmask: network.netmask(),
+            hostmask: network.hostmask(),
+            prefix_len: network.prefix_len(),
+            total_addresses: network.total_addresses(),
+            usable_hosts: network.usable_hosts(),
+            class: Ipv4Class::of(&network.network_address()),
+            special: SpecialPurpose::of(network),
+        }
+    }
+
+    // Counts are numbers, missing values null
+    fn fields(&self) -> Vec<(&'static str, Json)> {
+        let text = |value: String| Json::String(value);
+        let address = |address: &Option<SomeAddress>| address.map_or(Json::Null, |address| text(address.to_string()));
+        vec![
+            ("network", text(self.network.to_string())),
+            ("network_address", text(self.network_address.to_string())),
+            ("broadcast_address", address(&self.broadcast_address)),
+            ("first_host", address(&self.first_host)),
+            ("last_host", address(&self.last_host)),
+            ("netmask", text(self.netmask.to_string())),
+            ("hostmask", text(self.hostmask.to_string())),
+            ("prefix_len", Json::Number(self.prefix_len.into())),
+            ("total_addresses", Json::Number(self.total_addresses)),
+            ("usable_hosts", Json::Number(self.usable_hosts)),
+            ("class", self.class.map_or(Json::Null, |class| text(format!("{:?}", class)))),
+            ("special", self.special.map_or(Json::Null, |special| text(special.description().to_string()))),
+        ]
+    }
+
+    // One "Label: value" line per field, labels right aligned
+    pub fn to_text(&self) -> String {
+        let fields = self.fields();
+        let labels: Vec<String> = fields.iter().map(|(key, _)| {
+            let label = key.replace('_', " ");
+            label[..1].to_uppercase() + &label[1..]
+        }).collect();
+        let width = labels.iter().map(|label| label.len()).max().unwrap_or(0);
+        fields.iter().zip(labels)
+            .map(|((_, value), label)| {
+                let value = match value {
+                    Json::Null => String::from("-"),
+                    Json::String(value) => value.clone(),
+                    value => value.to_text(),
+                };
+                format!("{:>width$}: {}\n", label, value, width = width)
+            })
+            .collect()
+    }
+
+    // Flat JSON object, through the shared writer so names get escaped
+    pub fn to_json(&self) -> String {
+        let members = self.fields().into_iter().map(|(key, value)| (key.to_string(), value));
+        Json::Object(members.collect()).to_text()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::address::*;
+    use super::super::test_helpers::network_v4;
+
+    #[test]
+    fn summary_text() {
+        let summary = Summary::new(&network_v4("192.168.0.0", 28));
+        assert_eq!(summary.to_text(), [
+            "          Network: 192.168.0.0/28",
+            "  Network address: 192.168.0.0",
+            "Broadcast address: 192.168.0.15",
+            "       First host: 192.168.0.1",
+            "        Last host: 192.168.0.14",
+            "          Netmask: 255.255.255.240",
+            "         Hostmask: 0.0.0.15",
+            "       Prefix len: 28",
+            "  Total addresses: 16",
+            "     Usable hosts: 14",
+            "            Class: C",
+            "          Special: Private-use",
+            "",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn summary_json() {
+        let summary = Summary::new(&network_v4("8.8.8.0", 31));
+        assert_eq!(summary.to_json(), concat!(
+            "{\"network\":\"8.8.8.0/31\",\"network_address\":\"8.8.8.0\",\"broadcast_address\":\"8.8.8.1\",",
+            "\"first_host\":null,\"last_host\":null,\"netmask\":\"255.255.255.254\",\"hostmask\":\"0.0.0.1\",",
+            "\"prefix_len\":31,\"total_addresses\":2,\"usable_hosts\":0,\"class\":\"A\",\"special\":null}"
+        ));
+        let v6 = Summary::new(&Network::from_bits(Version::IpV6, 0x20010db8 << 96, 64)).to_json();
+        assert!(v6.contains("\"usable_hosts\":18446744073709551616,"));
+        assert!(v6.ends_with("\"special\":\"Documentation\"}"));
+    }
+
+    #[test]
+    fn special_purpose_classification() {
+        assert_eq!(SpecialPurpose::of(&network_v4("100.64.1.0", 24)), Some(SpecialPurpose::SharedAddressSpace));
+        assert_eq!(SpecialPurpose::of(&network_v4("255.255.255.255", 32)), Some(SpecialPurpose::LimitedBroadcast));
+        assert_eq!(SpecialPurpose::of(&network_v4("240.0.0.0", 8)), Some(SpecialPurpose::Reserved));
+        assert_eq!(SpecialPurpose::of(&network_v4("172.0.0.0", 8)), None);
+        assert_eq!(SpecialPurpose::of(&Network::from_bits(Version::IpV6, 0xfd12 << 112, 48)), Some(SpecialPurpose::UniqueLocal));
+        assert_eq!(SpecialPurpose::of(&Network::host(&AddressV6::from_u128(1))), Some(SpecialPurpose::Loopback));
+        // AMT has no variant, the enclosing block still doesn't make it special
+        assert_eq!(SpecialPurpose::of(&network_v4("192.52.193.0", 24)), None);
+        assert_eq!(SpecialPurpose::of(&network_v4("192.0.0.8", 32)), Some(SpecialPurpose::ProtocolAssignments));
+        assert_eq!(SpecialPurpose::of(&network_v4("203.0.113.0", 25)), Some(SpecialPurpose::Documentation));
+        assert_eq!(SpecialPurpose::of(&network_v4("239.1.0.0", 16)), Some(SpecialPurpose::Multicast));
+        assert_eq!(SpecialPurpose::of(&Network::from_bits(Version::IpV6, 0xff02 << 112, 16)), Some(SpecialPurpose::Multicast));
+        assert_eq!(SpecialPurpose::of(&Network::from_bits(Version::IpV6, 0x0064_ff9b << 96, 96)), Some(SpecialPurpose::Translation));
+        assert_eq!(Ipv4Class::of(&SomeAddress::V4(AddressV4::from_string("224.0.0.1").unwrap())), Some(Ipv4Class::D));
+        assert_eq!(Ipv4Class::of(&SomeAddress::V6(AddressV6::from_u128(1))), None);
+    }
+}
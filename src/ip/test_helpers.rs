@@ -0,0 +1,15 @@
+// Shorthands shared by the unit tests of the ip modules
+use super::address::{AddressV4, FixedWidth};
+use super::{Network, Version};
+
+pub(crate) fn network(network: &str) -> Network {
+    Network::from_string(network).unwrap()
+}
+
+pub(crate) fn networks(networks: &[&str]) -> Vec<Network> {
+    networks.iter().map(|network| Network::from_string(network).unwrap()).collect()
+}
+
+pub(crate) fn network_v4(name: &str, bits: u8) -> Network {
+    Network::from_bits(Version::IpV4, AddressV4::from_string(name).unwrap().to_bits(), bits)
+}
@@ -1,15 +1,68 @@
 use num_derive::FromPrimitive;    
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Version {
     IpV4 = 4,
     IpV6 = 6,
 }
 
-#[derive(FromPrimitive)]
+impl Version {
+    pub fn address_bits(&self) -> u8 {
+        match self {
+            Version::IpV4 => 32,
+            Version::IpV6 => 128,
+        }
+    }
+
+    // The highest address of the family as an integer
+    pub fn max_bits(&self) -> u128 {
+        match self {
+            Version::IpV4 => u32::MAX.into(),
+            Version::IpV6 => u128::MAX,
+        }
+    }
+}
+
+#[derive(FromPrimitive, Clone, Copy, PartialEq, Eq)]
 #[derive(Debug)]
 pub enum TransportProtocolsNumbers {
     Tcp = 6,
     Udp = 17,
     Sctp = 132
-}
\ No newline at end of file
+}
+
+// Protocol number as carried in a header. Numbers without a variant above
+// are kept as they are, so decoding never fails or loses them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Known(TransportProtocolsNumbers),
+    Other(u8)
+}
+
+impl Protocol {
+    pub fn from_number(number: u8) -> Protocol {
+        match num_traits::FromPrimitive::from_u8(number) {
+            Some(protocol) => Protocol::Known(protocol),
+            None => Protocol::Other(number)
+        }
+    }
+
+    pub fn number(&self) -> u8 {
+        match self {
+            Protocol::Known(protocol) => *protocol as u8,
+            Protocol::Other(number) => *number
+        }
+    }
+}
+
+impl From<TransportProtocolsNumbers> for Protocol {
+    fn from(protocol: TransportProtocolsNumbers) -> Protocol {
+        Protocol::Known(protocol)
+    }
+}
+
+impl From<u8> for Protocol {
+    fn from(number: u8) -> Protocol {
+        Protocol::from_number(number)
+    }
+}